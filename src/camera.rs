@@ -1,9 +1,9 @@
-#[path = "primitive.rs"] mod primitive;
-pub use primitive::*;
+use serde::{Serialize, Deserialize};
+use super::*;
 
 
 
-#[derive(Clone)]
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub origin: Point3,
     pub p: Vec3,
@@ -20,8 +20,8 @@ impl Camera {
         let u = w.cross(p).normalize();
         let v = u.cross(w).normalize();
         Self {
-            origin: origin,
-            p: p,
+            origin,
+            p,
             u, v, w,
             focal_length
         }
@@ -46,6 +46,17 @@ impl Camera {
     pub fn move_z(&mut self, delta: f32) {
         self.origin = self.origin + self.w * delta;
     }
+    /// Direction of the ray through `uv`, as computed in the shader's `main`. The shader gets the
+    /// basis from `as_data` as a column major matrix, so the ray is (u.d, v.d, w.d).
+    pub fn ray(&self, uv: Vec2) -> Vec3 {
+        let d = Vec3::new(uv.x, uv.y, self.focal_length).normalize();
+        Vec3::new(self.u.dot(d), self.v.dot(d), self.w.dot(d))
+    }
+    /// Ray through the point (`x`, `y`) of a `width` by `height` viewport, in pixels from the top left.
+    pub fn screen_ray(&self, x: f32, y: f32, width: f32, height: f32) -> Vec3 {
+        let uv = Vec2::new(x - 0.5 * width, (height - y) - 0.5 * height) / height;
+        self.ray(uv)
+    }
     pub fn as_data(&self) -> [[f32; 3]; 3] {
         [
             [self.u.x, self.v.x, self.w.x],
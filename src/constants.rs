@@ -1,6 +1,8 @@
-pub const MAX_OBJECTS: usize = 32;
-pub const MAX_BOOL_OPS: usize = 32;
-pub const MAX_LIGHTS: usize = 8;
+pub(crate) const MAX_CSG_STACK: usize = 16;
+// GL_MAX_3D_TEXTURE_SIZE that OpenGL 4 guarantees, which the voxel grids are stacked into
+pub const MAX_VOXEL_TEXTURE_SIZE: u32 = 2048;
 
 pub const FONT_PATH: &[u8] = include_bytes!("../resources/Monaco.ttf");
-pub const FONT_NAME: &str = "Monaco";
\ No newline at end of file
+pub const FONT_NAME: &str = "Monaco";
+
+pub const SCENE_FORMAT_VERSION: u32 = 1;
@@ -0,0 +1,72 @@
+//! Signed distance field scenes, raymarched on the GPU with GLSL or on the CPU.
+//!
+//! A `Scene` holds `Primitive`s placed by `Transform`s, grouped in a hierarchy and combined
+//! with `BooleanOp`s, seen through a `Camera`. `Scene::render` draws it on the CPU,
+//! `GpuRenderer` draws it into a glium surface, and `Scene::load` and `Scene::save` read and
+//! write RON scene files. Scenes can also be meshed (`Scene::mesh`), sampled as point clouds
+//! (`Scene::sample_points`) and baked into voxel grids (`Scene::bake`).
+//!
+//! ```
+//! use gpu_raymarching::*;
+//!
+//! let mut scene = Scene::new();
+//! scene.set_camera(Camera::new(point3(0., 1., 0.), point3(0., 1., 1.), 0.0, 1.0));
+//! let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+//! let sphere = scene.add(Primitive::Sphere(0.75), Transform::translation(vec3(-0.5, 1., 6.)), white);
+//! let cuboid = scene.add(Primitive::Cuboid(vec3(0.5, 0.5, 0.5), 0.05), Transform::translation(vec3(0.5, 1., 6.)), white);
+//! scene.add_bool_op(BooleanOp::new(BooleanOpType::SmoothUnion(0.3), vec![sphere.into(), cuboid.into()]))?;
+//! scene.add_light(Light::point(point3(6., 5., -6.)));
+//!
+//! let image = scene.render(32, 18);
+//! assert_eq!(image.pixels.len(), 32 * 18);
+//! # Ok::<(), SceneError>(())
+//! ```
+#[macro_use]
+extern crate glium;
+
+mod constants;
+mod utils;
+mod quat;
+mod vec3;
+mod material;
+mod modifier;
+mod shape2d;
+mod text;
+mod terrain;
+mod voxel;
+mod primitive;
+mod camera;
+mod light;
+mod sdf;
+mod render;
+mod scene_graph;
+mod mesh;
+mod point_cloud;
+mod scene_file;
+mod scene;
+mod program;
+
+pub use constants::{FONT_PATH, FONT_NAME, SCENE_FORMAT_VERSION, MAX_VOXEL_TEXTURE_SIZE};
+pub use utils::{PI, TAU, Axis, to_radians, clamp};
+pub use quat::Quat;
+pub use vec3::{Vec2, Vec3, ONB, Rgb, Point3, vec2, vec3, point3, rgb};
+pub use material::{Material, MaterialId};
+pub use modifier::Modifier;
+pub use shape2d::Shape2D;
+pub use text::{Text, FontError};
+pub use terrain::{HeightSource, Heightmap};
+pub use voxel::VoxelGrid;
+pub use primitive::{Primitive, BooleanOp, BooleanOpType, CsgNode, ObjectId, Transform};
+pub use camera::Camera;
+pub use light::Light;
+pub use sdf::SdfResult;
+pub use render::{Image, Pick, Renderer};
+pub use scene_graph::{Group, GroupId, SceneNode};
+pub use mesh::{Mesh, MeshMethod, MeshFormat};
+pub use point_cloud::{PointCloud, PointSampling, SampleKind};
+pub use scene_file::{SceneFile, SceneFileObject, SceneFileGroup, SceneError};
+pub use scene::{Scene, SceneParams, CsgProgram, SceneFogColorBlock, SceneParamsBlock, SceneConstsBlock};
+pub use program::{GpuRenderer, DrawError, ShaderError, ShaderWatcher, annotate_shader_log};
+
+// shared between the modules, but not part of the API
+use constants::MAX_CSG_STACK;
@@ -0,0 +1,105 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// A light source. `range` is the distance at which a light has faded out completely,
+/// or `0.0` for no falloff. Spot cone angles are half angles in radians, and an area
+/// light's `size` is its radius, which sets the width of its shadow penumbra.
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Light {
+    Point { position: Point3, color: Rgb, intensity: f32, range: f32 },
+    Directional { direction: Vec3, color: Rgb, intensity: f32 },
+    Spot { position: Point3, direction: Vec3, color: Rgb, intensity: f32, range: f32, inner_angle: f32, outer_angle: f32 },
+    Area { position: Point3, size: f32, color: Rgb, intensity: f32, range: f32 },
+}
+
+const DEFAULT_LIGHT_COLOR: Rgb = Rgb { x: 0.9, y: 0.9, z: 0.8 };
+
+impl Light {
+    pub fn point(position: Point3) -> Self {
+        Self::Point { position, color: DEFAULT_LIGHT_COLOR, intensity: 1.0, range: 0.0 }
+    }
+    pub fn directional(direction: Vec3) -> Self {
+        Self::Directional { direction: direction.normalize(), color: DEFAULT_LIGHT_COLOR, intensity: 1.0 }
+    }
+    pub fn spot(position: Point3, direction: Vec3, angle: f32) -> Self {
+        Self::Spot {
+            position,
+            direction: direction.normalize(),
+            color: DEFAULT_LIGHT_COLOR,
+            intensity: 1.0,
+            range: 0.0,
+            inner_angle: angle * 0.8,
+            outer_angle: angle,
+        }
+    }
+    pub fn area(position: Point3, size: f32) -> Self {
+        Self::Area { position, size, color: DEFAULT_LIGHT_COLOR, intensity: 1.0, range: 0.0 }
+    }
+    pub fn with_color(mut self, color: Rgb, intensity: f32) -> Self {
+        *self.color_mut() = color;
+        *self.intensity_mut() = intensity;
+        self
+    }
+    pub fn with_range(mut self, range: f32) -> Self {
+        match &mut self {
+            Self::Point { range: r, .. } | Self::Spot { range: r, .. } | Self::Area { range: r, .. } => *r = range,
+            Self::Directional { .. } => {}
+        }
+        self
+    }
+    pub fn color_mut(&mut self) -> &mut Rgb {
+        match self {
+            Self::Point { color, .. } | Self::Directional { color, .. } | Self::Spot { color, .. } | Self::Area { color, .. } => color,
+        }
+    }
+    pub fn intensity_mut(&mut self) -> &mut f32 {
+        match self {
+            Self::Point { intensity, .. } | Self::Directional { intensity, .. } | Self::Spot { intensity, .. } | Self::Area { intensity, .. } => intensity,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Point { .. } => "Point",
+            Self::Directional { .. } => "Directional",
+            Self::Spot { .. } => "Spot",
+            Self::Area { .. } => "Area",
+        }
+    }
+    /// Whether a spot light's cone angles are from 0 to pi with the inner one no wider than
+    /// the outer one. Other lights are always valid.
+    pub fn is_valid(&self) -> bool {
+        match *self {
+            Self::Spot { inner_angle, outer_angle, .. } =>
+                (0.0..=std::f32::consts::PI).contains(&outer_angle) && (0.0..=outer_angle).contains(&inner_angle),
+            _ => true,
+        }
+    }
+    /// Packed as four texels of the `scene_lights` buffer texture:
+    /// (position, type), (direction, range), (colour * intensity, size), (cos inner, cos outer, 0, 0).
+    /// Directions are normalized here, since they can be edited or loaded at any length, and a
+    /// zero direction points straight down. Spot angles are clamped to 0 to pi, with the
+    /// inner one no wider than the outer one, and equal angles give a hard-edged cone.
+    pub fn get_data(&self) -> [[f32; 4]; 4] {
+        let zero = vec3(0., 0., 0.);
+        let (kind, position, direction, color, intensity, range, size, cones) = match *self {
+            Self::Point { position, color, intensity, range } => (0.0, position, zero, color, intensity, range, 0.0, (-1.0, -1.0)),
+            Self::Directional { direction, color, intensity } => (1.0, zero, direction, color, intensity, 0.0, 0.0, (-1.0, -1.0)),
+            Self::Spot { position, direction, color, intensity, range, inner_angle, outer_angle } => {
+                let outer_angle = outer_angle.clamp(0.0, std::f32::consts::PI);
+                let inner_angle = inner_angle.clamp(0.0, outer_angle);
+                (2.0, position, direction, color, intensity, range, 0.0, (inner_angle.cos(), outer_angle.cos()))
+            },
+            Self::Area { position, size, color, intensity, range } => (3.0, position, zero, color, intensity, range, size, (-1.0, -1.0)),
+        };
+        let direction = if direction.length() > 0.0 { direction.normalize() } else { vec3(0., -1., 0.) };
+        let c = color * intensity;
+        [
+            [position.x, position.y, position.z, kind],
+            [direction.x, direction.y, direction.z, range],
+            [c.x, c.y, c.z, size],
+            [cones.0, cones.1, 0.0, 0.0],
+        ]
+    }
+}
@@ -1,15 +1,12 @@
-#[macro_use]
-extern crate glium;
 use glium::glutin;
 use glium::Surface;
 use egui_glium::egui_winit::egui;
+use gpu_raymarching::*;
+use std::path::PathBuf;
 
 
-use program::*;
-use scene::*;
-#[path = "program.rs"] mod program;
-#[path = "scene.rs"] mod scene;
-
+// the shaders `view --dev` watches, in the source tree this was built from
+const SOURCE_SHADER_DIR: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/src/shaders");
 
 fn basic_scene() -> Scene {
     let mut scene = Scene::new();
@@ -19,15 +16,20 @@ fn basic_scene() -> Scene {
         0.0, 1.0
     ));
 
-    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0., rgb(1.0, 1.0, 1.0)), Transform::none());
-    let sphere = scene.add(Primitive::Sphere(0.75, rgb(0.2, 1.0, 0.2)), Transform::translation(vec3(0., 1., 6.)));
-    let cuboid = scene.add(Primitive::Cuboid(vec3(0.5, 0.5, 0.65), 0.05, rgb(1.0, 0.2, 0.2)), Transform::translation(vec3(1., 1., 6.)));
-    scene.add_bool_op(BooleanOp::new(BooleanOpType::SmoothUnion(8.0), vec![sphere, cuboid]));
-    scene.add_light(point3(6., 5., -6.));
-    return scene
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    let green = scene.add_material(Material::new(rgb(0.2, 1.0, 0.2)));
+    let red = scene.add_material(Material::new(rgb(1.0, 0.2, 0.2)));
+
+    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0.), Transform::none(), white);
+    let blob = scene.add_group("Blob", Transform::translation(vec3(0.5, 1., 6.)));
+    let sphere = scene.add_child(blob, Primitive::Sphere(0.75), Transform::translation(vec3(-0.5, 0., 0.)), green);
+    let cuboid = scene.add_child(blob, Primitive::Cuboid(vec3(0.5, 0.5, 0.65), 0.05), Transform::translation(vec3(0.5, 0., 0.)), red);
+    scene.add_bool_op(BooleanOp::new(BooleanOpType::SmoothUnion(0.3), vec![sphere.into(), cuboid.into()])).unwrap();
+    scene.add_light(Light::point(point3(6., 5., -6.)));
+    scene
 }
 
-fn input(scene: &mut Scene, held_keys: &[bool; 255], prev_keys: &[bool; 255], selected_obj: &mut usize) {
+fn input(scene: &mut Scene, held_keys: &[bool; 255], prev_keys: &[bool; 255], selected_obj: &mut Option<ObjectId>) {
     let movement_speed = 0.1;
     let rotate_speed = 0.08;
 
@@ -53,23 +55,435 @@ fn input(scene: &mut Scene, held_keys: &[bool; 255], prev_keys: &[bool; 255], se
     // if held_keys[glutin::event::VirtualKeyCode::Down as usize] {
     //     scene.camera.rotate_y(-rotate_speed); }
 
-    if held_keys[glutin::event::VirtualKeyCode::Tab as usize] && !prev_keys[glutin::event::VirtualKeyCode::Tab as usize] {
+    if held_keys[glutin::event::VirtualKeyCode::Tab as usize] && !prev_keys[glutin::event::VirtualKeyCode::Tab as usize] && !scene.ids().is_empty() {
+        let n = scene.ids().len();
+        let i = selected_obj.and_then(|id| scene.index_of(id)).unwrap_or(0);
         if held_keys[glutin::event::VirtualKeyCode::LShift as usize] {
-            *selected_obj = (*selected_obj - 1) % scene.objects.len();
+            *selected_obj = Some(scene.ids()[(i + n - 1) % n]);
         } else {
-            *selected_obj = (*selected_obj + 1) % scene.objects.len();
+            *selected_obj = Some(scene.ids()[(i + 1) % n]);
+        }
+    }
+}
+
+
+/// Position, rotation and scale fields for a transform. The rotation is only written back
+/// when an angle was edited, so the Euler round trip doesn't disturb the quaternion.
+/// A `uniform_scale` is edited as one value, as groups need.
+fn transform_ui(ui: &mut egui::Ui, transform: &mut Transform, uniform_scale: bool) {
+    ui.label("Position");
+    vec3_ui(ui, &mut transform.translate, 0.05);
+
+    let rot = transform.euler();
+    let mut yaw = rot.x;
+    let mut pitch = rot.y;
+    let mut roll = rot.z;
+    ui.label("Rotation").on_hover_text("Yaw about Z, then pitch about Y, then roll about X, in radians");
+    ui.add(egui::DragValue::new(&mut yaw).speed(0.05).prefix("Yaw: "));
+    ui.add(egui::DragValue::new(&mut pitch).speed(0.05).prefix("Pitch: "));
+    ui.add(egui::DragValue::new(&mut roll).speed(0.05).prefix("Roll: "));
+    if (rot.x != yaw) || (rot.y != pitch) || (rot.z != roll) {
+        transform.set_euler(vec3(yaw, pitch, roll));
+    }
+
+    ui.label("Scale");
+    if uniform_scale {
+        let mut scale = transform.scale.x;
+        ui.add(egui::DragValue::new(&mut scale).speed(0.02).clamp_range(0.01..=100.0));
+        transform.scale = vec3(scale, scale, scale);
+        return;
+    }
+    ui.add(egui::DragValue::new(&mut transform.scale.x).speed(0.02).clamp_range(0.01..=100.0).prefix("X: "));
+    ui.add(egui::DragValue::new(&mut transform.scale.y).speed(0.02).clamp_range(0.01..=100.0).prefix("Y: "));
+    ui.add(egui::DragValue::new(&mut transform.scale.z).speed(0.02).clamp_range(0.01..=100.0).prefix("Z: "));
+}
+
+fn vec3_ui(ui: &mut egui::Ui, v: &mut Vec3, speed: f32) {
+    ui.add(egui::DragValue::new(&mut v.x).speed(speed).prefix("X: "));
+    ui.add(egui::DragValue::new(&mut v.y).speed(speed).prefix("Y: "));
+    ui.add(egui::DragValue::new(&mut v.z).speed(speed).prefix("Z: "));
+}
+
+/// The modifier stack, outermost first, with a parameter row per modifier.
+fn modifiers_ui(ui: &mut egui::Ui, modifiers: &mut Vec<Modifier>) {
+    ui.label("Modifiers");
+    let mut remove = None;
+    for (i, modifier) in modifiers.iter_mut().enumerate() {
+        ui.horizontal(|ui| {
+            if ui.small_button("x").on_hover_text("Remove").clicked() {
+                remove = Some(i);
+            }
+            ui.label(modifier.as_str());
+            match modifier {
+                Modifier::Repeat(spacing) => vec3_ui(ui, spacing, 0.05),
+                Modifier::RepeatLimited(spacing, copies) => {
+                    vec3_ui(ui, spacing, 0.05);
+                    ui.label("Copies");
+                    vec3_ui(ui, copies, 0.1);
+                },
+                Modifier::Mirror(normal, k) => {
+                    vec3_ui(ui, normal, 0.02);
+                    ui.add(egui::DragValue::new(k).speed(0.05).prefix("Offset: "));
+                },
+                Modifier::Twist(axis, rate) | Modifier::Bend(axis, rate) => {
+                    egui::ComboBox::from_id_source(("modifier_axis", i))
+                        .selected_text(format!("{:?}", axis))
+                        .show_ui(ui, |ui| {
+                            for a in [Axis::X, Axis::Y, Axis::Z] {
+                                ui.selectable_value(axis, a, format!("{:?}", a));
+                            }
+                        });
+                    ui.add(egui::DragValue::new(rate).speed(0.02).prefix("Rate: "));
+                },
+                Modifier::Elongate(half) => vec3_ui(ui, half, 0.02),
+                Modifier::Onion(thickness) => { ui.add(egui::DragValue::new(thickness).speed(0.005).clamp_range(0.0..=10.0).prefix("Thickness: ")); },
+                Modifier::Round(radius) => { ui.add(egui::DragValue::new(radius).speed(0.005).clamp_range(0.0..=10.0).prefix("Radius: ")); },
+            }
+        });
+    }
+    if let Some(i) = remove {
+        modifiers.remove(i);
+    }
+    egui::ComboBox::from_id_source("add_modifier")
+        .selected_text("Add Modifier")
+        .show_ui(ui, |ui| {
+            for modifier in Modifier::defaults() {
+                if ui.selectable_label(false, modifier.as_str()).clicked() {
+                    modifiers.push(modifier);
+                }
+            }
+        });
+}
+
+/// Operator and parameters of a boolean op, followed by its nested ops. `id` keeps the
+/// widgets of different ops apart.
+fn bool_op_ui(ui: &mut egui::Ui, op: &mut BooleanOp, id: &str) {
+    ui.horizontal(|ui| {
+        egui::ComboBox::from_id_source(("bool_op", id))
+            .selected_text(op.operator.as_str())
+            .show_ui(ui, |ui| {
+                for operator in BooleanOpType::defaults() {
+                    let selected = std::mem::discriminant(&op.operator) == std::mem::discriminant(&operator);
+                    if ui.selectable_label(selected, operator.as_str()).clicked() && !selected {
+                        op.operator = operator;
+                    }
+                }
+            });
+        match &mut op.operator {
+            BooleanOpType::SmoothUnion(k) | BooleanOpType::SmoothIntersect(k) | BooleanOpType::SmoothSubtract(k) => {
+                ui.add(egui::DragValue::new(k).speed(0.01).clamp_range(0.0..=10.0).prefix("k: "));
+            },
+            BooleanOpType::Chamfer(r) => {
+                ui.add(egui::DragValue::new(r).speed(0.01).clamp_range(0.0..=10.0).prefix("r: "));
+            },
+            BooleanOpType::Stairs(r, n) | BooleanOpType::Columns(r, n) => {
+                ui.add(egui::DragValue::new(r).speed(0.01).clamp_range(0.0..=10.0).prefix("r: "));
+                ui.add(egui::DragValue::new(n).speed(0.05).clamp_range(1.0..=16.0).max_decimals(0).prefix("n: "));
+            },
+            BooleanOpType::Morph(t) => {
+                ui.add(egui::Slider::new(t, 0.0..=1.0).text("t"));
+            },
+            BooleanOpType::Union | BooleanOpType::Intersect | BooleanOpType::Subtract | BooleanOpType::Xor => {},
+        }
+        ui.label(format!("{} children", op.children.len()));
+    });
+    ui.indent(("bool_op_children", id), |ui| {
+        for (i, child) in op.children.iter_mut().enumerate() {
+            if let CsgNode::Op(child) = child {
+                bool_op_ui(ui, child, &format!("{}.{}", id, i));
+            }
+        }
+    });
+}
+
+/// Grid cells along the longest side of the bounds a mesh export may ask for.
+const MESH_RESOLUTIONS: std::ops::RangeInclusive<u32> = 4..=512;
+
+/// Settings for exporting the scene as a mesh, shared by the File menu and `export-mesh`.
+#[derive(Clone)]
+struct MeshExport {
+    min: Vec3,
+    max: Vec3,
+    resolution: u32,
+    method: MeshMethod,
+    format: MeshFormat,
+}
+
+impl Default for MeshExport {
+    fn default() -> Self {
+        Self {
+            min: vec3(-5., -5., -5.),
+            max: vec3(5., 5., 5.),
+            resolution: 128,
+            method: MeshMethod::MarchingCubes,
+            format: MeshFormat::Obj,
+        }
+    }
+}
+
+impl MeshExport {
+    /// Meshes the scene and writes it to `path`, in the format its extension names. Returns the triangle count.
+    fn export(&self, scene: &Scene, path: &std::path::Path) -> std::io::Result<usize> {
+        let mesh = scene.mesh(self.min, self.max, self.resolution, self.method);
+        mesh.save(path)?;
+        Ok(mesh.triangles.len())
+    }
+}
+
+fn mesh_export_ui(ui: &mut egui::Ui, export: &mut MeshExport) {
+    egui::ComboBox::from_label("Method")
+        .selected_text(export.method.as_str())
+        .show_ui(ui, |ui| {
+            for method in [MeshMethod::MarchingCubes, MeshMethod::DualContouring] {
+                ui.selectable_value(&mut export.method, method, method.as_str());
+            }
+        });
+    egui::ComboBox::from_label("Format")
+        .selected_text(export.format.extension())
+        .show_ui(ui, |ui| {
+            for format in MeshFormat::all() {
+                ui.selectable_value(&mut export.format, format, format.extension());
+            }
+        });
+    ui.add(egui::DragValue::new(&mut export.resolution).speed(1).clamp_range(MESH_RESOLUTIONS).prefix("Resolution: "))
+        .on_hover_text("Grid cells along the longest side of the bounds");
+    ui.label("Bounds Min");
+    vec3_ui(ui, &mut export.min, 0.1);
+    ui.label("Bounds Max");
+    vec3_ui(ui, &mut export.max, 0.1);
+}
+
+const USAGE: &str = "usage: gpu-raymarching [command]
+
+commands:
+    view [scene.ron] [--dev] [--shaders dir]
+                                open the interactive viewer, on the example scene without a file;
+                                --dev reloads src/shaders/*.glsl whenever they change, and
+                                --shaders does the same for another directory
+    render <scene.ron> <image.png> [--width w] [--height h] [--camera-origin x,y,z]
+        [--camera-target x,y,z] [--camera-roll radians] [--focal-length f]
+                                render the scene on the CPU, without opening a window
+    export-mesh <scene.ron> <mesh.obj|stl|ply|gltf|glb> [--resolution n]
+        [--method marching-cubes|dual-contouring] [--min x,y,z] [--max x,y,z]
+                                mesh the scene's surface within the bounds
+    validate <scene.ron>...     check scene files and report what's wrong with them
+    help                        show this message";
+
+fn parse_vec3(value: &str) -> Result<Vec3, String> {
+    let parts: Vec<f32> = value.split(',').map(|x| x.trim().parse::<f32>()).collect::<Result<_, _>>().map_err(|e| format!("bad vector {}: {}", value, e))?;
+    match parts[..] {
+        [x, y, z] => Ok(vec3(x, y, z)),
+        _ => Err(format!("bad vector {}, expected x,y,z", value)),
+    }
+}
+
+/// Pixels along either side of an image `render` may ask for.
+const IMAGE_SIZES: std::ops::RangeInclusive<u32> = 1..=16384;
+
+/// `render <scene> <image> [--width w] [--height h] [--camera-origin x,y,z] [--camera-target x,y,z] [--camera-roll r] [--focal-length f]`
+fn render_command(args: &[String]) -> Result<(), String> {
+    let usage = "usage: render <scene.ron> <image.png> [--width w] [--height h] [--camera-origin x,y,z] \
+        [--camera-target x,y,z] [--camera-roll radians] [--focal-length f]";
+    let (scene_path, image_path) = match args {
+        [scene, image, ..] => (scene, image),
+        _ => return Err(usage.into()),
+    };
+    let (mut width, mut height) = (1280u32, 720u32);
+    let (mut origin, mut target, mut roll, mut focal_length) = (None, None, None, None);
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or(format!("{} needs a value\n{}", option, usage))?;
+        let number = |value: &str| value.parse::<f32>().map_err(|e| format!("bad {} {}: {}", option, value, e));
+        match option.as_str() {
+            "--width" => width = value.parse().map_err(|e| format!("bad width {}: {}", value, e))?,
+            "--height" => height = value.parse().map_err(|e| format!("bad height {}: {}", value, e))?,
+            "--camera-origin" => origin = Some(parse_vec3(value)?),
+            "--camera-target" => target = Some(parse_vec3(value)?),
+            "--camera-roll" => roll = Some(number(value)?),
+            "--focal-length" => focal_length = Some(number(value)?),
+            _ => return Err(format!("unknown option {}\n{}", option, usage)),
+        }
+    }
+    if !IMAGE_SIZES.contains(&width) || !IMAGE_SIZES.contains(&height) {
+        return Err(format!("can't render a {}x{} image, width and height must be from {} to {}",
+            width, height, IMAGE_SIZES.start(), IMAGE_SIZES.end()));
+    }
+    let mut scene = Scene::load(scene_path).map_err(|e| format!("failed to open {}: {}", scene_path, e))?;
+    let camera = &scene.camera;
+    let origin = origin.unwrap_or(camera.origin);
+    let focal_length = focal_length.unwrap_or(camera.focal_length);
+    if target.is_some() || roll.is_some() {
+        // keep looking the same way unless given a target
+        let target = target.unwrap_or(origin + camera.w);
+        if (target - origin).length() == 0.0 {
+            return Err("the camera target can't be the camera origin".into());
+        }
+        scene.set_camera(Camera::new(origin, target, roll.unwrap_or(0.0), focal_length));
+    } else {
+        scene.camera.origin = origin;
+        scene.camera.focal_length = focal_length;
+    }
+    scene.render(width, height).save_png(image_path).map_err(|e| format!("failed to write {}: {}", image_path, e))?;
+    println!("rendered {}x{} to {}", width, height, image_path);
+    Ok(())
+}
+
+/// `validate <scene>...`, failing if any of the files doesn't load.
+fn validate_command(args: &[String]) -> Result<(), String> {
+    if args.is_empty() {
+        return Err("usage: validate <scene.ron>...".into());
+    }
+    let mut failed = 0;
+    for path in args {
+        match Scene::load(path) {
+            Ok(scene) => println!("{}: ok, {} objects, {} groups, {} materials, {} lights, {} boolean ops", path,
+                scene.ids().len(), scene.group_ids().len(), scene.materials.len(), scene.lights.len(), scene.bool_ops().len()),
+            Err(e) => {
+                eprintln!("{}: {}", path, e);
+                failed += 1;
+            },
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} scene files are invalid", failed, args.len()));
+    }
+    Ok(())
+}
+
+/// `export-mesh <scene> <mesh> [--resolution n] [--method marching-cubes|dual-contouring] [--min x,y,z] [--max x,y,z]`
+fn export_mesh_command(args: &[String]) -> Result<(), String> {
+    let usage = "usage: export-mesh <scene.ron> <mesh.obj|stl|ply|gltf|glb> [--resolution n] \
+        [--method marching-cubes|dual-contouring] [--min x,y,z] [--max x,y,z]";
+    let (scene_path, mesh_path) = match args {
+        [scene, mesh, ..] => (scene, std::path::Path::new(mesh)),
+        _ => return Err(usage.into()),
+    };
+    let mut export = MeshExport::default();
+    let mut options = args[2..].iter();
+    while let Some(option) = options.next() {
+        let value = options.next().ok_or(format!("{} needs a value\n{}", option, usage))?;
+        match option.as_str() {
+            "--resolution" => export.resolution = value.parse().map_err(|e| format!("bad resolution {}: {}", value, e))?,
+            "--method" => export.method = match value.as_str() {
+                "marching-cubes" => MeshMethod::MarchingCubes,
+                "dual-contouring" => MeshMethod::DualContouring,
+                _ => return Err(format!("unknown method {}\n{}", value, usage)),
+            },
+            "--min" => export.min = parse_vec3(value)?,
+            "--max" => export.max = parse_vec3(value)?,
+            _ => return Err(format!("unknown option {}\n{}", option, usage)),
         }
     }
+    if !MESH_RESOLUTIONS.contains(&export.resolution) {
+        return Err(format!("resolution must be from {} to {}", MESH_RESOLUTIONS.start(), MESH_RESOLUTIONS.end()));
+    }
+    let scene = Scene::load(scene_path).map_err(|e| format!("failed to open {}: {}", scene_path, e))?;
+    let triangles = export.export(&scene, mesh_path).map_err(|e| format!("failed to write {}: {}", mesh_path.display(), e))?;
+    println!("wrote {} triangles to {}", triangles, mesh_path.display());
+    Ok(())
 }
 
+/// Drag and drop state of the outliner, kept between frames.
+#[derive(Default)]
+struct Outliner {
+    dragged: Option<SceneNode>,
+    /// Where the dragged node would be dropped this frame, `Some(None)` for the top level.
+    target: Option<Option<GroupId>>,
+}
+
+/// One row per child of `parent`, with each group's children indented below it.
+/// Dropping onto a group moves the dragged node into it, dropping onto an object makes
+/// it a sibling of that object.
+fn outliner_rows(ui: &mut egui::Ui, scene: &Scene, parent: Option<GroupId>, selected_object: &mut Option<ObjectId>, selected_group: &mut Option<GroupId>, outliner: &mut Outliner) {
+    for node in scene.children(parent) {
+        let (text, selected, target) = match node {
+            SceneNode::Object(id) => (format!("{} {}", scene.get(id).unwrap().as_str(), id.0), *selected_object == Some(id), parent),
+            SceneNode::Group(id) => (scene.group(id).unwrap().name.clone(), *selected_group == Some(id), Some(id)),
+        };
+        let response = ui.add(egui::SelectableLabel::new(selected, text)).interact(egui::Sense::drag());
+        if response.clicked() {
+            match node {
+                SceneNode::Object(id) => { *selected_object = Some(id); *selected_group = None; },
+                SceneNode::Group(id) => { *selected_group = Some(id); *selected_object = None; },
+            }
+        }
+        if response.drag_started() {
+            outliner.dragged = Some(node);
+        }
+        if outliner.dragged.is_some() && ui.rect_contains_pointer(response.rect) {
+            outliner.target = Some(target);
+        }
+        if let SceneNode::Group(id) = node {
+            ui.indent(("outliner", id.0), |ui| outliner_rows(ui, scene, Some(id), selected_object, selected_group, outliner));
+        }
+    }
+}
 
 
 fn main() {
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let result = match args.first().map(|command| command.as_str()) {
+        None => view(basic_scene(), String::from("scene.ron"), None),
+        Some("view") => view_command(&args[1..]),
+        Some("render") => render_command(&args[1..]),
+        Some("export-mesh") => export_mesh_command(&args[1..]),
+        Some("validate") => validate_command(&args[1..]),
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        },
+        Some(command) => Err(format!("unknown command {}\n{}", command, USAGE)),
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    }
+}
+
+/// `view [scene] [--dev] [--shaders dir]`
+fn view_command(args: &[String]) -> Result<(), String> {
+    let usage = "usage: view [scene.ron] [--dev] [--shaders dir]";
+    let (mut scene_path, mut shader_dir) = (None, None);
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--dev" => shader_dir = shader_dir.or(Some(PathBuf::from(SOURCE_SHADER_DIR))),
+            "--shaders" => shader_dir = Some(PathBuf::from(args.next().ok_or(usage)?)),
+            path if scene_path.is_none() && !path.starts_with("--") => scene_path = Some(path),
+            _ => return Err(usage.into()),
+        }
+    }
+    match scene_path {
+        None => view(basic_scene(), String::from("scene.ron"), shader_dir),
+        Some(path) => {
+            let scene = Scene::load(path).map_err(|e| format!("failed to open {}: {}", path, e))?;
+            view(scene, path.into(), shader_dir)
+        },
+    }
+}
+
+/// Opens the interactive viewer on `scene`, with `scene_path` as the File menu's path. With a
+/// `shader_dir`, the shaders are read from it, and read again when they change. Never returns.
+fn view(mut scene: Scene, mut scene_path: String, shader_dir: Option<PathBuf>) -> Result<(), String> {
     let event_loop = glutin::event_loop::EventLoop::new();
     let wb = glutin::window::WindowBuilder::new();
     let cb = glutin::ContextBuilder::new();
     let display = glium::Display::new(wb, cb, &event_loop).unwrap();
-    let (vertex_buffer, indices, program) = load_program(&display);
+    let mut shader_watcher = shader_dir.map(ShaderWatcher::new);
+    let mut shader_error: Option<String> = None;
+    // the watched shaders are the ones being worked on, so the built in ones are only a fallback
+    let watched = shader_watcher.as_mut().map(|watcher| {
+        watcher.changed();
+        watcher.read().and_then(|(vertex, fragment)| GpuRenderer::with_shaders(&display, &vertex, &fragment))
+    });
+    let mut gpu = match watched {
+        Some(Ok(gpu)) => gpu,
+        Some(Err(e)) => {
+            shader_error = Some(e.to_string());
+            GpuRenderer::new(&display).map_err(|e| e.to_string())?
+        },
+        None => GpuRenderer::new(&display).map_err(|e| e.to_string())?,
+    };
     let mut gui = egui_glium::EguiGlium::new(&display, &event_loop);
     let mut fonts = egui::FontDefinitions::default();
     fonts.font_data.insert(FONT_NAME.into(), egui::FontData::from_static(FONT_PATH));
@@ -77,77 +491,229 @@ fn main() {
     fonts.families.get_mut(&egui::FontFamily::Proportional).unwrap().insert(0, FONT_NAME.into());
     gui.egui_ctx.set_fonts(fonts);
 
-    let mut scene = basic_scene();
-    let mut selected_object = 0;
+    let mut selected_object = scene.ids().first().copied();
+    let mut selected_group: Option<GroupId> = None;
+    let mut outliner = Outliner::default();
     let mut time = 0f32;
     let mut held_keys = [false; 255];
     let mut prev_keys = [false; 255];
     let mut mouse = [0f32; 4];
-    let mut new_object_choice = Primitive::Sphere(4., rgb(1., 1., 1.));
+    let mut new_object_choice = Primitive::Sphere(4.);
+    let mut scene_status = String::new();
+    // statuses of work done off the UI thread, shown when it finishes
+    let (status_sender, statuses) = std::sync::mpsc::channel::<String>();
+    let mut mesh_export = MeshExport::default();
 
-    let mut scene_fog_color = SceneFogColorBlock {
-        fog_color: [0.30, 0.36, 0.60, 1.0],
-    };
-    let mut scene_params = SceneParamsBlock {
-        params: [4.0, 1.0, 1.0, 0.0],
-    };
-    let mut scene_consts = SceneConstsBlock {
-        consts: [scene.objects.len() as f32 + 1.0, 0.0, 0.0, 0.0],
-    };
     event_loop.run(move |ev, _, control_flow| {
+        if shader_watcher.as_mut().is_some_and(|watcher| watcher.changed()) {
+            // a failed reload keeps drawing with the last shaders that compiled
+            match shader_watcher.as_ref().unwrap().read().and_then(|(vertex, fragment)| gpu.reload(&display, &vertex, &fragment)) {
+                Ok(()) => {
+                    shader_error = None;
+                    scene_status = String::from("Reloaded shaders");
+                },
+                Err(e) => shader_error = Some(e.to_string()),
+            }
+        }
+        if let Some(status) = statuses.try_iter().last() {
+            scene_status = status;
+        }
         let repaint_after = gui.run(&display, |egui_ctx| {
-            let pos = scene.obj_transforms[selected_object].translate;
-            let mut x = pos.x;
-            let mut y = pos.y;
-            let mut z = pos.z;
-
-            let rot = scene.obj_transforms[selected_object].rotate;
-            let mut yaw = rot.x;
-            let mut pitch = rot.y;
-            let mut roll = rot.z;
-            egui::Window::new(&scene.objects[selected_object].as_str())
-                .collapsible(true)
-                .current_pos((20., 20.)).show(egui_ctx, |ui| {
-                ui.label("Position");
-                ui.add(egui::DragValue::new(&mut x).speed(0.05).prefix("X: "));
-                ui.add(egui::DragValue::new(&mut y).speed(0.05).prefix("Y: "));
-                ui.add(egui::DragValue::new(&mut z).speed(0.05).prefix("Z: "));
-
-                ui.label("Rotation");
-                ui.add(egui::DragValue::new(&mut yaw).speed(0.05).prefix("Yaw: "));
-                ui.add(egui::DragValue::new(&mut pitch).speed(0.05).prefix("Pitch: "));
-                ui.add(egui::DragValue::new(&mut roll).speed(0.05).prefix("Roll: "));
+            egui::TopBottomPanel::top("menu").show(egui_ctx, |ui| {
+                egui::menu::bar(ui, |ui| {
+                    ui.menu_button("File", |ui| {
+                        ui.add(egui::TextEdit::singleline(&mut scene_path).hint_text("Scene path"));
+                        if ui.button("Open").clicked() {
+                            match Scene::load(&scene_path) {
+                                Ok(loaded) => {
+                                    scene = loaded;
+                                    selected_object = scene.ids().first().copied();
+                                    selected_group = None;
+                                    outliner = Outliner::default();
+                                    scene_status = format!("Opened {}", scene_path);
+                                },
+                                Err(e) => scene_status = format!("Failed to open {}: {}", scene_path, e),
+                            }
+                            ui.close_menu();
+                        }
+                        if ui.button("Save").clicked() {
+                            scene_status = match scene.save(&scene_path) {
+                                Ok(()) => format!("Saved {}", scene_path),
+                                Err(e) => format!("Failed to save {}: {}", scene_path, e),
+                            };
+                            ui.close_menu();
+                        }
+                        if ui.button("Render PNG").clicked() {
+                            let image_path = std::path::Path::new(&scene_path).with_extension("png");
+                            let (width, height) = display.get_framebuffer_dimensions();
+                            let (snapshot, status) = (scene.clone(), status_sender.clone());
+                            scene_status = format!("Rendering {}...", image_path.display());
+                            std::thread::spawn(move || {
+                                let _ = status.send(match snapshot.render(width, height).save_png(&image_path) {
+                                    Ok(()) => format!("Rendered {}", image_path.display()),
+                                    Err(e) => format!("Failed to render {}: {}", image_path.display(), e),
+                                });
+                            });
+                            ui.close_menu();
+                        }
+                        ui.menu_button("Export Mesh", |ui| {
+                            mesh_export_ui(ui, &mut mesh_export);
+                            if ui.button("Export").clicked() {
+                                let mesh_path = std::path::Path::new(&scene_path).with_extension(mesh_export.format.extension());
+                                let (snapshot, export, status) = (scene.clone(), mesh_export.clone(), status_sender.clone());
+                                scene_status = format!("Exporting {}...", mesh_path.display());
+                                std::thread::spawn(move || {
+                                    let _ = status.send(match export.export(&snapshot, &mesh_path) {
+                                        Ok(triangles) => format!("Exported {} triangles to {}", triangles, mesh_path.display()),
+                                        Err(e) => format!("Failed to export {}: {}", mesh_path.display(), e),
+                                    });
+                                });
+                                ui.close_menu();
+                            }
+                        });
+                    });
+                    ui.label(&scene_status);
+                });
             });
-            if (pos.x != x) || (pos.y != y) || (pos.z != z) {
-                scene.obj_transforms[selected_object].translate = vec3(x, y, z);
+
+            if let Some(error) = &shader_error {
+                egui::Window::new("Shader Errors")
+                    .collapsible(true)
+                    .current_pos((420., 20.)).show(egui_ctx, |ui| {
+                        ui.label("Still drawing with the last shaders that compiled.");
+                        egui::ScrollArea::vertical().max_height(400.).show(ui, |ui| {
+                            ui.label(egui::RichText::new(error).monospace().color(ui.visuals().error_fg_color));
+                        });
+                    });
+            }
+
+            if let Some(id) = selected_object.filter(|id| scene.contains(*id)) {
+                let mut transform = *scene.transform(id).unwrap();
+                let mut modifiers = scene.modifiers_mut(id).unwrap().clone();
+                let mut material = scene.material(id).unwrap();
+                let mut remove = false;
+                egui::Window::new(scene.get(id).unwrap().as_str())
+                    .collapsible(true)
+                    .current_pos((20., 20.)).show(egui_ctx, |ui| {
+                    transform_ui(ui, &mut transform, false);
+                    modifiers_ui(ui, &mut modifiers);
+
+                    ui.label("Material");
+                    egui::ComboBox::from_id_source("material")
+                        .selected_text(format!("Material {}", material.0))
+                        .show_ui(ui, |ui| {
+                            for i in 0..scene.materials.len() {
+                                ui.selectable_value(&mut material, MaterialId(i), format!("Material {}", i));
+                            }
+                        });
+                    if ui.button("New Material").clicked() {
+                        material = scene.add_material(scene.materials[material.0]);
+                    }
+                    let m = &mut scene.materials[material.0];
+                    let mut albedo = m.albedo.to_tuple();
+                    let mut emissive = m.emissive.to_tuple();
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut albedo);
+                        ui.label("Albedo");
+                    });
+                    ui.horizontal(|ui| {
+                        ui.color_edit_button_rgb(&mut emissive);
+                        ui.label("Emissive");
+                    });
+                    m.albedo = albedo.into();
+                    m.emissive = emissive.into();
+                    ui.add(egui::Slider::new(&mut m.roughness, 0.0..=1.0).text("Roughness"));
+                    ui.add(egui::Slider::new(&mut m.metallic, 0.0..=1.0).text("Metallic"));
+                    ui.add(egui::Slider::new(&mut m.specular, 0.0..=1.0).text("Specular"));
+                    ui.add(egui::Slider::new(&mut m.opacity, 0.0..=1.0).text("Opacity"));
+
+                    ui.separator();
+                    remove = ui.button("Remove").clicked();
+                });
+                *scene.transform_mut(id).unwrap() = transform;
+                *scene.modifiers_mut(id).unwrap() = modifiers;
+                scene.set_material(id, material);
+                if remove {
+                    scene.remove(id);
+                    selected_object = scene.ids().first().copied();
+                }
             }
-            if (rot.x != yaw) || (rot.y != pitch) || (rot.z != roll) {
-                scene.obj_transforms[selected_object].rotate = vec3(yaw, pitch, roll);
+
+            if let Some(id) = selected_group.filter(|id| scene.contains_group(*id)) {
+                let mut ungroup = false;
+                let group = scene.group_mut(id).unwrap();
+                egui::Window::new("Group")
+                    .collapsible(true)
+                    .current_pos((20., 20.)).show(egui_ctx, |ui| {
+                    ui.add(egui::TextEdit::singleline(&mut group.name).hint_text("Name"));
+                    transform_ui(ui, &mut group.transform, true);
+
+                    ui.separator();
+                    ungroup = ui.button("Ungroup").on_hover_text("Remove the group, keeping its children in place").clicked();
+                });
+                if ungroup {
+                    scene.remove_group(id);
+                    selected_group = None;
+                }
             }
 
+            egui::Window::new("Outliner")
+                .collapsible(true)
+                .current_pos((20., 420.)).show(egui_ctx, |ui| {
+                    if ui.button("New Group").clicked() {
+                        selected_group = Some(scene.add_group("Group", Transform::none()));
+                        selected_object = None;
+                    }
+                    outliner.target = None;
+                    let top = ui.selectable_label(false, "Scene");
+                    if outliner.dragged.is_some() && ui.rect_contains_pointer(top.rect) {
+                        outliner.target = Some(None);
+                    }
+                    ui.indent("outliner", |ui| outliner_rows(ui, &scene, None, &mut selected_object, &mut selected_group, &mut outliner));
+                    if ui.input().pointer.any_released() {
+                        if let (Some(node), Some(target)) = (outliner.dragged.take(), outliner.target) {
+                            if scene.parent(node) != target && !scene.set_parent(node, target) {
+                                scene_status = String::from("Can't move a group into itself");
+                            }
+                        }
+                    }
+                });
+
             egui::Window::new("Scene")
                 .collapsible(true)
                 .current_pos((200., 20.)).show(egui_ctx, |ui| {
                     ui.label("Objects");
                     egui::ComboBox::from_label("New Object")
-                        .selected_text(format!("{}", new_object_choice.as_str()))
+                        .selected_text(new_object_choice.as_str())
                         .show_ui(ui, |ui| {
-                            ui.selectable_value(&mut new_object_choice, Primitive::Sphere(4., rgb(1., 1., 1.)), "Sphere");
+                            ui.selectable_value(&mut new_object_choice, Primitive::Sphere(4.), "Sphere");
                         });
                     // if ui.button("Add").clicked() {
                     //     scene.add(new_object_choice.clone());
                     // }
 
+                    ui.label("Boolean Ops");
+                    for (i, op) in scene.bool_ops_mut().iter_mut().enumerate() {
+                        bool_op_ui(ui, op, &i.to_string());
+                    }
+
+                    ui.label("Lights");
+                    for (i, light) in scene.lights.iter_mut().enumerate() {
+                        ui.horizontal(|ui| {
+                            let mut color = light.color_mut().to_tuple();
+                            ui.color_edit_button_rgb(&mut color);
+                            *light.color_mut() = color.into();
+                            ui.add(egui::DragValue::new(light.intensity_mut()).speed(0.05).clamp_range(0.0..=100.0).prefix("Intensity: "));
+                            ui.label(format!("{} {}", light.as_str(), i));
+                        });
+                    }
+
                     ui.label("Quality");
-                    ui.add(egui::DragValue::new(&mut scene_params.params[0]).speed(0.05).prefix("Shadow Quality: "));
+                    ui.add(egui::DragValue::new(&mut scene.params.shadow_quality).speed(0.05).prefix("Shadow Quality: "));
 
                     ui.label("Performance");
-                    let mut shadows_enabled = scene_params.params[1] != 0.0;
-                    let mut ao_enabled = scene_params.params[2] != 0.0;
-                    ui.add(egui::Checkbox::new(&mut shadows_enabled, "Shadows Enabled"));
-                    ui.add(egui::Checkbox::new(&mut ao_enabled, "Ambient Occlusion Enabled"));
-                    scene_params.params[1] = shadows_enabled as i32 as f32;
-                    scene_params.params[2] = ao_enabled as i32 as f32;
+                    ui.add(egui::Checkbox::new(&mut scene.params.shadows, "Shadows Enabled"));
+                    ui.add(egui::Checkbox::new(&mut scene.params.ambient_occlusion, "Ambient Occlusion Enabled"));
                 });
         });
 
@@ -183,7 +749,23 @@ fn main() {
                 glutin::event::WindowEvent::MouseInput { button, state, .. } => { match state {
                         glutin::event::ElementState::Pressed => {
                             match button {
-                                glutin::event::MouseButton::Left => { mouse[2] = 1.0; },
+                                glutin::event::MouseButton::Left => {
+                                    mouse[2] = 1.0;
+                                    if !gui.egui_ctx.is_pointer_over_area() {
+                                        let (width, height) = display.get_framebuffer_dimensions();
+                                        match scene.pick(mouse[0], mouse[1], width as f32, height as f32) {
+                                            Some(pick) => {
+                                                selected_object = Some(pick.object);
+                                                selected_group = None;
+                                                scene_status = format!("Picked {} at ({:.2}, {:.2}, {:.2}), normal ({:.2}, {:.2}, {:.2})",
+                                                    scene.get(pick.object).unwrap().as_str(),
+                                                    pick.point.x, pick.point.y, pick.point.z,
+                                                    pick.normal.x, pick.normal.y, pick.normal.z);
+                                            },
+                                            None => selected_object = None,
+                                        }
+                                    }
+                                },
                                 glutin::event::MouseButton::Right => { mouse[3] = 1.0; },
                                 _ => ()
                             }
@@ -210,7 +792,11 @@ fn main() {
                 }
             },
             glutin::event::Event::MainEventsCleared => {
+                let previous = selected_object;
                 input(&mut scene, &held_keys, &prev_keys, &mut selected_object);
+                if selected_object != previous {
+                    selected_group = None;
+                }
             },
             glutin::event::Event::NewEvents(cause) => { match cause {
                     glutin::event::StartCause::ResumeTimeReached { .. } => {
@@ -232,39 +818,9 @@ fn main() {
 
         let mut target = display.draw();
         target.clear_color(0.0, 0.0, 0.0, 0.0);
-        let buffer_objects = glium::uniforms::UniformBuffer::new(&display, UniformBlockObjects {
-            objects: scene.get_objects(), 
-        }).unwrap();
-        let buffer_lights = glium::uniforms::UniformBuffer::new(&display, UniformBlockLights {
-            lights: scene.get_lights(), 
-        }).unwrap();
-        let buffer_bool_ops = glium::uniforms::UniformBuffer::new(&display, UniformBlockBoolOps {
-            bool_ops: scene.get_bool_ops(),
-        }).unwrap();
-        let buffer_transforms = glium::uniforms::UniformBuffer::new(&display, UniformBlockTransforms {
-            transformations: scene.get_transformations(),
-        }).unwrap();
-        let scene_fog_color = glium::uniforms::UniformBuffer::new(&display, scene_fog_color).unwrap();
-        let scene_params = glium::uniforms::UniformBuffer::new(&display, scene_params).unwrap();
-        let scene_consts = glium::uniforms::UniformBuffer::new(&display, scene_consts).unwrap();
-        target.draw(&vertex_buffer, &indices, &program, &uniform! {
-            time: time, 
-            resolution: [display.get_framebuffer_dimensions().0 as f32, display.get_framebuffer_dimensions().1 as f32],
-            mouse: mouse,
-
-            camera: scene.camera.as_data(),
-            camera_origin: scene.camera.origin.to_tuple(),
-            camera_focal_length: scene.camera.focal_length,
-
-            scene_fog_color: &scene_fog_color,
-            scene_params: &scene_params,
-            scene_consts: &scene_consts,
-
-            scene_objects: &buffer_objects,
-            scene_lights: &buffer_lights,
-            scene_bool_ops: &buffer_bool_ops,
-            scene_transforms: &buffer_transforms,
-        }, &Default::default()).unwrap();
+        if let Err(e) = gpu.draw(&display, &mut target, &scene, time, mouse) {
+            scene_status = format!("Failed to draw the scene: {}", e);
+        }
         gui.paint(&display, &mut target);
         target.finish().unwrap();
     });
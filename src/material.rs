@@ -0,0 +1,65 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// Index into a `Scene`'s material table.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct MaterialId(pub usize);
+
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Material {
+    pub albedo: Rgb,
+    pub roughness: f32,
+    pub metallic: f32,
+    pub specular: f32,
+    pub emissive: Rgb,
+    pub opacity: f32,
+}
+
+impl Material {
+    pub fn new(albedo: Rgb) -> Self {
+        Self {
+            albedo,
+            ..Default::default()
+        }
+    }
+    pub fn metal(albedo: Rgb, roughness: f32) -> Self {
+        Self {
+            albedo,
+            roughness,
+            metallic: 1.0,
+            ..Default::default()
+        }
+    }
+    pub fn emissive(color: Rgb) -> Self {
+        Self {
+            albedo: color,
+            emissive: color,
+            ..Default::default()
+        }
+    }
+    /// Packed as three texels of the `scene_materials` buffer texture.
+    pub fn get_data(&self) -> [[f32; 4]; 3] {
+        [
+            [self.albedo.x, self.albedo.y, self.albedo.z, self.roughness],
+            [self.emissive.x, self.emissive.y, self.emissive.z, self.metallic],
+            [self.specular, self.opacity, 0.0, 0.0],
+        ]
+    }
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Self {
+            albedo: rgb(1.0, 1.0, 1.0),
+            roughness: 0.6,
+            metallic: 0.0,
+            specular: 1.0,
+            emissive: rgb(0.0, 0.0, 0.0),
+            opacity: 1.0,
+        }
+    }
+}
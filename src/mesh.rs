@@ -0,0 +1,532 @@
+use std::collections::HashMap;
+use std::io::Write;
+use std::path::Path;
+use super::*;
+
+
+
+// ridge weight pulling a dual contouring vertex towards the middle of its cell's crossings,
+// for flat and edge-only cells where the planes alone don't pin it down
+const QEF_REGULARIZATION: f32 = 0.001;
+// halvings of a grid edge when placing a dual contouring crossing on it
+const CROSSING_BISECTIONS: usize = 10;
+// glTF written for a mesh without triangles, a scene with no nodes
+const EMPTY_GLTF: &str = r#"{"asset":{"version":"2.0","generator":"gpu-raymarching"},"scene":0,"scenes":[{}]}"#;
+
+/// A triangle mesh with a normal and a colour per vertex, wound counterclockwise seen from outside.
+#[derive(Clone, Default, Debug)]
+pub struct Mesh {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Rgb>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// How `Scene::mesh` turns the sampled distances into triangles.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MeshMethod {
+    /// A vertex on each grid edge the surface crosses. Smooth, but rounds off sharp edges.
+    MarchingCubes,
+    /// A vertex inside each cell the surface passes through, placed to line up with the
+    /// surface's tangent planes, so the edges and corners of boxes stay sharp.
+    DualContouring,
+}
+
+impl MeshMethod {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::MarchingCubes => "Marching Cubes",
+            Self::DualContouring => "Dual Contouring",
+        }
+    }
+}
+
+/// File formats `Mesh::save` writes, picked by the file's extension.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum MeshFormat {
+    Obj,
+    Stl,
+    Ply,
+    Gltf,
+    Glb,
+}
+
+impl MeshFormat {
+    pub fn all() -> [Self; 5] {
+        [Self::Obj, Self::Stl, Self::Ply, Self::Gltf, Self::Glb]
+    }
+    pub fn extension(&self) -> &str {
+        match self {
+            Self::Obj => "obj",
+            Self::Stl => "stl",
+            Self::Ply => "ply",
+            Self::Gltf => "gltf",
+            Self::Glb => "glb",
+        }
+    }
+    pub fn from_path<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let extension = path.as_ref().extension()?.to_str()?.to_lowercase();
+        Self::all().into_iter().find(|format| format.extension() == extension)
+    }
+}
+
+
+// corners of a cell are numbered x + 2y + 4z, and each face lists its corners counterclockwise
+// seen from outside the cell
+const CELL_FACES: [[usize; 4]; 6] = [
+    [0, 4, 6, 2], [1, 3, 7, 5],
+    [0, 1, 5, 4], [2, 6, 7, 3],
+    [0, 2, 3, 1], [4, 5, 7, 6],
+];
+
+fn corner_offset(corner: usize) -> [usize; 3] {
+    [corner & 1, (corner >> 1) & 1, (corner >> 2) & 1]
+}
+
+/// Distances sampled at the corners of a grid of cells, padded by a cell on every side.
+struct Grid {
+    origin: Point3,
+    cell: f32,
+    size: [usize; 3],
+    values: Vec<f32>,
+}
+
+impl Grid {
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.size[1] + y) * self.size[0] + x
+    }
+    fn point(&self, [x, y, z]: [usize; 3]) -> Point3 {
+        self.origin + vec3(x as f32, y as f32, z as f32) * self.cell
+    }
+    fn value(&self, at: [usize; 3]) -> f32 {
+        self.values[self.index(at)]
+    }
+    /// Where the surface crosses the grid edge from `at` along `axis`, by linear interpolation.
+    fn crossing(&self, at: [usize; 3], axis: usize) -> Point3 {
+        let mut end = at;
+        end[axis] += 1;
+        let (a, b) = (self.value(at), self.value(end));
+        let t = a / (a - b);
+        self.point(at).lerp(self.point(end), t)
+    }
+}
+
+/// The scene's distance field cut off at a box, so meshes of open or infinite surfaces close
+/// along the box's faces.
+struct BoundedField<'a> {
+    scene: &'a Scene,
+    program: CsgProgram,
+    centre: Point3,
+    half: Vec3,
+}
+
+impl BoundedField<'_> {
+    fn distance(&self, p: Point3) -> f32 {
+        let q = (p - self.centre).abs() - self.half;
+        let bounds = q.max(0.0).length() + q.x.max(q.y).max(q.z).min(0.0);
+        let dist = self.scene.evaluate_program(&self.program, p).map_or(1e20, |res| res.distance);
+        dist.max(bounds)
+    }
+    /// Where the surface crosses the segment from `a` to `b`, found by bisection since the
+    /// distance isn't linear along it near edges and corners.
+    fn crossing(&self, mut a: Point3, mut b: Point3) -> Point3 {
+        let inside = self.distance(a) < 0.0;
+        for _ in 0..CROSSING_BISECTIONS {
+            let m = (a + b) * 0.5;
+            if (self.distance(m) < 0.0) == inside { a = m } else { b = m }
+        }
+        (a + b) * 0.5
+    }
+    fn normal(&self, p: Point3, eps: f32) -> Vec3 {
+        let d = |offset: Vec3| self.distance(p + offset) - self.distance(p - offset);
+        let n = vec3(d(vec3(eps, 0., 0.)), d(vec3(0., eps, 0.)), d(vec3(0., 0., eps)));
+        if n.length() > 0.0 { n.normalize() } else { vec3(0., 1., 0.) }
+    }
+}
+
+
+impl Scene {
+    /// Samples the distance field on a grid over the box from `min` to `max`, with `resolution`
+    /// cells along its longest side, and extracts the surface as a triangle mesh in world space.
+    /// The surface is cut off at the box, so the mesh is closed even for planes. Vertices take
+    /// the albedo of the material at their position.
+    pub fn mesh(&self, min: Point3, max: Point3, resolution: u32, method: MeshMethod) -> Mesh {
+        let extent = (max - min).abs();
+        let cell = extent.x.max(extent.y).max(extent.z).max(1e-6) / resolution.max(1) as f32;
+        let field = BoundedField {
+            scene: self,
+            program: self.csg_program(),
+            centre: (min + max) * 0.5,
+            half: extent * 0.5,
+        };
+        let cells = |e: f32| (e / cell).ceil().max(1.0) as usize;
+        // one padding cell past each side keeps every crossing away from the grid's border
+        let mut grid = Grid {
+            origin: vec3(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z)) - cell,
+            cell,
+            size: [cells(extent.x) + 3, cells(extent.y) + 3, cells(extent.z) + 3],
+            values: Vec::new(),
+        };
+        let mut values = vec![0.0; grid.size.iter().product()];
+        let slice = grid.size[0] * grid.size[1];
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let slices_per_thread = grid.size[2].div_ceil(threads).max(1);
+        std::thread::scope(|s| {
+            for (i, values) in values.chunks_mut(slices_per_thread * slice).enumerate() {
+                let (field, grid) = (&field, &grid);
+                s.spawn(move || {
+                    for (j, value) in values.iter_mut().enumerate() {
+                        let k = i * slices_per_thread * slice + j;
+                        let at = [k % grid.size[0], k / grid.size[0] % grid.size[1], k / slice];
+                        *value = field.distance(grid.point(at));
+                    }
+                });
+            }
+        });
+        grid.values = values;
+
+        let mut mesh = match method {
+            MeshMethod::MarchingCubes => marching_cubes(&grid),
+            MeshMethod::DualContouring => dual_contouring(&grid, &field),
+        };
+        let eps = cell * 0.05;
+        mesh.normals = mesh.positions.iter().map(|p| field.normal(*p, eps)).collect();
+        mesh.colors = mesh.positions.iter().map(|p| {
+            self.evaluate_program(&field.program, *p).map_or(rgb(1.0, 1.0, 1.0), |res| res.material(&self.materials).albedo)
+        }).collect();
+        mesh
+    }
+}
+
+fn marching_cubes(grid: &Grid) -> Mesh {
+    let mut mesh = Mesh::default();
+    let mut vertices: HashMap<(usize, usize), u32> = HashMap::new();
+    let [sx, sy, sz] = grid.size;
+    for z in 0..sz - 1 {
+        for y in 0..sy - 1 {
+            for x in 0..sx - 1 {
+                let corner = |c: usize| {
+                    let o = corner_offset(c);
+                    [x + o[0], y + o[1], z + o[2]]
+                };
+                let inside: Vec<bool> = (0..8).map(|c| grid.value(corner(c)) < 0.0).collect();
+                if inside.iter().all(|i| *i) || inside.iter().all(|i| !*i) {
+                    continue;
+                }
+                // walking each face, the surface enters the inside corners at one crossing and
+                // leaves at the next, which cuts the inside corners of ambiguous faces apart.
+                // Each crossing is entered on one face and left on the other, so following
+                // them joins the face segments into loops around the surface
+                let mut next: Vec<((usize, usize), (usize, usize))> = Vec::new();
+                for face in CELL_FACES.iter() {
+                    let mut entered = None;
+                    for k in (0..4).chain(0..4) {
+                        let (a, b) = (face[k], face[(k + 1) % 4]);
+                        let edge = (a.min(b), a.max(b));
+                        if !inside[a] && inside[b] {
+                            entered = Some(edge);
+                        } else if inside[a] && !inside[b] {
+                            if let Some(start) = entered.take() {
+                                if !next.contains(&(start, edge)) {
+                                    next.push((start, edge));
+                                }
+                            }
+                        }
+                    }
+                }
+                while let Some(&(start, _)) = next.first() {
+                    let mut polygon = Vec::new();
+                    let mut edge = start;
+                    while let Some(i) = next.iter().position(|(from, _)| *from == edge) {
+                        let (_, following) = next.swap_remove(i);
+                        let at = corner(edge.0);
+                        let axis = (edge.0 ^ edge.1).trailing_zeros() as usize;
+                        polygon.push(*vertices.entry((grid.index(at), axis)).or_insert_with(|| {
+                            mesh.positions.push(grid.crossing(at, axis));
+                            (mesh.positions.len() - 1) as u32
+                        }));
+                        edge = following;
+                    }
+                    for i in 1..polygon.len().saturating_sub(1) {
+                        mesh.triangles.push([polygon[0], polygon[i], polygon[i + 1]]);
+                    }
+                }
+            }
+        }
+    }
+    mesh
+}
+
+fn dual_contouring(grid: &Grid, field: &BoundedField) -> Mesh {
+    let mut mesh = Mesh::default();
+    let [sx, sy, sz] = grid.size;
+    let eps = grid.cell * 0.05;
+    // the crossing and surface normal on every grid edge the surface crosses
+    let mut crossings: HashMap<(usize, usize), (Point3, Vec3)> = HashMap::new();
+    for z in 0..sz {
+        for y in 0..sy {
+            for x in 0..sx {
+                for axis in 0..3 {
+                    let mut end = [x, y, z];
+                    end[axis] += 1;
+                    if end[axis] >= grid.size[axis] || (grid.value([x, y, z]) < 0.0) == (grid.value(end) < 0.0) {
+                        continue;
+                    }
+                    let p = field.crossing(grid.point([x, y, z]), grid.point(end));
+                    crossings.insert((grid.index([x, y, z]), axis), (p, field.normal(p, eps)));
+                }
+            }
+        }
+    }
+    // a vertex per cell with a crossing on any of its edges
+    let mut vertices: HashMap<usize, u32> = HashMap::new();
+    let mut cell_vertex = |cell: [usize; 3], mesh: &mut Mesh| -> u32 {
+        *vertices.entry(grid.index(cell)).or_insert_with(|| {
+            let mut planes = Vec::new();
+            for (a, b) in (0..8).flat_map(|a| (0..3).map(move |axis| (a, a | 1 << axis))).filter(|(a, b)| a != b) {
+                let o = corner_offset(a);
+                let at = [cell[0] + o[0], cell[1] + o[1], cell[2] + o[2]];
+                if let Some(plane) = crossings.get(&(grid.index(at), (a ^ b).trailing_zeros() as usize)) {
+                    planes.push(*plane);
+                }
+            }
+            let lo = grid.point(cell);
+            mesh.positions.push(solve_qef(&planes, lo, lo + grid.cell));
+            (mesh.positions.len() - 1) as u32
+        })
+    };
+    let mut edges: Vec<&(usize, usize)> = crossings.keys().collect();
+    edges.sort();
+    for &(index, axis) in edges {
+        let at = [index % sx, index / sx % sy, index / (sx * sy)];
+        let (b, c) = ((axis + 1) % 3, (axis + 2) % 3);
+        if at[b] == 0 || at[c] == 0 {
+            continue;
+        }
+        // the four cells around the edge, counterclockwise about it
+        let mut cells = [at; 4];
+        cells[0][b] -= 1;
+        cells[0][c] -= 1;
+        cells[1][c] -= 1;
+        cells[3][b] -= 1;
+        let quad = cells.map(|cell| cell_vertex(cell, &mut mesh));
+        let [q0, q1, q2, q3] = if grid.value(at) < 0.0 { quad } else { [quad[3], quad[2], quad[1], quad[0]] };
+        mesh.triangles.push([q0, q1, q2]);
+        mesh.triangles.push([q0, q2, q3]);
+    }
+    mesh
+}
+
+/// The point closest to all the tangent planes through `planes`, regularized towards their
+/// mass point and kept within the cell from `lo` to `hi`.
+fn solve_qef(planes: &[(Point3, Vec3)], lo: Point3, hi: Point3) -> Point3 {
+    let mass = planes.iter().fold(vec3(0., 0., 0.), |sum, (p, _)| sum + *p) / planes.len().max(1) as f32;
+    // solved relative to the mass point, which keeps the numbers small
+    let mut a = [[0f32; 3]; 3];
+    let mut rhs = [0f32; 3];
+    for (p, n) in planes.iter() {
+        let d = n.dot(*p - mass);
+        for i in 0..3 {
+            for j in 0..3 {
+                a[i][j] += n[i] * n[j];
+            }
+            rhs[i] += n[i] * d;
+        }
+    }
+    for (i, row) in a.iter_mut().enumerate() {
+        row[i] += QEF_REGULARIZATION;
+    }
+    let x = match solve3(a, rhs) {
+        Some(x) => mass + vec3(x[0], x[1], x[2]),
+        None => mass,
+    };
+    let inside = (0..3).all(|i| x[i] >= lo[i] - 1e-4 && x[i] <= hi[i] + 1e-4);
+    if inside { x } else { mass }
+}
+
+// Gaussian elimination with partial pivoting
+fn solve3(mut a: [[f32; 3]; 3], mut b: [f32; 3]) -> Option<[f32; 3]> {
+    for col in 0..3 {
+        let pivot = (col..3).max_by(|i, j| a[*i][col].abs().total_cmp(&a[*j][col].abs()))?;
+        if a[pivot][col].abs() < 1e-9 {
+            return None;
+        }
+        a.swap(col, pivot);
+        b.swap(col, pivot);
+        for row in col + 1..3 {
+            let f = a[row][col] / a[col][col];
+            let pivot_row = a[col];
+            for (x, p) in a[row].iter_mut().zip(pivot_row.iter()).skip(col) {
+                *x -= f * p;
+            }
+            b[row] -= f * b[col];
+        }
+    }
+    let mut x = [0f32; 3];
+    for row in (0..3).rev() {
+        let sum: f32 = (row + 1..3).map(|k| a[row][k] * x[k]).sum();
+        x[row] = (b[row] - sum) / a[row][row];
+    }
+    Some(x)
+}
+
+
+impl Mesh {
+    /// Writes the mesh in the format matching the extension of `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let format = MeshFormat::from_path(&path).ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput,
+            format!("{} doesn't end in one of .obj, .stl, .ply, .gltf or .glb", path.as_ref().display())))?;
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        self.write(&mut file, format)?;
+        file.flush()
+    }
+    pub fn write<W: Write>(&self, w: &mut W, format: MeshFormat) -> std::io::Result<()> {
+        match format {
+            MeshFormat::Obj => self.write_obj(w),
+            MeshFormat::Stl => self.write_stl(w),
+            MeshFormat::Ply => self.write_ply(w),
+            MeshFormat::Gltf => self.write_gltf(w),
+            MeshFormat::Glb => self.write_glb(w),
+        }
+    }
+    /// Wavefront OBJ, with the common extension of colours after each vertex position.
+    pub fn write_obj<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        for (p, c) in self.positions.iter().zip(self.colors.iter()) {
+            writeln!(w, "v {} {} {} {} {} {}", p.x, p.y, p.z, c.x, c.y, c.z)?;
+        }
+        for n in self.normals.iter() {
+            writeln!(w, "vn {} {} {}", n.x, n.y, n.z)?;
+        }
+        for [a, b, c] in self.triangles.iter().map(|t| t.map(|i| i + 1)) {
+            writeln!(w, "f {}//{} {}//{} {}//{}", a, a, b, b, c, c)?;
+        }
+        Ok(())
+    }
+    /// Binary STL, which has no colours.
+    pub fn write_stl<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        w.write_all(&[0u8; 80])?;
+        w.write_all(&(self.triangles.len() as u32).to_le_bytes())?;
+        for t in self.triangles.iter() {
+            let [a, b, c] = t.map(|i| self.positions[i as usize]);
+            let n = (b - a).cross(c - a);
+            let n = if n.length() > 0.0 { n.normalize() } else { n };
+            for v in [n, a, b, c] {
+                for x in [v.x, v.y, v.z] {
+                    w.write_all(&x.to_le_bytes())?;
+                }
+            }
+            w.write_all(&[0u8; 2])?;
+        }
+        Ok(())
+    }
+    /// Binary little endian PLY, with 8 bit colours.
+    pub fn write_ply<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write!(w, "ply\nformat binary_little_endian 1.0\nelement vertex {}\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            element face {}\nproperty list uchar int vertex_indices\nend_header\n",
+            self.positions.len(), self.triangles.len())?;
+        for i in 0..self.positions.len() {
+            let (p, n, c) = (self.positions[i], self.normals[i], self.colors[i]);
+            for x in [p.x, p.y, p.z, n.x, n.y, n.z] {
+                w.write_all(&x.to_le_bytes())?;
+            }
+            w.write_all(&[c.x, c.y, c.z].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8))?;
+        }
+        for t in self.triangles.iter() {
+            w.write_all(&[3])?;
+            for i in t {
+                w.write_all(&(*i as i32).to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+    /// glTF 2.0 with the buffer embedded as a base64 data URI. An empty mesh is written as
+    /// a scene with nothing in it, since glTF has no empty meshes or buffers.
+    pub fn write_gltf<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        if self.triangles.is_empty() {
+            return w.write_all(EMPTY_GLTF.as_bytes());
+        }
+        let buffer = self.gltf_buffer();
+        let uri = format!("data:application/octet-stream;base64,{}", base64(&buffer));
+        w.write_all(self.gltf_json(buffer.len(), Some(&uri)).as_bytes())
+    }
+    /// Binary glTF 2.0, the JSON and buffer in one file. An empty mesh has no buffer chunk,
+    /// as in `write_gltf`.
+    pub fn write_glb<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let (mut json, mut buffer) = if self.triangles.is_empty() {
+            (EMPTY_GLTF.as_bytes().to_vec(), Vec::new())
+        } else {
+            let buffer = self.gltf_buffer();
+            (self.gltf_json(buffer.len(), None).into_bytes(), buffer)
+        };
+        // chunks are padded to 4 bytes, JSON with spaces and binary with zeros
+        json.resize(json.len().next_multiple_of(4), b' ');
+        buffer.resize(buffer.len().next_multiple_of(4), 0);
+        let bin_chunk = if buffer.is_empty() { 0 } else { 8 + buffer.len() as u32 };
+        w.write_all(b"glTF")?;
+        w.write_all(&2u32.to_le_bytes())?;
+        w.write_all(&(12 + 8 + json.len() as u32 + bin_chunk).to_le_bytes())?;
+        w.write_all(&(json.len() as u32).to_le_bytes())?;
+        w.write_all(b"JSON")?;
+        w.write_all(&json)?;
+        if buffer.is_empty() {
+            return Ok(());
+        }
+        w.write_all(&(buffer.len() as u32).to_le_bytes())?;
+        w.write_all(b"BIN\0")?;
+        w.write_all(&buffer)
+    }
+    // positions, normals and colours as float VEC3s, then the indices as u32s
+    fn gltf_buffer(&self) -> Vec<u8> {
+        let floats = [&self.positions, &self.normals, &self.colors].into_iter()
+            .flat_map(|vs| vs.iter().flat_map(|v| [v.x, v.y, v.z]));
+        let indices = self.triangles.iter().flatten();
+        floats.flat_map(|x| x.to_le_bytes()).chain(indices.flat_map(|i| i.to_le_bytes())).collect()
+    }
+    fn gltf_json(&self, buffer_len: usize, uri: Option<&str>) -> String {
+        let (n, attribute) = (self.positions.len(), self.positions.len() * 12);
+        let (min, max) = self.positions.iter().fold((vec3(1e20, 1e20, 1e20), vec3(-1e20, -1e20, -1e20)), |(min, max), p| {
+            (vec3(min.x.min(p.x), min.y.min(p.y), min.z.min(p.z)), vec3(max.x.max(p.x), max.y.max(p.y), max.z.max(p.z)))
+        });
+        let uri = uri.map_or(String::new(), |uri| format!(r#","uri":"{}""#, uri));
+        format!(concat!(
+            r#"{{"asset":{{"version":"2.0","generator":"gpu-raymarching"}},"scene":0,"scenes":[{{"nodes":[0]}}],"nodes":[{{"mesh":0}}],"#,
+            r#""meshes":[{{"primitives":[{{"attributes":{{"POSITION":0,"NORMAL":1,"COLOR_0":2}},"indices":3}}]}}],"#,
+            r#""accessors":["#,
+            r#"{{"bufferView":0,"componentType":5126,"count":{n},"type":"VEC3","min":[{},{},{}],"max":[{},{},{}]}},"#,
+            r#"{{"bufferView":1,"componentType":5126,"count":{n},"type":"VEC3"}},"#,
+            r#"{{"bufferView":2,"componentType":5126,"count":{n},"type":"VEC3"}},"#,
+            r#"{{"bufferView":3,"componentType":5125,"count":{}}}],"#,
+            r#""bufferViews":["#,
+            r#"{{"buffer":0,"byteOffset":0,"byteLength":{a},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{a},"byteLength":{a},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{a},"target":34962}},"#,
+            r#"{{"buffer":0,"byteOffset":{},"byteLength":{},"target":34963}}],"#,
+            r#""buffers":[{{"byteLength":{}{}}}]}}"#),
+            min.x, min.y, min.z, max.x, max.y, max.z,
+            self.triangles.len() * 3,
+            2 * attribute,
+            3 * attribute, self.triangles.len() * 12,
+            buffer_len, uri,
+            n = n, a = attribute)
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [chunk[0], *chunk.get(1).unwrap_or(&0), *chunk.get(2).unwrap_or(&0)];
+        let n = (b[0] as u32) << 16 | (b[1] as u32) << 8 | b[2] as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                out.push(ALPHABET[(n >> (18 - 6 * i) & 63) as usize] as char);
+            } else {
+                out.push('=');
+            }
+        }
+    }
+    out
+}
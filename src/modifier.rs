@@ -0,0 +1,91 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// Reshapes the space an object or CSG subtree is evaluated in. Modifiers in a stack are
+/// applied to the point in order, and the distance ones (`Onion`, `Round`) are applied to
+/// the resulting distance in reverse, so the first modifier in the stack is the outermost.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Modifier {
+    Repeat(Vec3), // Spacing, 0 to leave an axis unrepeated
+    RepeatLimited(Vec3, Vec3), // Spacing, Copies On Each Side
+    Mirror(Vec3, f32), // Plane Normal, Distance Along Normal
+    Twist(Axis, f32), // Axis, Radians Per Unit Along It
+    Bend(Axis, f32), // Axis, Radians Per Unit Along The Next Axis
+    Elongate(Vec3), // Half Lengths
+    Onion(f32), // Shell Thickness
+    Round(f32), // Radius
+}
+
+impl Modifier {
+    pub fn id(&self) -> f32 {
+        match &self {
+            Self::Repeat(..) => 1.,
+            Self::RepeatLimited(..) => 2.,
+            Self::Mirror(..) => 3.,
+            Self::Twist(..) => 4.,
+            Self::Bend(..) => 5.,
+            Self::Elongate(..) => 6.,
+            Self::Onion(..) => 7.,
+            Self::Round(..) => 8.,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match &self {
+            Self::Repeat(..) => "Repeat",
+            Self::RepeatLimited(..) => "Repeat Limited",
+            Self::Mirror(..) => "Mirror",
+            Self::Twist(..) => "Twist",
+            Self::Bend(..) => "Bend",
+            Self::Elongate(..) => "Elongate",
+            Self::Onion(..) => "Onion",
+            Self::Round(..) => "Round",
+        }
+    }
+    /// One of each modifier with parameters that visibly change a unit sized object.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::Repeat(vec3(3., 0., 3.)),
+            Self::RepeatLimited(vec3(2., 0., 0.), vec3(1., 0., 0.)),
+            Self::Mirror(vec3(1., 0., 0.), 0.),
+            Self::Twist(Axis::Y, 1.),
+            Self::Bend(Axis::Z, 0.3),
+            Self::Elongate(vec3(0.5, 0., 0.)),
+            Self::Onion(0.05),
+            Self::Round(0.1),
+        ]
+    }
+    /// Packed as two texels, (id, a, b, c), (d, e, f, g), inline in the CSG program.
+    pub fn get_data(&self) -> [[f32; 4]; 2] {
+        match *self {
+            Self::Repeat(spacing) => [
+                [self.id(), spacing.x, spacing.y, spacing.z],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::RepeatLimited(spacing, copies) => [
+                [self.id(), spacing.x, spacing.y, spacing.z],
+                [copies.x, copies.y, copies.z, 0.0]],
+            Self::Mirror(normal, k) => {
+                // a zero normal would put NaNs in the shader, mirror across X instead
+                let n = if normal.length() > 0.0 { normal.normalize() } else { vec3(1., 0., 0.) };
+                [[self.id(), n.x, n.y, n.z],
+                 [k, 0.0, 0.0, 0.0]]
+            },
+            Self::Twist(axis, rate) => [
+                [self.id(), axis.as_int() as f32, rate, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Bend(axis, rate) => [
+                [self.id(), axis.as_int() as f32, rate, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Elongate(half) => [
+                [self.id(), half.x, half.y, half.z],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Onion(thickness) => [
+                [self.id(), thickness, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Round(radius) => [
+                [self.id(), radius, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+        }
+    }
+}
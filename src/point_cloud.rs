@@ -0,0 +1,253 @@
+use std::io::Write;
+use std::path::Path;
+use super::*;
+
+
+
+// Newton steps taken to move a point from the mesh onto the zero level set
+const PROJECTION_STEPS: usize = 6;
+// grid cells along the longest side of the bounds for the mesh surface samples start from
+const SURFACE_MESH_RESOLUTION: u32 = 96;
+
+/// What a point in a `PointCloud` was sampled as.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SampleKind {
+    /// On the surface, at distance 0.
+    Surface,
+    /// A surface sample pushed off the surface by a normally distributed offset.
+    NearSurface,
+    /// Anywhere in the bounds.
+    Volume,
+}
+
+impl SampleKind {
+    pub fn id(&self) -> u8 {
+        match self {
+            Self::Surface => 0,
+            Self::NearSurface => 1,
+            Self::Volume => 2,
+        }
+    }
+}
+
+/// Settings for `Scene::sample_points`.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct PointSampling {
+    pub min: Point3,
+    pub max: Point3,
+    pub seed: u64,
+    pub surface: usize,
+    pub near_surface: usize,
+    pub volume: usize,
+    /// Standard deviation of the near surface samples' distance from the surface.
+    pub near_surface_spread: f32,
+}
+
+impl Default for PointSampling {
+    fn default() -> Self {
+        Self {
+            min: vec3(-5., -5., -5.),
+            max: vec3(5., 5., 5.),
+            seed: 0,
+            surface: 10000,
+            near_surface: 10000,
+            volume: 10000,
+            near_surface_spread: 0.02,
+        }
+    }
+}
+
+/// Points sampled from a scene, with the surface normal (the distance gradient away from the
+/// surface), the material's albedo and the object nearest to each, and its signed distance.
+#[derive(Clone, Default, Debug)]
+pub struct PointCloud {
+    pub positions: Vec<Point3>,
+    pub normals: Vec<Vec3>,
+    pub colors: Vec<Rgb>,
+    pub objects: Vec<ObjectId>,
+    /// The scene's distance field at each point. It's the exact distance to the surface for
+    /// most primitives on their own, and outside unions of them, but only a bound under the
+    /// other boolean ops, terrain, twists and bends, and non-uniform scales: the surface is at
+    /// least that far away, and may be farther.
+    pub distances: Vec<f32>,
+    pub kinds: Vec<SampleKind>,
+    /// Samples asked for but left out: surface and near surface ones no point on the surface
+    /// could be found for, which happens when the bounds hold little or no surface, or every
+    /// sample of an empty scene.
+    pub shortfall: usize,
+}
+
+/// SplitMix64, small and seedable, so a seed gives the same samples on every platform.
+struct Random(u64);
+
+impl Random {
+    fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e3779b97f4a7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+        z ^ (z >> 31)
+    }
+    /// Uniform in [0, 1).
+    fn float(&mut self) -> f32 {
+        (self.next() >> 40) as f32 / (1u64 << 24) as f32
+    }
+    fn normal(&mut self) -> f32 {
+        let (u, v) = (1.0 - self.float(), self.float());
+        (-2.0 * u.ln()).sqrt() * (2.0 * std::f32::consts::PI * v).cos()
+    }
+}
+
+
+impl Scene {
+    /// Samples points on, near and around the surface within the bounds, in that order.
+    /// Surface samples are spread uniformly over the surface's area: they're drawn from the
+    /// triangles of a marching cubes mesh by area and then moved onto the zero level set.
+    /// The same settings always give the same points.
+    pub fn sample_points(&self, sampling: &PointSampling) -> PointCloud {
+        let mut cloud = PointCloud::default();
+        let program = self.csg_program();
+        if program.is_empty() {
+            cloud.shortfall = sampling.surface + sampling.near_surface + sampling.volume;
+            return cloud;
+        }
+        let mut random = Random(sampling.seed);
+        let (min, max) = (sampling.min, sampling.max);
+        let lo = vec3(min.x.min(max.x), min.y.min(max.y), min.z.min(max.z));
+        let extent = (max - min).abs();
+        let distance = |p: Point3| self.evaluate_program(&program, p).map_or(1e20, |res| res.distance);
+        let renderer = Renderer::new(self, &self.camera);
+        let within = |p: Point3| (0..3).all(|i| p[i] >= lo[i] && p[i] <= lo[i] + extent[i]);
+
+        // the mesh is closed off along the bounds, leave those faces out
+        let mesh = self.mesh(min, max, SURFACE_MESH_RESOLUTION, MeshMethod::MarchingCubes);
+        let cell = extent.x.max(extent.y).max(extent.z) / SURFACE_MESH_RESOLUTION as f32;
+        let triangles: Vec<[Point3; 3]> = mesh.triangles.iter()
+            .map(|t| t.map(|i| mesh.positions[i as usize]))
+            .filter(|[a, b, c]| distance((*a + *b + *c) / 3.0).abs() < 0.5 * cell)
+            .collect();
+        let mut areas = Vec::with_capacity(triangles.len());
+        let mut total = 0.0;
+        for [a, b, c] in triangles.iter() {
+            total += (*b - *a).cross(*c - *a).length() * 0.5;
+            areas.push(total);
+        }
+        let surface_point = |random: &mut Random| -> Option<Point3> {
+            if total <= 0.0 {
+                return None;
+            }
+            for _ in 0..16 {
+                let target = random.float() * total;
+                let [a, b, c] = triangles[areas.partition_point(|area| *area < target).min(triangles.len() - 1)];
+                let (r1, r2) = (random.float().sqrt(), random.float());
+                let mut p = a * (1.0 - r1) + b * (r1 * (1.0 - r2)) + c * (r1 * r2);
+                for _ in 0..PROJECTION_STEPS {
+                    p = p - renderer.get_normal(p) * distance(p);
+                }
+                if within(p) && distance(p).abs() < 0.01 * cell {
+                    return Some(p);
+                }
+            }
+            None
+        };
+
+        let mut points: Vec<(Point3, SampleKind)> = Vec::new();
+        for _ in 0..sampling.surface {
+            match surface_point(&mut random) {
+                Some(p) => points.push((p, SampleKind::Surface)),
+                None => cloud.shortfall += 1,
+            }
+        }
+        for _ in 0..sampling.near_surface {
+            match surface_point(&mut random) {
+                Some(p) => {
+                    let offset = renderer.get_normal(p) * (random.normal() * sampling.near_surface_spread);
+                    points.push((p + offset, SampleKind::NearSurface));
+                },
+                None => cloud.shortfall += 1,
+            }
+        }
+        for _ in 0..sampling.volume {
+            let p = lo + vec3(random.float(), random.float(), random.float()) * extent;
+            points.push((p, SampleKind::Volume));
+        }
+
+        for (p, kind) in points {
+            let res = self.evaluate_program(&program, p).unwrap();
+            let distances = self.object_distances(&program, p);
+            let nearest = (0..distances.len()).min_by(|a, b| distances[*a].total_cmp(&distances[*b])).unwrap();
+            cloud.positions.push(p);
+            cloud.normals.push(renderer.get_normal(p));
+            cloud.colors.push(res.material(&self.materials).albedo);
+            cloud.objects.push(self.ids()[nearest]);
+            cloud.distances.push(if kind == SampleKind::Surface { 0.0 } else { res.distance });
+            cloud.kinds.push(kind);
+        }
+        cloud
+    }
+}
+
+
+impl PointCloud {
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+    /// Writes the points as PLY or NPY, picked by the extension of `path`.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let extension = path.as_ref().extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase());
+        if !matches!(extension.as_deref(), Some("ply") | Some("npy")) {
+            return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput,
+                format!("{} doesn't end in .ply or .npy", path.as_ref().display())));
+        }
+        let mut file = std::io::BufWriter::new(std::fs::File::create(&path)?);
+        if extension.as_deref() == Some("ply") {
+            self.write_ply(&mut file)?;
+        } else {
+            self.write_npy(&mut file)?;
+        }
+        file.flush()
+    }
+    /// Binary little endian PLY vertices with position, normal, 8 bit colour, object id,
+    /// signed distance and `SampleKind` id.
+    pub fn write_ply<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        write!(w, "ply\nformat binary_little_endian 1.0\nelement vertex {}\n\
+            property float x\nproperty float y\nproperty float z\n\
+            property float nx\nproperty float ny\nproperty float nz\n\
+            property uchar red\nproperty uchar green\nproperty uchar blue\n\
+            property int object\nproperty float distance\nproperty uchar kind\nend_header\n", self.len())?;
+        for i in 0..self.len() {
+            let (p, n, c) = (self.positions[i], self.normals[i], self.colors[i]);
+            for x in [p.x, p.y, p.z, n.x, n.y, n.z] {
+                w.write_all(&x.to_le_bytes())?;
+            }
+            w.write_all(&[c.x, c.y, c.z].map(|x| (x.clamp(0.0, 1.0) * 255.0).round() as u8))?;
+            w.write_all(&(self.objects[i].0 as i32).to_le_bytes())?;
+            w.write_all(&self.distances[i].to_le_bytes())?;
+            w.write_all(&[self.kinds[i].id()])?;
+        }
+        Ok(())
+    }
+    /// A NumPy `.npy` array of little endian `f32`s, one row of 12 per point:
+    /// x, y, z, nx, ny, nz, r, g, b, object id, signed distance, `SampleKind` id.
+    pub fn write_npy<W: Write>(&self, w: &mut W) -> std::io::Result<()> {
+        let mut header = format!("{{'descr': '<f4', 'fortran_order': False, 'shape': ({}, 12), }}", self.len());
+        // the magic, version and length take 10 bytes, and the data starts 64 byte aligned
+        let padded = (10 + header.len() + 1).next_multiple_of(64) - 10;
+        header.extend(std::iter::repeat_n(' ', padded - header.len() - 1));
+        header.push('\n');
+        w.write_all(b"\x93NUMPY\x01\x00")?;
+        w.write_all(&(header.len() as u16).to_le_bytes())?;
+        w.write_all(header.as_bytes())?;
+        for i in 0..self.len() {
+            let (p, n, c) = (self.positions[i], self.normals[i], self.colors[i]);
+            let row = [p.x, p.y, p.z, n.x, n.y, n.z, c.x, c.y, c.z, self.objects[i].0 as f32, self.distances[i], self.kinds[i].id() as f32];
+            for x in row {
+                w.write_all(&x.to_le_bytes())?;
+            }
+        }
+        Ok(())
+    }
+}
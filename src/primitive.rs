@@ -1,37 +1,46 @@
-#[allow(dead_code, unused)]
-#[path = "vec3.rs"] mod vec3;
+use serde::{Serialize, Deserialize};
+use super::*;
 
-pub use vec3::*;
 
 
 
-
-#[derive(PartialEq, Clone)]
+#[derive(PartialEq, Clone, Serialize, Deserialize)]
 pub enum Primitive {
-    Sphere(f32, Rgb), // Radius
-    Plane(Vec3, f32, Rgb), // Plane Normal, Distance Along Normal
-    Cuboid(Vec3, f32, Rgb), // Dimensions, Rounding Value
-    BoxFrame(Vec3, f32, Rgb), // Dimensions, Edge Thickness
-    Torus(f32, f32, Rgb), // Inner Radius, Outer Radius
-    Horseshoe(f32, f32, f32, f32, Rgb), // Inner Radius, Outer Radius, Cap A, Cap B
-    Link(f32, f32, f32, Rgb), // Length, Radius 1, Radius 2
-    Cone(f32, f32, Rgb), // Angle, Height
-    HexagonalPrism(f32, f32, Rgb), // Radius, Height
-    TriangularPrism(f32, f32, Rgb), // Radius, Height
-    Capsule(f32, f32, Rgb), // Height, Radius
-    CappedCylinder(f32, f32, Rgb), // Height, Radius
-    RoundedCylinder(f32, f32, f32, Rgb), // Radius Max, Radius Min, Height
-    CappedCone(f32, f32, f32, Rgb), // Height, Radius 1, Radius 2
-    SolidAngle(f32, f32, Rgb), // Angle, Rounding
-    CutSphere(f32, f32, Rgb), // Radius, Height
-    CutHollowSphere(f32, f32, f32, Rgb), // Radius, Height, Thickness
-    DeathStar(f32, f32, f32, Rgb), // Radius 1, Radius 2, d
-    RoundCone(f32, f32, f32, Rgb), // Radius 1, Radius 2, Height
-    Ellipsoid(Vec3, Rgb), // Radii
-    Rhombus(f32, f32, f32, f32, Rgb), // la, lb, Height, ra
-    Octahedron(f32, Rgb), // Side length
-    Pyramid(f32, Rgb), // Height
-    Triangle(Point3, Point3, Point3, Rgb), // Point 1, Point 2, Point 3
+    Sphere(f32), // Radius
+    Plane(Vec3, f32), // Plane Normal, Distance Along Normal
+    Cuboid(Vec3, f32), // Dimensions, Rounding Value
+    BoxFrame(Vec3, f32), // Dimensions, Edge Thickness
+    Torus(f32, f32), // Inner Radius, Outer Radius
+    Horseshoe(f32, f32, f32, f32), // Inner Radius, Outer Radius, Cap A, Cap B
+    Link(f32, f32, f32), // Length, Radius 1, Radius 2
+    Cone(f32, f32), // Angle, Height
+    HexagonalPrism(f32, f32), // Radius, Height
+    TriangularPrism(f32, f32), // Radius, Height
+    Capsule(f32, f32), // Height, Radius
+    CappedCylinder(f32, f32), // Height, Radius
+    RoundedCylinder(f32, f32, f32), // Radius Max, Radius Min, Height
+    CappedCone(f32, f32, f32), // Height, Radius 1, Radius 2
+    SolidAngle(f32, f32), // Angle, Rounding
+    CutSphere(f32, f32), // Radius, Height
+    CutHollowSphere(f32, f32, f32), // Radius, Height, Thickness
+    DeathStar(f32, f32, f32), // Radius 1, Radius 2, d
+    RoundCone(f32, f32, f32), // Radius 1, Radius 2, Height
+    Ellipsoid(Vec3), // Radii
+    Rhombus(f32, f32, f32, f32), // la, lb, Height, ra
+    Octahedron(f32), // Side length
+    Pyramid(f32), // Height
+    Triangle(Point3, Point3, Point3), // Point 1, Point 2, Point 3
+    Mandelbulb(f32, u32, f32, MaterialId), // Power, Iterations, Bailout Radius, Orbit Trap Material
+    MengerSponge(f32, u32, MaterialId), // Half Size, Iterations, Orbit Trap Material
+    SierpinskiTetrahedron(f32, u32, MaterialId), // Size, Iterations, Orbit Trap Material
+    JuliaQuaternion(Quat, u32, f32, MaterialId), // Constant, Iterations, Bailout Radius, Orbit Trap Material
+    Extrude(Shape2D, f32, f32), // Shape In XY, Half Depth Along Z, Rounding
+    Revolve(Shape2D, Axis, f32), // Shape (x Away From The Axis, y Along It), Axis, Offset From The Axis
+    Text(Text, f32, f32), // Text, Half Depth Along Z, Bevel
+    Terrain(HeightSource, Vec2, f32), // Heights, Half Size In XZ, Height (Solid Below The Surface)
+    // the GPU stacks every grid along z, so their depths together and each one's width and height
+    // have to fit in MAX_VOXEL_TEXTURE_SIZE
+    VoxelSdf(VoxelGrid), // Distance Grid (Sized By Its Own Half Size)
 }
 
 impl Primitive {
@@ -61,6 +70,15 @@ impl Primitive {
             Self::Octahedron(..) => 22.,
             Self::Pyramid(..) => 23.,
             Self::Triangle(..) => 24.,
+            Self::Mandelbulb(..) => 25.,
+            Self::MengerSponge(..) => 26.,
+            Self::SierpinskiTetrahedron(..) => 27.,
+            Self::JuliaQuaternion(..) => 28.,
+            Self::Extrude(..) => 29.,
+            Self::Revolve(..) => 30.,
+            Self::Text(..) => 31.,
+            Self::Terrain(..) => 32.,
+            Self::VoxelSdf(..) => 33.,
         }
     }
     pub fn as_str(&self) -> String {
@@ -89,130 +107,243 @@ impl Primitive {
             Self::Octahedron(..) => "Octahedron".to_string(),
             Self::Pyramid(..) => "Pyramid".to_string(),
             Self::Triangle(..) => "Triangle".to_string(),
+            Self::Mandelbulb(..) => "Mandelbulb".to_string(),
+            Self::MengerSponge(..) => "Menger Sponge".to_string(),
+            Self::SierpinskiTetrahedron(..) => "Sierpinski Tetrahedron".to_string(),
+            Self::JuliaQuaternion(..) => "Julia Quaternion".to_string(),
+            Self::Extrude(shape, ..) => format!("Extruded {}", shape.as_str()),
+            Self::Revolve(shape, ..) => format!("Revolved {}", shape.as_str()),
+            Self::Text(..) => "Text".to_string(),
+            Self::Terrain(..) => "Terrain".to_string(),
+            Self::VoxelSdf(..) => "Voxel SDF".to_string(),
         }
     }
-    pub fn as_data(&self, op_group: f32) -> [[f32; 4]; 4] {
+    pub fn as_data(&self) -> [[f32; 4]; 4] {
         match &self {
-            Self::Sphere(rad, color) => [
-                [self.id(), *rad, 0.0, op_group],
+            Self::Sphere(rad) => [
+                [self.id(), *rad, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Plane(normal, k, color) => [
-                [self.id(), *k, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Plane(normal, k) => [
+                [self.id(), *k, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [normal.x, normal.y, normal.z, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Cuboid(dims, rounding, color) => [
-                [self.id(), *rounding, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Cuboid(dims, rounding) => [
+                [self.id(), *rounding, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [dims.x, dims.y, dims.z, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::BoxFrame(dims, edge, color) => [
-                [self.id(), *edge, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::BoxFrame(dims, edge) => [
+                [self.id(), *edge, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [dims.x, dims.y, dims.z, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Torus(in_rad, out_rad, color) => [
-                [self.id(), *in_rad, *out_rad, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Torus(in_rad, out_rad) => [
+                [self.id(), *in_rad, *out_rad, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Horseshoe(in_rad, out_rad, capa, capb, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Horseshoe(in_rad, out_rad, capa, capb) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*in_rad, *out_rad, *capa, *capb],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Link(length, rad1, rad2, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Link(length, rad1, rad2) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*length, *rad1, *rad2, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Cone(angle, height, color) => [
-                [self.id(), *angle, *height, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Cone(angle, height) => [
+                [self.id(), *angle, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::HexagonalPrism(radius, height, color) => [
-                [self.id(), *radius, *height, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::HexagonalPrism(radius, height) => [
+                [self.id(), *radius, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::TriangularPrism(radius, height, color) => [
-                [self.id(), *radius, *height, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::TriangularPrism(radius, height) => [
+                [self.id(), *radius, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Capsule(height, radius, color) => [
-                [self.id(), *height, *radius, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Capsule(height, radius) => [
+                [self.id(), *height, *radius, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::CappedCylinder(height, radius, color) => [
-                [self.id(), *height, *radius, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::CappedCylinder(height, radius) => [
+                [self.id(), *height, *radius, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::RoundedCylinder(rad1, rad2, height, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::RoundedCylinder(rad1, rad2, height) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*rad1, *rad2, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::CappedCone(height, rad1, rad2, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::CappedCone(height, rad1, rad2) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*height, *rad1, *rad2, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::SolidAngle(angle, rounding, color) => [
-                [self.id(), *angle, *rounding, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::SolidAngle(angle, rounding) => [
+                [self.id(), *angle, *rounding, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::CutSphere(radius, height, color) => [
-                [self.id(), *radius, *height, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::CutSphere(radius, height) => [
+                [self.id(), *radius, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::CutHollowSphere(radius, height, thickness, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::CutHollowSphere(radius, height, thickness) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*radius, *height, *thickness, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::DeathStar(radius1, radius2, d, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::DeathStar(radius1, radius2, d) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*radius1, *radius2, *d, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::RoundCone(rad1, rad2, height, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::RoundCone(rad1, rad2, height) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*rad1, *rad2, *height, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Ellipsoid(radii, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Ellipsoid(radii) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [radii.x, radii.y, radii.z, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Rhombus(la, lb, height, ra, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Rhombus(la, lb, height, ra) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [*la, *lb, *height, *ra],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Octahedron(side, color) => [
-                [self.id(), *side, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Octahedron(side) => [
+                [self.id(), *side, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Pyramid(height, color) => [
-                [self.id(), *height, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Pyramid(height) => [
+                [self.id(), *height, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
                 [0.0, 0.0, 0.0, 0.0],
-                [color.x, color.y, color.z, 0.0]],
-            Self::Triangle(p1, p2, p3, color) => [
-                [self.id(), 0.0, 0.0, op_group],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Triangle(p1, p2, p3) => [
+                [self.id(), 0.0, 0.0, 0.0],
                 [p1.x, p1.y, p1.z, p3.x],
                 [p2.x, p2.y, p2.z, p3.y],
-                [color.x, color.y, color.z, p3.z]],
+                [0.0, 0.0, 0.0, p3.z]],
+            // the orbit trap material goes next to the object's own material in [3]
+            Self::Mandelbulb(power, iterations, bailout, trap) => [
+                [self.id(), *power, *iterations as f32, *bailout],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, trap.0 as f32, 0.0, 0.0]],
+            Self::MengerSponge(size, iterations, trap) => [
+                [self.id(), *size, *iterations as f32, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, trap.0 as f32, 0.0, 0.0]],
+            Self::SierpinskiTetrahedron(size, iterations, trap) => [
+                [self.id(), *size, *iterations as f32, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, trap.0 as f32, 0.0, 0.0]],
+            Self::JuliaQuaternion(c, iterations, bailout, trap) => [
+                [self.id(), 0.0, *iterations as f32, *bailout],
+                [c.x, c.y, c.z, c.w],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, trap.0 as f32, 0.0, 0.0]],
+            // the shape itself goes in `scene_shapes`, see Scene::get_objects
+            Self::Extrude(_, depth, rounding) => [
+                [self.id(), *depth, *rounding, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Revolve(_, axis, offset) => [
+                [self.id(), axis.as_int() as f32, *offset, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            // an extrusion of the glyph outlines
+            Self::Text(_, depth, bevel) => [
+                [self.id(), *depth, *bevel, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Terrain(source, size, height) => {
+                let step = source.step(*size, *height);
+                match source {
+                    HeightSource::Fbm(octaves, lacunarity, gain, seed, frequency) => [
+                        [self.id(), *height, step, 0.0],
+                        [size.x, size.y, *frequency, 0.0],
+                        [*octaves as f32, *lacunarity, *gain, *seed as f32],
+                        [0.0, 0.0, 0.0, 0.0]],
+                    // the samples go in `scene_heightmaps`, see Scene::get_objects
+                    HeightSource::Image(map) => [
+                        [self.id(), *height, step, 1.0],
+                        [size.x, size.y, 0.0, 0.0],
+                        [map.width() as f32, map.height() as f32, 0.0, 0.0],
+                        [0.0, 0.0, 0.0, 0.0]],
+                }
+            },
+            // the samples go in `scene_voxels`, see Scene::get_objects
+            Self::VoxelSdf(grid) => {
+                let (half, [x, y, z]) = (grid.half_size(), grid.resolution());
+                [[self.id(), half.x, half.y, half.z],
+                 [x as f32, y as f32, z as f32, 0.0],
+                 [0.0, 0.0, 0.0, 0.0],
+                 [0.0, 0.0, 0.0, 0.0]]
+            },
+        }
+    }
+    /// The height image a terrain is drawn from.
+    pub fn heightmap(&self) -> Option<&Heightmap> {
+        match self {
+            Self::Terrain(HeightSource::Image(map), ..) => Some(map),
+            _ => None,
+        }
+    }
+    /// The distance grid a voxel SDF samples.
+    pub fn voxels(&self) -> Option<&VoxelGrid> {
+        match self {
+            Self::VoxelSdf(grid) => Some(grid),
+            _ => None,
+        }
+    }
+    /// The file a heightmap, font or voxel grid is read from, as a scene file writes it.
+    pub(crate) fn asset_path_mut(&mut self) -> Option<&mut String> {
+        match self {
+            Self::Terrain(HeightSource::Image(map), ..) => Some(map.path_mut()),
+            Self::Text(text, ..) => text.font_path_mut(),
+            Self::VoxelSdf(grid) => Some(grid.path_mut()),
+            _ => None,
+        }
+    }
+    /// The 2D shape an extrusion, revolution or text lifts into 3D.
+    pub fn shape(&self) -> Option<&Shape2D> {
+        match self {
+            Self::Extrude(shape, ..) => Some(shape),
+            Self::Revolve(shape, ..) => Some(shape),
+            Self::Text(text, ..) => Some(text.outline()),
+            _ => None,
+        }
+    }
+    /// Material the fractals blend towards by their orbit trap, `None` for everything else.
+    pub fn trap_material(&self) -> Option<MaterialId> {
+        match *self {
+            Self::Mandelbulb(.., trap) => Some(trap),
+            Self::MengerSponge(.., trap) => Some(trap),
+            Self::SierpinskiTetrahedron(.., trap) => Some(trap),
+            Self::JuliaQuaternion(.., trap) => Some(trap),
+            _ => None,
         }
     }
     // pub fn center(&self) -> Point3 {
@@ -223,56 +354,221 @@ impl Primitive {
 }
 
 
-#[derive(Clone, Copy)]
+/// How a boolean op combines its children. Smooth ops blend over a band `k` wide, the
+/// chamfer, stairs and columns unions fill the corner where two surfaces meet, out to `r`,
+/// with a bevel or `n` steps or columns, and `Morph(t)` fades linearly from the first child to the second.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub enum BooleanOpType {
     Union,
     Intersect,
     Subtract,
-    SmoothUnion(f32),
-    SmoothIntersect(f32),
+    SmoothUnion(f32), // k
+    SmoothIntersect(f32), // k
+    SmoothSubtract(f32), // k
+    Chamfer(f32), // r
+    Stairs(f32, f32), // r, n
+    Columns(f32, f32), // r, n
+    Xor,
+    Morph(f32), // t
+}
+
+impl BooleanOpType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Self::Union => "Union",
+            Self::Intersect => "Intersect",
+            Self::Subtract => "Subtract",
+            Self::SmoothUnion(..) => "Smooth Union",
+            Self::SmoothIntersect(..) => "Smooth Intersect",
+            Self::SmoothSubtract(..) => "Smooth Subtract",
+            Self::Chamfer(..) => "Chamfer",
+            Self::Stairs(..) => "Stairs",
+            Self::Columns(..) => "Columns",
+            Self::Xor => "Xor",
+            Self::Morph(..) => "Morph",
+        }
+    }
+    /// One of each operator, with parameters sized for unit sized objects.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::Union,
+            Self::Intersect,
+            Self::Subtract,
+            Self::SmoothUnion(0.3),
+            Self::SmoothIntersect(0.3),
+            Self::SmoothSubtract(0.3),
+            Self::Chamfer(0.2),
+            Self::Stairs(0.3, 4.0),
+            Self::Columns(0.3, 4.0),
+            Self::Xor,
+            Self::Morph(0.5),
+        ]
+    }
+}
+
+
+
+/// Stable handle to an object in a `Scene`. Stays valid while other objects are added or removed.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ObjectId(pub usize);
+
+
+/// A node in a CSG expression tree: either a scene object or a boolean op over further nodes.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum CsgNode {
+    Object(ObjectId),
+    Op(BooleanOp),
+}
+
+impl From<ObjectId> for CsgNode {
+    fn from(obj: ObjectId) -> Self {
+        Self::Object(obj)
+    }
+}
+
+impl From<BooleanOp> for CsgNode {
+    fn from(op: BooleanOp) -> Self {
+        Self::Op(op)
+    }
 }
 
+impl CsgNode {
+    pub fn objects(&self) -> Vec<ObjectId> {
+        match self {
+            Self::Object(obj) => vec![*obj],
+            Self::Op(op) => op.objects(),
+        }
+    }
+    /// Number of stack slots the shader needs to evaluate this node.
+    pub fn depth(&self) -> usize {
+        match self {
+            Self::Object(_) => 1,
+            Self::Op(op) => op.depth(),
+        }
+    }
+    /// Appends this node to `program` in post-order, addressing objects by their position in `ids`.
+    /// `modifiers` holds each object's modifier stack, parallel to `ids`.
+    /// Returns false if the node is empty and emitted nothing.
+    pub fn compile(&self, program: &mut Vec<[f32; 4]>, ids: &[ObjectId], modifiers: &[Vec<Modifier>]) -> bool {
+        match self {
+            Self::Object(obj) => match ids.iter().position(|id| id == obj) {
+                Some(index) => {
+                    program.push([1., index as f32, modifiers[index].len() as f32, 0.0]);
+                    program.extend(modifiers[index].iter().flat_map(|m| m.get_data()));
+                    true
+                },
+                None => false,
+            },
+            Self::Op(op) => op.compile(program, ids, modifiers),
+        }
+    }
+}
 
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BooleanOp {
-    operator: BooleanOpType,
-    pub obj_uids: Vec<usize>,
-    pub uid: usize
+    pub operator: BooleanOpType,
+    pub children: Vec<CsgNode>,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
 }
 
-// * Csgs have indices to two primitives. csgs just dictate how those two act together. 
-// * in the end, there will be a list of csgs that will be passed to the shader
+// * An operator is folded left over its children, so [a, b, c] evaluates as op(op(a, b), c).
+// * Trees are flattened into a post-order program of push-object and apply-operator instructions
+// * that the shader runs with a small stack.
+// * Instructions are one texel, followed by two texels of data per modifier for the ones that
+// * carry a modifier stack:
+// *   [1, object index, modifier count, 0]  push an object, modifiers applied in its own space
+// *   [2, operator, k, n]                   pop two results and push the combined one
+// *   [3, modifier count, 0, 0]             save the point and apply the modifiers to it
+// *   [4, start, modifier count, 0]         apply the distance modifiers of the [3] at `start`, restore the point
 impl BooleanOp {
-    pub fn new(operator: BooleanOpType, objs: Vec<usize>) -> Self {
+    pub fn new(operator: BooleanOpType, children: Vec<CsgNode>) -> Self {
         Self {
-            operator: operator,
-            obj_uids: objs,
-            uid: 0
+            operator,
+            children,
+            modifiers: Vec::new(),
         }
     }
-    pub fn get(&self) -> [f32; 2] {
+    /// Applies `modifiers` to the whole subtree, in world space.
+    pub fn with_modifiers(mut self, modifiers: Vec<Modifier>) -> Self {
+        self.modifiers = modifiers;
+        self
+    }
+    pub fn get(&self) -> [f32; 3] {
         match self.operator {
-            BooleanOpType::Union => [1., 0.0],
-            BooleanOpType::Intersect => [2., 0.0],
-            BooleanOpType::Subtract => [3., 0.0],
-            BooleanOpType::SmoothUnion(k) => [4., k],
-            BooleanOpType::SmoothIntersect(k) => [5., k],
+            BooleanOpType::Union => [1., 0.0, 0.0],
+            BooleanOpType::Intersect => [2., 0.0, 0.0],
+            BooleanOpType::Subtract => [3., 0.0, 0.0],
+            BooleanOpType::SmoothUnion(k) => [4., k, 0.0],
+            BooleanOpType::SmoothIntersect(k) => [5., k, 0.0],
+            BooleanOpType::SmoothSubtract(k) => [6., k, 0.0],
+            BooleanOpType::Chamfer(r) => [7., r, 0.0],
+            BooleanOpType::Stairs(r, n) => [8., r, n],
+            BooleanOpType::Columns(r, n) => [9., r, n],
+            BooleanOpType::Xor => [10., 0.0, 0.0],
+            BooleanOpType::Morph(t) => [11., t, 0.0],
+        }
+    }
+    pub fn objects(&self) -> Vec<ObjectId> {
+        self.children.iter().flat_map(|c| c.objects()).collect()
+    }
+    /// Drops every reference to `obj` from the tree, along with any nested ops left empty.
+    pub fn remove_object(&mut self, obj: ObjectId) {
+        self.children.retain_mut(|c| match c {
+            CsgNode::Object(id) => *id != obj,
+            CsgNode::Op(op) => {
+                op.remove_object(obj);
+                !op.children.is_empty()
+            },
+        });
+    }
+    /// Stack slots needed to evaluate the op. Each level with modifiers counts one extra,
+    /// which also bounds the shader's stack of saved points.
+    pub fn depth(&self) -> usize {
+        let depth = self.children.iter().enumerate().map(|(i, c)| c.depth() + (i > 0) as usize).max().unwrap_or(0);
+        depth + !self.modifiers.is_empty() as usize
+    }
+    pub fn compile(&self, program: &mut Vec<[f32; 4]>, ids: &[ObjectId], modifiers: &[Vec<Modifier>]) -> bool {
+        let op = self.get();
+        let start = program.len();
+        if !self.modifiers.is_empty() {
+            program.push([3., self.modifiers.len() as f32, 0.0, 0.0]);
+            program.extend(self.modifiers.iter().flat_map(|m| m.get_data()));
         }
+        let mut emitted = false;
+        for child in self.children.iter() {
+            if child.compile(program, ids, modifiers) {
+                if emitted {
+                    program.push([2., op[0], op[1], op[2]]);
+                }
+                emitted = true;
+            }
+        }
+        if !emitted {
+            program.truncate(start);
+        } else if !self.modifiers.is_empty() {
+            program.push([4., start as f32, self.modifiers.len() as f32, 0.0]);
+        }
+        emitted
     }
 }
 
 
 
-#[derive(Clone, Copy)]
+/// Object placement. The object is scaled, then rotated, then translated into its parent's
+/// space, or the world for objects without a parent.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
 pub struct Transform {
     pub translate: Vec3,
-    pub rotate: Vec3,
+    pub rotate: Quat,
     pub scale: Vec3,
     // pub shear: Vec3,
 }
 
 impl Transform {
-    pub fn new(translate: Vec3, rotate: Vec3, scale: Vec3) -> Self {
+    pub fn new(translate: Vec3, rotate: Quat, scale: Vec3) -> Self {
         Self {
             translate, 
             rotate, 
@@ -282,11 +578,11 @@ impl Transform {
     pub fn translation(translate: Vec3) -> Self {
         Self {
             translate,
-            rotate: vec3(0., 0., 0.),
+            rotate: Quat::identity(),
             scale: vec3(1., 1., 1.)
         }
     }
-    pub fn rotation(rotate: Vec3) -> Self {
+    pub fn rotation(rotate: Quat) -> Self {
         Self {
             translate: vec3(0., 0., 0.),
             rotate,
@@ -296,11 +592,11 @@ impl Transform {
     pub fn scaler(scale: Vec3) -> Self {
         Self {
             translate: vec3(0., 0., 0.),
-            rotate: vec3(0., 0., 0.),
-            scale: scale
+            rotate: Quat::identity(),
+            scale
         }
     }
-    pub fn transrot(translate: Vec3, rotate: Vec3) -> Self {
+    pub fn transrot(translate: Vec3, rotate: Quat) -> Self {
         Self {
             translate,
             rotate,
@@ -310,11 +606,11 @@ impl Transform {
     pub fn transscale(translate: Vec3, scale: Vec3) -> Self {
         Self {
             translate,
-            rotate: vec3(0., 0., 0.),
+            rotate: Quat::identity(),
             scale
         }
     }
-    pub fn rotscale(rotate: Vec3, scale: Vec3) -> Self {
+    pub fn rotscale(rotate: Quat, scale: Vec3) -> Self {
         Self {
             translate: vec3(0., 0., 0.),
             rotate,
@@ -324,16 +620,60 @@ impl Transform {
     pub fn none() -> Self {
         Self {
             translate: vec3(0., 0., 0.),
-            rotate: vec3(0., 0., 0.),
+            rotate: Quat::identity(),
             scale: vec3(1., 1., 1.)
         }
     }
+    /// Rotation as (yaw, pitch, roll), see `Quat::from_euler`.
+    pub fn euler(&self) -> Vec3 {
+        self.rotate.to_euler()
+    }
+    pub fn set_euler(&mut self, angles: Vec3) {
+        self.rotate = Quat::from_euler(angles);
+    }
+    /// Whether the scale is the same on every axis, as groups need.
+    pub fn has_uniform_scale(&self) -> bool {
+        self.scale.x == self.scale.y && self.scale.y == self.scale.z
+    }
+    /// Places `local`, given relative to this transform, in this transform's parent space.
+    /// Exact only when this transform's scale is uniform: a non-uniform one would shear a
+    /// rotated child, which a `Transform` can't hold.
+    pub fn child(&self, local: &Transform) -> Self {
+        Self {
+            translate: self.translate + self.rotate.rotate(self.scale * local.translate),
+            rotate: (self.rotate * local.rotate).normalize(),
+            scale: self.scale * local.scale,
+        }
+    }
+    /// Inverse of `child`: the local transform that `child` maps onto `world`.
+    pub fn relative(&self, world: &Transform) -> Self {
+        let inverse = self.rotate.conjugate();
+        Self {
+            translate: inverse.rotate(world.translate - self.translate) / self.scale,
+            rotate: (inverse * world.rotate).normalize(),
+            scale: world.scale / self.scale,
+        }
+    }
+    /// Factor from object space to world space distances. Exact for uniform scale and a
+    /// lower bound for non-uniform scale, which keeps marching from overshooting.
+    pub fn distance_scale(&self) -> f32 {
+        self.scale.x.min(self.scale.y.min(self.scale.z))
+    }
+    /// World to object space matrix as four GLSL columns. The last row isn't needed for an
+    /// affine transform, so `[3][3]` holds `distance_scale` instead.
     pub fn get_data(&self) -> [[f32; 4]; 4] {
+        let r = self.rotate.to_mat3();
+        let s = self.scale;
+        // inverse rotation is the transpose, then undo the scale per row
+        let col = |j: usize| [r[0][j] / s.x, r[1][j] / s.y, r[2][j] / s.z];
+        let (c0, c1, c2) = (col(0), col(1), col(2));
+        let t = self.translate;
+        let offset = |i: usize| -(c0[i] * t.x + c1[i] * t.y + c2[i] * t.z);
         [
-            [self.translate.x, self.translate.y, self.translate.z, 0.0],
-            [self.rotate.x, self.rotate.y, self.rotate.z, 0.0],
-            [self.scale.x, self.scale.y, self.scale.z, 0.0],
-            [0.0, 0.0, 0.0, 0.0],
+            [c0[0], c0[1], c0[2], 0.0],
+            [c1[0], c1[1], c1[2], 0.0],
+            [c2[0], c2[1], c2[2], 0.0],
+            [offset(0), offset(1), offset(2), self.distance_scale()],
         ]
     }
-}
\ No newline at end of file
+}
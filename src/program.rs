@@ -1,21 +1,257 @@
+use glium::texture::buffer_texture::{BufferTexture, BufferTextureType};
+use glium::Surface;
+use glium::backend::Facade;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+use super::*;
+
+// how often a `ShaderWatcher` looks at the files' modification times
+const SHADER_POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+
 #[derive(Copy, Clone)]
-pub struct Vertex {
+pub(crate) struct Vertex {
     position: [f32; 2],
 }
 implement_vertex!(Vertex, position);
 
 
-pub fn load_program(display: &glium::Display) -> (glium::VertexBuffer<Vertex>, glium::index::NoIndices, glium::program::Program) {
-    let vertex_buffer = glium::VertexBuffer::new(display, &[
-        Vertex { position: [-1.0, -1.0] },
-        Vertex { position: [3.0, -1.0] },
-        Vertex { position: [-1.0, 3.0] }
-    ]).unwrap();
-    let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+/// Draws scenes with the raymarching shaders, as a single triangle covering the target.
+pub struct GpuRenderer {
+    vertex_buffer: glium::VertexBuffer<Vertex>,
+    indices: glium::index::NoIndices,
+    program: glium::Program,
+    // the grids in the last scene drawn and their texture, uploaded again only when they change
+    voxels: Option<(Vec<VoxelGrid>, glium::texture::Texture3d)>,
+}
+
+impl GpuRenderer {
+    /// A renderer with the shaders built into the crate.
+    pub fn new<F: Facade>(display: &F) -> Result<Self, ShaderError> {
+        Self::with_shaders(display, include_str!("shaders/vertex.glsl"), include_str!("shaders/fragment.glsl"))
+    }
+    /// A renderer with the given shader sources, e.g. from a `ShaderWatcher`.
+    pub fn with_shaders<F: Facade>(display: &F, vertex_shader_src: &str, fragment_shader_src: &str) -> Result<Self, ShaderError> {
+        let vertex_buffer = glium::VertexBuffer::new(display, &[
+            Vertex { position: [-1.0, -1.0] },
+            Vertex { position: [3.0, -1.0] },
+            Vertex { position: [-1.0, 3.0] }
+        ]).map_err(|e| ShaderError::Other(e.to_string()))?;
+        let indices = glium::index::NoIndices(glium::index::PrimitiveType::TriangleStrip);
+        let program = compile(display, vertex_shader_src, fragment_shader_src)?;
+        Ok(Self { vertex_buffer, indices, program, voxels: None })
+    }
+    /// Swaps in a program built from the given shader sources. If they don't compile or link,
+    /// the current program stays in use.
+    pub fn reload<F: Facade>(&mut self, display: &F, vertex_shader_src: &str, fragment_shader_src: &str) -> Result<(), ShaderError> {
+        self.program = compile(display, vertex_shader_src, fragment_shader_src)?;
+        Ok(())
+    }
+    /// Uploads `scene` and draws it over all of `target`, looking through the scene's camera.
+    /// `time` and `mouse` (x, y in pixels, then left and right button state) are passed through
+    /// to the shader.
+    pub fn draw<F: Facade, S: Surface>(&mut self, display: &F, target: &mut S, scene: &Scene, time: f32, mouse: [f32; 4]) -> Result<(), DrawError> {
+        let (width, height) = target.get_dimensions();
+        let buffer_objects = load_buffer(display, &scene.get_objects())?;
+        let buffer_lights = load_buffer(display, &scene.get_lights())?;
+        let csg = scene.csg_program();
+        let buffer_csg = load_buffer(display, csg.instructions())?;
+        let buffer_transforms = load_buffer(display, &scene.get_transformations())?;
+        let buffer_materials = load_buffer(display, &scene.get_materials())?;
+        let buffer_shapes = load_buffer(display, &scene.get_shapes())?;
+        let buffer_heightmaps = load_buffer(display, &scene.get_heightmaps())?;
+        let grids: Vec<&VoxelGrid> = scene.objects().iter().filter_map(|obj| obj.voxels()).collect();
+        check_voxel_stack(scene)?;
+        if !self.voxels.as_ref().is_some_and(|(uploaded, _)| uploaded.iter().eq(grids.iter().copied())) {
+            let texture = glium::texture::Texture3d::with_format(display, scene.get_voxels(),
+                glium::texture::UncompressedFloatFormat::F32, glium::texture::MipmapsOption::NoMipmap)
+                .map_err(|e| DrawError::Upload(format!("voxel grids: {:?}", e)))?;
+            self.voxels = Some((grids.into_iter().cloned().collect(), texture));
+        }
+        let texture_voxels = &self.voxels.as_ref().unwrap().1;
+        let upload = |e: glium::buffer::BufferCreationError| DrawError::Upload(format!("uniform block: {:?}", e));
+        let scene_fog_color = glium::uniforms::UniformBuffer::new(display, SceneFogColorBlock {
+            fog_color: scene.get_fog_color(),
+        }).map_err(upload)?;
+        let scene_params = glium::uniforms::UniformBuffer::new(display, SceneParamsBlock {
+            params: scene.get_params(),
+        }).map_err(upload)?;
+        let scene_consts = glium::uniforms::UniformBuffer::new(display, SceneConstsBlock {
+            consts: scene.get_consts(&csg),
+        }).map_err(upload)?;
+        target.draw(&self.vertex_buffer, self.indices, &self.program, &uniform! {
+            time: time,
+            resolution: [width as f32, height as f32],
+            mouse: mouse,
+
+            camera: scene.camera.as_data(),
+            camera_origin: scene.camera.origin.to_tuple(),
+            camera_focal_length: scene.camera.focal_length,
+
+            scene_fog_color: &scene_fog_color,
+            scene_params: &scene_params,
+            scene_consts: &scene_consts,
+
+            scene_objects: &buffer_objects,
+            scene_lights: &buffer_lights,
+            scene_csg: &buffer_csg,
+            scene_transforms: &buffer_transforms,
+            scene_materials: &buffer_materials,
+            scene_shapes: &buffer_shapes,
+            scene_heightmaps: &buffer_heightmaps,
+            scene_voxels: texture_voxels.sampled()
+                .magnify_filter(glium::uniforms::MagnifySamplerFilter::Linear)
+                .minify_filter(glium::uniforms::MinifySamplerFilter::Linear)
+                .wrap_function(glium::uniforms::SamplerWrapFunction::Clamp),
+        }, &Default::default()).map_err(DrawError::Draw)
+    }
+}
+
+fn compile<F: Facade>(display: &F, vertex_shader_src: &str, fragment_shader_src: &str) -> Result<glium::Program, ShaderError> {
+    use glium::program::{ProgramCreationError, ShaderType};
+    glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None).map_err(|e| match e {
+        ProgramCreationError::CompilationError(log, ShaderType::Vertex) => ShaderError::Compile("vertex", annotate_shader_log(&log, vertex_shader_src)),
+        ProgramCreationError::CompilationError(log, ShaderType::Fragment) => ShaderError::Compile("fragment", annotate_shader_log(&log, fragment_shader_src)),
+        ProgramCreationError::LinkingError(log) => ShaderError::Link(log),
+        e => ShaderError::Other(e.to_string()),
+    })
+}
+
+/// Why a scene couldn't be drawn.
+#[derive(Debug)]
+pub enum DrawError {
+    /// Scene data didn't fit in the buffers or textures the shader reads it from.
+    Upload(String),
+    Draw(glium::DrawError),
+}
+
+impl fmt::Display for DrawError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Upload(e) => write!(f, "failed to upload {}", e),
+            Self::Draw(e) => write!(f, "{}", e),
+        }
+    }
+}
 
-    let vertex_shader_src = include_str!("shaders/vertex.glsl");
-    let fragment_shader_src = include_str!("shaders/fragment.glsl");
+impl std::error::Error for DrawError {}
 
-    let program = glium::Program::from_source(display, vertex_shader_src, fragment_shader_src, None).unwrap();
-    return (vertex_buffer, indices, program);
-}
\ No newline at end of file
+/// Why shaders couldn't be built.
+#[derive(Debug)]
+pub enum ShaderError {
+    Io(PathBuf, std::io::Error),
+    /// The shader stage and its compile log, as given by `annotate_shader_log`.
+    Compile(&'static str, String),
+    Link(String),
+    Other(String),
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(path, e) => write!(f, "failed to read {}: {}", path.display(), e),
+            Self::Compile(stage, log) => write!(f, "{} shader failed to compile:\n{}", stage, log),
+            Self::Link(log) => write!(f, "shaders failed to link:\n{}", log),
+            Self::Other(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ShaderError {}
+
+/// Follows each message of a GLSL compile log that names a line of `source` with that line
+/// and the one before it, since drivers often report a missing `;` on the next line.
+/// Understands Mesa's `0:12(5):`, NVIDIA's `0(12) :` and the `ERROR: 0:12:` of most others.
+pub fn annotate_shader_log(log: &str, source: &str) -> String {
+    let lines: Vec<&str> = source.lines().collect();
+    let mut annotated = String::new();
+    for message in log.lines().filter(|line| !line.trim().is_empty()) {
+        annotated += message.trim_end();
+        annotated.push('\n');
+        if let Some(n) = log_line_number(message).filter(|n| (1..=lines.len()).contains(n)) {
+            for i in n.saturating_sub(1).max(1)..=n {
+                annotated += &format!("{} {:>5} | {}\n", if i == n { ">" } else { " " }, i, lines[i - 1].trim_end());
+            }
+        }
+    }
+    annotated
+}
+
+/// The line number in a compile log message: the digits after the first `:` or `(` that
+/// follows the source string number.
+fn log_line_number(message: &str) -> Option<usize> {
+    let bytes = message.as_bytes();
+    for i in 1..bytes.len() {
+        if (bytes[i] == b':' || bytes[i] == b'(') && bytes[i - 1].is_ascii_digit() {
+            let digits: String = message[i + 1..].chars().take_while(|c| c.is_ascii_digit()).collect();
+            if let Ok(n) = digits.parse() {
+                return Some(n);
+            }
+        }
+    }
+    None
+}
+
+/// Watches the `.glsl` files in a directory, so shaders can be edited while the viewer runs.
+pub struct ShaderWatcher {
+    dir: PathBuf,
+    modified: Vec<(PathBuf, SystemTime)>,
+    last_poll: Option<Instant>,
+}
+
+impl ShaderWatcher {
+    pub fn new<P: AsRef<Path>>(dir: P) -> Self {
+        Self { dir: dir.as_ref().to_path_buf(), modified: Vec::new(), last_poll: None }
+    }
+    /// Whether any of the shaders was added, removed or modified since the last call. Always
+    /// true on the first call, and never true more than every `SHADER_POLL_INTERVAL`.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.is_some_and(|last| last.elapsed() < SHADER_POLL_INTERVAL) {
+            return false;
+        }
+        let first = self.last_poll.is_none();
+        self.last_poll = Some(Instant::now());
+        let mut modified: Vec<(PathBuf, SystemTime)> = std::fs::read_dir(&self.dir).into_iter().flatten().flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|e| e == "glsl"))
+            .filter_map(|path| Some((path.clone(), path.metadata().ok()?.modified().ok()?)))
+            .collect();
+        modified.sort();
+        let changed = first || modified != self.modified;
+        self.modified = modified;
+        changed
+    }
+    /// The vertex and fragment shader sources.
+    pub fn read(&self) -> Result<(String, String), ShaderError> {
+        let read = |name: &str| {
+            let path = self.dir.join(name);
+            std::fs::read_to_string(&path).map_err(|e| ShaderError::Io(path, e))
+        };
+        Ok((read("vertex.glsl")?, read("fragment.glsl")?))
+    }
+}
+
+// the grids `Scene::get_voxels` stacks have to fit in the 3D texture, and which one didn't
+// is more use than the driver's error
+fn check_voxel_stack(scene: &Scene) -> Result<(), DrawError> {
+    let mut depth = 0;
+    for (i, grid) in scene.objects().iter().enumerate().filter_map(|(i, obj)| Some((i, obj.voxels()?))) {
+        let [w, h, d] = grid.resolution();
+        depth += d;
+        if w.max(h) > MAX_VOXEL_TEXTURE_SIZE || depth > MAX_VOXEL_TEXTURE_SIZE {
+            return Err(DrawError::Upload(format!(
+                "voxel grids: object {}'s {}x{}x{} grid takes them past {} samples along an axis, stacked along z",
+                scene.ids()[i].0, w, h, d, MAX_VOXEL_TEXTURE_SIZE)));
+        }
+    }
+    Ok(())
+}
+
+/// Uploads scene data as an RGBA32F buffer texture, read in the shader with `texelFetch`.
+pub(crate) fn load_buffer<F: Facade>(display: &F, data: &[[f32; 4]]) -> Result<BufferTexture<[f32; 4]>, DrawError> {
+    // empty buffers can't be bound, so an empty list still gets one (unread) texel
+    let data = if data.is_empty() { &[[0.0; 4]][..] } else { data };
+    BufferTexture::new(display, data, BufferTextureType::Float)
+        .map_err(|e| DrawError::Upload(format!("{} texels of scene data: {:?}", data.len(), e)))
+}
@@ -0,0 +1,102 @@
+use std::ops::Mul;
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// A rotation quaternion. Serialized as `(x, y, z, w)`.
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "[f32; 4]", into = "[f32; 4]")]
+pub struct Quat {
+    pub x: f32,
+    pub y: f32,
+    pub z: f32,
+    pub w: f32,
+}
+
+impl Mul for Quat {
+    type Output = Self;
+
+    fn mul(self, o: Self) -> Self {
+        Self {
+            x: self.w * o.x + self.x * o.w + self.y * o.z - self.z * o.y,
+            y: self.w * o.y - self.x * o.z + self.y * o.w + self.z * o.x,
+            z: self.w * o.z + self.x * o.y - self.y * o.x + self.z * o.w,
+            w: self.w * o.w - self.x * o.x - self.y * o.y - self.z * o.z,
+        }
+    }
+}
+
+impl From<[f32; 4]> for Quat {
+    fn from(q: [f32; 4]) -> Self {
+        Self::new(q[0], q[1], q[2], q[3])
+    }
+}
+
+impl From<Quat> for [f32; 4] {
+    fn from(q: Quat) -> Self {
+        [q.x, q.y, q.z, q.w]
+    }
+}
+
+impl Quat {
+    pub fn new(x: f32, y: f32, z: f32, w: f32) -> Self {
+        Self {
+            x,
+            y,
+            z,
+            w,
+        }
+    }
+    pub fn identity() -> Self {
+        Self::new(0.0, 0.0, 0.0, 1.0)
+    }
+    pub fn from_axis_angle(axis: Vec3, angle: f32) -> Self {
+        let axis = axis.normalize();
+        let (s, c) = (angle * 0.5).sin_cos();
+        Self::new(axis.x * s, axis.y * s, axis.z * s, c)
+    }
+    /// Rotation by `yaw` about Z, then `pitch` about Y, then `roll` about X, all about the
+    /// world axes. Angles are in radians and given as (yaw, pitch, roll).
+    pub fn from_euler(angles: Vec3) -> Self {
+        let yaw = Self::from_axis_angle(vec3(0., 0., 1.), angles.x);
+        let pitch = Self::from_axis_angle(vec3(0., 1., 0.), angles.y);
+        let roll = Self::from_axis_angle(vec3(1., 0., 0.), angles.z);
+        roll * pitch * yaw
+    }
+    /// Inverse of `from_euler`. Pitch is kept within +-90 degrees, and at exactly +-90
+    /// degrees (gimbal lock) the whole rotation about the vertical is reported as yaw.
+    pub fn to_euler(self) -> Vec3 {
+        let m = self.to_mat3();
+        let pitch = m[2][0].clamp(-1.0, 1.0).asin();
+        if m[2][0].abs() < 0.9999 {
+            vec3((-m[1][0]).atan2(m[0][0]), pitch, (-m[2][1]).atan2(m[2][2]))
+        } else {
+            vec3(m[0][1].atan2(m[1][1]), pitch, 0.0)
+        }
+    }
+    pub fn length(&self) -> f32 {
+        (self.x * self.x + self.y * self.y + self.z * self.z + self.w * self.w).sqrt()
+    }
+    pub fn normalize(&self) -> Self {
+        let l = self.length();
+        Self::new(self.x / l, self.y / l, self.z / l, self.w / l)
+    }
+    pub fn conjugate(&self) -> Self {
+        Self::new(-self.x, -self.y, -self.z, self.w)
+    }
+    pub fn rotate(&self, v: Vec3) -> Vec3 {
+        let u = vec3(self.x, self.y, self.z);
+        let t = u.cross(v) * 2.0;
+        v + t * self.w + u.cross(t)
+    }
+    /// Rotation matrix as three columns.
+    pub fn to_mat3(self) -> [[f32; 3]; 3] {
+        let Self { x, y, z, w } = self.normalize();
+        [
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y + w * z), 2.0 * (x * z - w * y)],
+            [2.0 * (x * y - w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z + w * x)],
+            [2.0 * (x * z + w * y), 2.0 * (y * z - w * x), 1.0 - 2.0 * (x * x + y * y)],
+        ]
+    }
+}
@@ -0,0 +1,328 @@
+use std::path::Path;
+use super::*;
+
+
+
+const MAX_STEPS: usize = 128;
+const MAX_DIST: f32 = 100.0;
+const MIN_DIST: f32 = 0.0001;
+
+/// An image of colours in the 0 to 1 range, stored row by row from the top.
+#[derive(Clone)]
+pub struct Image {
+    pub width: u32,
+    pub height: u32,
+    pub pixels: Vec<Rgb>,
+}
+
+impl Image {
+    pub fn new(width: u32, height: u32) -> Self {
+        Self {
+            width,
+            height,
+            pixels: vec![rgb(0.0, 0.0, 0.0); width as usize * height as usize],
+        }
+    }
+    pub fn get(&self, x: u32, y: u32) -> Rgb {
+        self.pixels[y as usize * self.width as usize + x as usize]
+    }
+    /// 8 bit RGB bytes, quantized the way the framebuffer does.
+    pub fn to_rgb8(&self) -> Vec<u8> {
+        self.pixels.iter()
+            .flat_map(|c| [c.x, c.y, c.z])
+            .map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8)
+            .collect()
+    }
+    pub fn save_png<P: AsRef<Path>>(&self, path: P) -> std::io::Result<()> {
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, self.width, self.height);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&self.to_rgb8())?;
+        Ok(())
+    }
+    /// Loads an 8 bit RGB or RGBA PNG, dropping any alpha.
+    pub fn load_png<P: AsRef<Path>>(path: P) -> std::io::Result<Self> {
+        let decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(path)?));
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let channels = match (info.color_type, info.bit_depth) {
+            (png::ColorType::Rgb, png::BitDepth::Eight) => 3,
+            (png::ColorType::Rgba, png::BitDepth::Eight) => 4,
+            _ => return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, "expected an 8 bit RGB or RGBA image")),
+        };
+        let pixels = buf[..info.buffer_size()].chunks(channels)
+            .map(|c| rgb(c[0] as f32 / 255.0, c[1] as f32 / 255.0, c[2] as f32 / 255.0))
+            .collect();
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            pixels,
+        })
+    }
+}
+
+
+/// An object hit by `Renderer::pick`.
+#[derive(Clone, Copy, Debug)]
+pub struct Pick {
+    pub object: ObjectId,
+    pub point: Point3,
+    pub normal: Vec3,
+    pub distance: f32,
+}
+
+
+// * CPU version of the fragment shader pipeline, from march through lighting, fog and gamma.
+// * Functions mirror the GLSL functions of the same name in fragment.glsl.
+
+/// Renders a scene on the CPU as seen from `camera`, matching what the viewer draws.
+pub struct Renderer<'a> {
+    scene: &'a Scene,
+    camera: &'a Camera,
+    program: CsgProgram,
+    lights: Vec<[f32; 4]>,
+    params: [f32; 4],
+    fog_color: Rgb,
+}
+
+fn step(edge: f32, x: f32) -> f32 {
+    if x < edge { 0.0 } else { 1.0 }
+}
+
+fn smoothstep(e0: f32, e1: f32, x: f32) -> f32 {
+    let t = ((x - e0) / (e1 - e0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn pow3(v: Vec3, e: f32) -> Vec3 {
+    vec3(v.x.powf(e), v.y.powf(e), v.z.powf(e))
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(scene: &'a Scene, camera: &'a Camera) -> Self {
+        Self {
+            scene,
+            camera,
+            program: scene.csg_program(),
+            lights: scene.get_lights(),
+            params: scene.get_params(),
+            fog_color: scene.fog_color,
+        }
+    }
+    /// Renders a `width` by `height` image, splitting rows across all available cores.
+    pub fn render(&self, width: u32, height: u32) -> Image {
+        let mut image = Image::new(width, height);
+        let threads = std::thread::available_parallelism().map_or(1, |n| n.get());
+        let rows_per_thread = (height as usize).div_ceil(threads).max(1);
+        std::thread::scope(|s| {
+            for (i, rows) in image.pixels.chunks_mut(rows_per_thread * width as usize).enumerate() {
+                s.spawn(move || {
+                    for (j, pixel) in rows.iter_mut().enumerate() {
+                        let x = (j % width as usize) as u32;
+                        let y = (i * rows_per_thread + j / width as usize) as u32;
+                        *pixel = self.pixel(x, y, width, height);
+                    }
+                });
+            }
+        });
+        image
+    }
+    /// Colour of pixel (`x`, `y`), counted from the top left, mirroring `main`.
+    pub fn pixel(&self, x: u32, y: u32, width: u32, height: u32) -> Rgb {
+        let rd = self.camera.screen_ray(x as f32 + 0.5, y as f32 + 0.5, width as f32, height as f32);
+        self.render_ray(rd)
+    }
+    /// Finds the object under the point (`x`, `y`) of a `width` by `height` viewport.
+    pub fn pick(&self, x: f32, y: f32, width: f32, height: f32) -> Option<Pick> {
+        let rd = self.camera.screen_ray(x, y, width, height);
+        let (distance, _) = self.march(self.camera.origin, rd)?;
+        let point = self.camera.origin + rd * distance;
+        // the hit surface belongs to whichever object's own surface is closest
+        let distances = self.scene.object_distances(&self.program, point);
+        let index = (0..distances.len()).min_by(|a, b| distances[*a].total_cmp(&distances[*b]))?;
+        Some(Pick {
+            object: self.scene.ids()[index],
+            point,
+            normal: self.get_normal(point),
+            distance,
+        })
+    }
+
+    fn scene_sd(&self, p: Point3) -> Option<SdfResult> {
+        self.scene.evaluate_program(&self.program, p)
+    }
+    fn scene_dist(&self, p: Point3) -> f32 {
+        self.scene_sd(p).map_or(1e20, |res| res.distance)
+    }
+
+    /// Distance along the ray to the first hit and the surface there, or `None` on a miss.
+    pub fn march(&self, origin: Point3, direction: Vec3) -> Option<(f32, SdfResult)> {
+        let mut dist = 0.0;
+        for _ in 0..MAX_STEPS {
+            let p = origin + direction * dist;
+            let ds = self.scene_sd(p)?;
+            if ds.distance.abs() < MIN_DIST * dist {
+                return Some((dist, ds));
+            }
+            dist += ds.distance;
+            if dist > MAX_DIST {
+                break;
+            }
+        }
+        None
+    }
+
+    fn get_soft_shadow(&self, ro: Point3, rd: Vec3, tmin: f32, tmax: f32, w: f32) -> f32 {
+        let mut res: f32 = 1.0;
+        let mut t = tmin;
+        for _ in 0..24 {
+            let h = self.scene_dist(ro + rd * t);
+            let s = (w * h / t).clamp(0.0, 1.0);
+            res = res.min(s);
+            t += h.clamp(0.01, 0.2);
+            if res < 0.004 || t > tmax {
+                break;
+            }
+        }
+        let res = res.clamp(0.0, 1.0);
+        res * res * (3.0 - 2.0 * res)
+    }
+
+    fn get_ambient_occlusion(&self, p: Point3, norm: Vec3) -> f32 {
+        let mut occ = 0.0;
+        let mut sca = 1.0;
+        for i in 0..5 {
+            let h = 0.01 + 0.12 * i as f32 / 4.0;
+            let d = self.scene_dist(p + norm * h);
+            occ += (h - d) * sca;
+            sca *= 0.95;
+            if occ > 0.35 {
+                break;
+            }
+        }
+        (1.0 - 3.0 * occ).clamp(0.0, 1.0) * (0.5 + 0.5 * norm.y)
+    }
+
+    pub fn get_normal(&self, p: Point3) -> Vec3 {
+        let mut n = vec3(0.0, 0.0, 0.0);
+        for i in 0..4 {
+            let e = (vec3((((i + 3) >> 1) & 1) as f32, ((i >> 1) & 1) as f32, (i & 1) as f32) * 2.0 - 1.0) * 0.5773;
+            n = n + e * self.scene_dist(p + e * 0.0005);
+        }
+        n.normalize()
+    }
+
+    fn get_light(&self, p: Point3, rd: Vec3, normal: Vec3, material: &Material) -> Rgb {
+        let occ = if self.params[2] != 0.0 { self.get_ambient_occlusion(p, normal) } else { 1.0 };
+        let mut total_light = material.albedo * rgb(0.03, 0.04, 0.1);
+        let shininess = (10.0 * (1.0 - material.roughness)).exp2();
+        let f0 = rgb(0.04, 0.04, 0.04).lerp(material.albedo, material.metallic);
+        for light in self.lights.chunks(4) {
+            let (position, direction, color, cones) = (light[0], light[1], light[2], light[3]);
+            let kind = position[3] as i32;
+
+            let l;
+            let light_dist;
+            let mut attenuation = 1.0;
+            if kind == 1 {
+                l = -vec3(direction[0], direction[1], direction[2]);
+                light_dist = MAX_DIST;
+            } else {
+                let to_light = vec3(position[0], position[1], position[2]) - p;
+                light_dist = to_light.length();
+                l = to_light / light_dist;
+                if direction[3] > 0.0 {
+                    let falloff = (1.0 - light_dist / direction[3]).clamp(0.0, 1.0);
+                    attenuation = falloff * falloff;
+                }
+                if kind == 2 {
+                    let cos_angle = (-l).dot(vec3(direction[0], direction[1], direction[2]));
+                    // equal angles leave no edge to blend across
+                    attenuation *= if cones[0] > cones[1] { smoothstep(cones[1], cones[0], cos_angle) } else { step(cones[1], cos_angle) };
+                }
+            }
+            if attenuation <= 0.0 {
+                continue;
+            }
+            let h = (l - rd).normalize();
+
+            let mut dif = normal.dot(l).clamp(0.0, 1.0);
+            dif *= occ;
+            if self.params[1] != 0.0 && dif > 0.0 {
+                let hardness = if kind == 3 { light_dist / color[3].max(0.001) } else { self.params[0] };
+                dif *= self.get_soft_shadow(p, l, 0.02, light_dist.min(5.0), hardness);
+            }
+            let radiance = rgb(color[0], color[1], color[2]) * attenuation;
+
+            let spec = normal.dot(h).clamp(0.0, 1.0).powf(shininess) * dif;
+            let fresnel = f0 + (-f0 + 1.0) * (1.0 - h.dot(l)).clamp(0.0, 1.0).powf(5.0);
+            total_light = total_light + material.albedo * radiance * (dif * (1.0 - material.metallic));
+            total_light = total_light + fresnel * radiance * (5.00 * material.specular * spec);
+        }
+        total_light + material.emissive
+    }
+
+    fn get_background(&self, rd: Vec3) -> Rgb {
+        self.fog_color - rd.y.max(0.0) * 0.4
+    }
+
+    // the shaded colour and the surface's material, None on a miss
+    fn shade(&self, origin: Point3, rd: Vec3, res: Option<(f32, SdfResult)>) -> (Rgb, Option<Material>) {
+        let (dist, surface) = match res {
+            Some(res) => res,
+            None => return (self.get_background(rd), None),
+        };
+        let pos = origin + rd * dist;
+        let normal = self.get_normal(pos);
+        let material = surface.material(&self.scene.materials);
+        let color = self.get_light(pos, rd, normal, &material);
+
+        let fog_start = 4.0;
+        let fog_thickness = 8.0;
+        let fog_strength = 1.0 / (1.0 + (-(pos.z / fog_thickness) + fog_start).exp());
+        (color.lerp(self.fog_color, fog_strength), Some(material))
+    }
+
+    fn march_through(&self, pos: Point3, rd: Vec3) -> Point3 {
+        let mut dist = 0.01;
+        for _ in 0..MAX_STEPS / 2 {
+            let d = -self.scene_dist(pos + rd * dist);
+            if d < MIN_DIST {
+                break;
+            }
+            dist += d.max(0.01);
+        }
+        pos + rd * (dist + 0.01)
+    }
+
+    /// Final colour for a ray from the camera, mirroring `render`.
+    pub fn render_ray(&self, rd: Vec3) -> Rgb {
+        let origin = self.camera.origin;
+        let res = self.march(origin, rd);
+        let (mut color, material) = self.shade(origin, rd, res);
+
+        if let (Some(material), Some((dist, _))) = (material, res) {
+            if material.opacity < 1.0 {
+                let behind = self.march_through(origin + rd * dist, rd);
+                let (behind_color, _) = self.shade(behind, rd, self.march(behind, rd));
+                color = behind_color.lerp(color, material.opacity);
+            }
+        }
+
+        color = pow3(color, 0.4545);
+        color.clamp(0.0, 1.0)
+    }
+}
+
+impl Scene {
+    /// Renders the scene from its own camera on the CPU.
+    pub fn render(&self, width: u32, height: u32) -> Image {
+        Renderer::new(self, &self.camera).render(width, height)
+    }
+    /// Finds the object under the point (`x`, `y`) of a `width` by `height` viewport, looking through the scene's camera.
+    pub fn pick(&self, x: f32, y: f32, width: f32, height: f32) -> Option<Pick> {
+        Renderer::new(self, &self.camera).pick(x, y, width, height)
+    }
+}
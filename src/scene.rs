@@ -1,116 +1,348 @@
-#[path = "camera.rs"] mod camera;
-pub use camera::*;
+use serde::{Serialize, Deserialize};
+use super::*;
 
 
 
+#[derive(Clone)]
 pub struct Scene {
-    pub objects: Vec<Primitive>,
-    pub obj_boolops: Vec<usize>,
-    pub obj_transforms: Vec<Transform>,
-    pub lights: Vec<Vec3>,
-    pub bool_ops: Vec<BooleanOp>,
+    pub(crate) objects: Vec<Primitive>,
+    pub(crate) obj_transforms: Vec<Transform>,
+    pub(crate) obj_materials: Vec<MaterialId>,
+    pub(crate) obj_parents: Vec<Option<GroupId>>,
+    pub(crate) obj_modifiers: Vec<Vec<Modifier>>,
+    pub(crate) obj_ids: Vec<ObjectId>,
+    pub(crate) groups: Vec<Group>,
+    pub(crate) group_ids: Vec<GroupId>,
+    pub materials: Vec<Material>,
+    pub lights: Vec<Light>,
+    pub(crate) bool_ops: Vec<BooleanOp>,
     pub camera: Camera,
-    uid_counter: isize,
+    pub fog_color: Rgb,
+    pub params: SceneParams,
+    pub(crate) uid_counter: usize,
+}
+
+impl Default for Scene {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Scene {
     pub fn new() -> Self {
         Self {
-            objects: Vec::with_capacity(MAX_OBJECTS),
-            obj_boolops: Vec::with_capacity(MAX_OBJECTS),
-            obj_transforms: Vec::with_capacity(MAX_OBJECTS),
-            lights: Vec::with_capacity(MAX_LIGHTS),
-            bool_ops: Vec::with_capacity(MAX_BOOL_OPS),
+            objects: Vec::new(),
+            obj_transforms: Vec::new(),
+            obj_materials: Vec::new(),
+            obj_parents: Vec::new(),
+            obj_modifiers: Vec::new(),
+            obj_ids: Vec::new(),
+            groups: Vec::new(),
+            group_ids: Vec::new(),
+            materials: Vec::new(),
+            lights: Vec::new(),
+            bool_ops: Vec::new(),
             camera: Camera::new(Vec3::new(0., 0., 0.), Vec3::new(1., 0., 0.), 0.0, 2.5),
+            fog_color: rgb(0.30, 0.36, 0.60),
+            params: SceneParams::default(),
             uid_counter: 0,
         }
     }
-    pub fn add(&mut self, object: Primitive, transform: Transform) -> usize {
+    pub fn add(&mut self, object: Primitive, transform: Transform, material: MaterialId) -> ObjectId {
+        let id = ObjectId(self.uid_counter);
+        self.add_with_id(id, object, transform, material, None);
+        id
+    }
+    pub(crate) fn add_with_id(&mut self, id: ObjectId, object: Primitive, transform: Transform, material: MaterialId, parent: Option<GroupId>) {
         self.objects.push(object);
-        self.obj_boolops.push(0);
         self.obj_transforms.push(transform);
-        self.uid_counter += 1;
-        return (self.uid_counter - 1) as usize;
+        self.obj_materials.push(material);
+        self.obj_parents.push(parent);
+        self.obj_modifiers.push(Vec::new());
+        self.obj_ids.push(id);
+        self.uid_counter = self.uid_counter.max(id.0 + 1);
+    }
+    /// Removes an object and every reference to it from the boolean op trees.
+    pub fn remove(&mut self, id: ObjectId) -> Option<(Primitive, Transform)> {
+        let index = self.index_of(id)?;
+        self.obj_ids.remove(index);
+        for op in self.bool_ops.iter_mut() {
+            op.remove_object(id);
+        }
+        self.bool_ops.retain(|op| !op.children.is_empty());
+        self.obj_materials.remove(index);
+        self.obj_parents.remove(index);
+        self.obj_modifiers.remove(index);
+        Some((self.objects.remove(index), self.obj_transforms.remove(index)))
+    }
+    pub fn index_of(&self, id: ObjectId) -> Option<usize> {
+        self.obj_ids.iter().position(|x| *x == id)
+    }
+    pub fn contains(&self, id: ObjectId) -> bool {
+        self.obj_ids.contains(&id)
+    }
+    /// Object handles in draw order, parallel to `objects`.
+    pub fn ids(&self) -> &[ObjectId] {
+        &self.obj_ids
+    }
+    /// Every object's primitive in draw order, parallel to `ids`.
+    pub fn objects(&self) -> &[Primitive] {
+        &self.objects
+    }
+    pub fn get(&self, id: ObjectId) -> Option<&Primitive> {
+        self.index_of(id).map(|i| &self.objects[i])
+    }
+    pub fn get_mut(&mut self, id: ObjectId) -> Option<&mut Primitive> {
+        self.index_of(id).map(|i| &mut self.objects[i])
+    }
+    pub fn transform(&self, id: ObjectId) -> Option<&Transform> {
+        self.index_of(id).map(|i| &self.obj_transforms[i])
+    }
+    pub fn transform_mut(&mut self, id: ObjectId) -> Option<&mut Transform> {
+        self.index_of(id).map(|i| &mut self.obj_transforms[i])
     }
-    pub fn add_light(&mut self, point: Point3) {
-        self.lights.push(point);
+    /// The object's modifier stack, applied in its own space.
+    pub fn modifiers(&self, id: ObjectId) -> Option<&[Modifier]> {
+        self.index_of(id).map(|i| self.obj_modifiers[i].as_slice())
     }
-    pub fn add_bool_op(&mut self, mut bool_op: BooleanOp) {
-        bool_op.uid = self.bool_ops.len() + 1;
-        for i in bool_op.obj_uids.iter() {
-            self.obj_boolops[*i] = bool_op.uid;
+    pub fn modifiers_mut(&mut self, id: ObjectId) -> Option<&mut Vec<Modifier>> {
+        self.index_of(id).map(|i| &mut self.obj_modifiers[i])
+    }
+    pub fn material(&self, id: ObjectId) -> Option<MaterialId> {
+        self.index_of(id).map(|i| self.obj_materials[i])
+    }
+    pub fn set_material(&mut self, id: ObjectId, material: MaterialId) {
+        if let Some(i) = self.index_of(id) {
+            self.obj_materials[i] = material;
+        }
+    }
+    pub fn add_material(&mut self, material: Material) -> MaterialId {
+        self.materials.push(material);
+        MaterialId(self.materials.len() - 1)
+    }
+    pub fn add_light(&mut self, light: Light) {
+        self.lights.push(light);
+    }
+    /// Fails with `SceneError::CsgTooDeep` if the op needs more stack slots than the shader has.
+    pub fn add_bool_op(&mut self, bool_op: BooleanOp) -> Result<(), SceneError> {
+        // one more for the union with the rest of the scene
+        let slots = bool_op.depth() + 1;
+        if slots > MAX_CSG_STACK {
+            return Err(SceneError::CsgTooDeep(slots));
         }
         self.bool_ops.push(bool_op);
+        Ok(())
+    }
+    pub fn bool_ops(&self) -> &[BooleanOp] {
+        &self.bool_ops
+    }
+    /// For editing ops in place. Their trees were checked against the shader's stack when
+    /// added, so only change operators and modifiers here.
+    pub fn bool_ops_mut(&mut self) -> &mut [BooleanOp] {
+        &mut self.bool_ops
+    }
+    pub fn remove_bool_op(&mut self, index: usize) -> Option<BooleanOp> {
+        (index < self.bool_ops.len()).then(|| self.bool_ops.remove(index))
     }
     pub fn set_camera(&mut self, camera: Camera) {
         self.camera = camera;
     }
-    pub fn get_objects(&mut self) -> [[[f32; 4]; 4]; MAX_OBJECTS] {
-        let mut arr = [[[0.; 4]; 4]; MAX_OBJECTS];
-        let obj_cpy = self.objects.clone();
-        self.objects.sort_by_key(|x| self.obj_boolops[obj_cpy.iter().position(|a| a == x).unwrap()]);
-        for (i, obj) in self.objects.iter().enumerate() {
-            arr[i] = obj.as_data(self.obj_boolops[i] as f32);
+    /// Flattens the scene into the post-order CSG program evaluated by `scene_sd` in the shader.
+    /// Boolean op trees and any objects not used by one are unioned together at the root.
+    pub fn csg_program(&self) -> CsgProgram {
+        self.compile_program(&self.obj_ids)
+    }
+    /// Like `csg_program`, but as if only the objects under `node` (or `node` itself) were
+    /// in the scene, so boolean ops with some of their objects elsewhere lose those children.
+    pub fn subtree_program(&self, node: SceneNode) -> CsgProgram {
+        // objects outside the subtree are swapped for an id nothing refers to, which keeps
+        // the others at their indices while the compile skips them
+        let ids: Vec<ObjectId> = self.obj_ids.iter().map(|id| match node {
+            SceneNode::Group(group) if self.is_descendant(SceneNode::Object(*id), group) => *id,
+            SceneNode::Object(object) if object == *id => *id,
+            _ => ObjectId(usize::MAX),
+        }).collect();
+        self.compile_program(&ids)
+    }
+    fn compile_program(&self, ids: &[ObjectId]) -> CsgProgram {
+        let used: Vec<ObjectId> = self.bool_ops.iter().flat_map(|op| op.objects()).collect();
+        let roots = self.obj_ids.iter()
+            .filter(|id| ids.contains(id) && !used.contains(id))
+            .map(|id| CsgNode::Object(*id))
+            .chain(self.bool_ops.iter().cloned().map(CsgNode::Op))
+            .collect();
+        let mut instructions = Vec::new();
+        BooleanOp::new(BooleanOpType::Union, roots).compile(&mut instructions, ids, &self.obj_modifiers);
+        CsgProgram {
+            instructions,
+            transforms: (0..self.objects.len()).map(|i| self.object_world_transform(i).get_data()).collect(),
+        }
+    }
+    /// Object data for the `scene_objects` buffer texture, four texels per object.
+    /// The object's material index is stored in the first component of the last texel,
+    /// and in the third the start of an extrusion's or revolution's shape in `get_shapes`,
+    /// of a terrain's heights in `get_heightmaps`, or of a voxel SDF's slab in `get_voxels`.
+    pub fn get_objects(&self) -> Vec<[f32; 4]> {
+        let mut shape_start = 0;
+        let mut heightmap_start = 0;
+        let mut voxel_start = 0;
+        self.objects.iter().zip(self.obj_materials.iter()).flat_map(|(obj, material)| {
+            let mut data = obj.as_data();
+            data[3][0] = material.0 as f32;
+            if let Some(shape) = obj.shape() {
+                data[3][2] = shape_start as f32;
+                shape_start += shape.get_data().len();
+            }
+            if let Some(map) = obj.heightmap() {
+                data[3][2] = heightmap_start as f32;
+                heightmap_start += map.get_data().len();
+            }
+            if let Some(grid) = obj.voxels() {
+                data[3][2] = voxel_start as f32;
+                voxel_start += grid.resolution()[2];
+            }
+            data
+        }).collect()
+    }
+    /// 2D shape data for the `scene_shapes` buffer texture, in object order.
+    pub fn get_shapes(&self) -> Vec<[f32; 4]> {
+        self.objects.iter().filter_map(|obj| obj.shape()).flat_map(|shape| shape.get_data()).collect()
+    }
+    /// Terrain height samples for the `scene_heightmaps` buffer texture, in object order.
+    pub fn get_heightmaps(&self) -> Vec<[f32; 4]> {
+        self.objects.iter().filter_map(|obj| obj.heightmap()).flat_map(|map| map.get_data()).collect()
+    }
+    /// Distance samples for the `scene_voxels` 3D texture, indexed `[z][y][x]`. Every grid in
+    /// the scene is stacked along z in object order, padded out to the widest and tallest one
+    /// by repeating its edge samples, so filtering never reaches into another grid.
+    pub fn get_voxels(&self) -> Vec<Vec<Vec<f32>>> {
+        let grids: Vec<&VoxelGrid> = self.objects.iter().filter_map(|obj| obj.voxels()).collect();
+        let width = grids.iter().map(|g| g.resolution()[0]).max().unwrap_or(1);
+        let height = grids.iter().map(|g| g.resolution()[1]).max().unwrap_or(1);
+        let slabs: Vec<Vec<Vec<f32>>> = grids.iter().flat_map(|grid| {
+            let [w, h, d] = grid.resolution();
+            (0..d).map(move |z| (0..height).map(|y| {
+                (0..width).map(|x| grid.sample(x.min(w - 1), y.min(h - 1), z)).collect()
+            }).collect())
+        }).collect();
+        if slabs.is_empty() {
+            return vec![vec![vec![1e20]]];
         }
-        return arr;
-    }
-    pub fn get_lights(&self) -> [[f32; 4]; MAX_LIGHTS] {
-        let mut arr = [[0.0; 4]; MAX_LIGHTS];
-        for (i, light) in self.lights.iter().enumerate() {
-            arr[i] = [light.x, light.y, light.z, 1.0];
-        };
-        return arr;
-    }
-    pub fn get_bool_ops(&self) -> [[f32; 2]; MAX_BOOL_OPS] {
-        let mut arr = [[0.0; 2]; MAX_BOOL_OPS];
-        for (i, op) in self.bool_ops.iter().enumerate() {
-            arr[i] = op.get();
-        };
-        return arr;
-    }
-    pub fn get_transformations(&self) -> [[[f32; 4]; 4]; MAX_OBJECTS] {
-        let mut arr = [[[0.0; 4]; 4]; MAX_OBJECTS];
-        for (i, t) in self.obj_transforms.iter().enumerate() {
-            arr[i] = t.get_data();
-        };
-        return arr;
+        slabs
+    }
+    /// Material table for the `scene_materials` buffer texture, three texels per material.
+    pub fn get_materials(&self) -> Vec<[f32; 4]> {
+        self.materials.iter().flat_map(|m| m.get_data()).collect()
+    }
+    /// Light data for the `scene_lights` buffer texture, four texels per light.
+    pub fn get_lights(&self) -> Vec<[f32; 4]> {
+        self.lights.iter().flat_map(|light| light.get_data()).collect()
+    }
+    pub fn get_fog_color(&self) -> [f32; 4] {
+        [self.fog_color.x, self.fog_color.y, self.fog_color.z, 1.0]
+    }
+    pub fn get_params(&self) -> [f32; 4] {
+        self.params.get()
+    }
+    /// Object, instruction and light counts for the `consts` uniform, with `program` this
+    /// scene's `csg_program`, which is compiled once per draw.
+    pub fn get_consts(&self, program: &CsgProgram) -> [f32; 4] {
+        [self.objects.len() as f32, program.instructions.len() as f32, self.lights.len() as f32, 0.0]
+    }
+    /// Transform data for the `scene_transforms` buffer texture, four texels per object,
+    /// with each object's transform composed with its parents'.
+    pub fn get_transformations(&self) -> Vec<[f32; 4]> {
+        (0..self.objects.len()).flat_map(|i| self.object_world_transform(i).get_data()).collect()
     }
 }
 
 
-#[derive(Clone, Copy)]
-pub struct UniformBlockObjects {
-    pub objects: [[[f32; 4]; 4]; MAX_OBJECTS],
+/// A scene flattened for evaluation: the instructions `scene_sd` runs, and each object's
+/// world to object matrix from `Transform::get_data`, composed down the hierarchy once
+/// rather than at every point.
+#[derive(Clone, Debug, Default)]
+pub struct CsgProgram {
+    pub(crate) instructions: Vec<[f32; 4]>,
+    pub(crate) transforms: Vec<[[f32; 4]; 4]>,
 }
-#[derive(Clone, Copy)]
-pub struct UniformBlockLights {
-    pub lights: [[f32; 4]; MAX_LIGHTS],
+
+impl CsgProgram {
+    /// The instructions as uploaded to the `scene_csg` buffer texture.
+    pub fn instructions(&self) -> &[[f32; 4]] {
+        &self.instructions
+    }
+    pub fn is_empty(&self) -> bool {
+        self.instructions.is_empty()
+    }
 }
-#[derive(Clone, Copy)]
-pub struct UniformBlockBoolOps {
-    pub bool_ops: [[f32; 2]; MAX_BOOL_OPS],
+
+
+/// Render settings that travel with a scene and feed `SceneParamsBlock`.
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct SceneParams {
+    pub shadow_quality: f32,
+    pub shadows: bool,
+    pub ambient_occlusion: bool,
 }
-#[derive(Clone, Copy)]
-pub struct UniformBlockTransforms {
-    pub transformations: [[[f32; 4]; 4]; MAX_BOOL_OPS],
+
+impl SceneParams {
+    pub fn get(&self) -> [f32; 4] {
+        [self.shadow_quality, self.shadows as i32 as f32, self.ambient_occlusion as i32 as f32, 0.0]
+    }
+}
+
+impl Default for SceneParams {
+    fn default() -> Self {
+        Self {
+            shadow_quality: 4.0,
+            shadows: true,
+            ambient_occlusion: true,
+        }
+    }
+}
+
+
+// glium's `implement_uniform_block!` finds field offsets by dereferencing a null pointer, which
+// debug builds abort on. Each of these blocks is a single vec4, so the layout is written out.
+macro_rules! vec4_uniform_block {
+    ($block:ident, $field:ident) => {
+        impl glium::uniforms::UniformBlock for $block {
+            fn matches(layout: &glium::program::BlockLayout, base_offset: usize) -> Result<(), glium::uniforms::LayoutMismatchError> {
+                use glium::uniforms::LayoutMismatchError;
+                match layout {
+                    glium::program::BlockLayout::Struct { members } if members.len() == 1 && members[0].0 == stringify!($field) => {
+                        <[f32; 4]>::matches(&members[0].1, base_offset).map_err(|e| LayoutMismatchError::MemberMismatch {
+                            member: stringify!($field).to_owned(),
+                            err: Box::new(e),
+                        })
+                    }
+                    _ => Err(LayoutMismatchError::LayoutMismatch {
+                        expected: layout.clone(),
+                        obtained: Self::build_layout(base_offset),
+                    }),
+                }
+            }
+            fn build_layout(base_offset: usize) -> glium::program::BlockLayout {
+                glium::program::BlockLayout::Struct {
+                    members: vec![(stringify!($field).to_owned(), <[f32; 4]>::build_layout(base_offset))],
+                }
+            }
+        }
+    };
 }
-implement_uniform_block!(UniformBlockObjects, objects);
-implement_uniform_block!(UniformBlockLights, lights);
-implement_uniform_block!(UniformBlockBoolOps, bool_ops);
-implement_uniform_block!(UniformBlockTransforms, transformations);
 
 #[derive(Clone, Copy)]
 pub struct SceneFogColorBlock {
     pub fog_color: [f32; 4],
 }
-implement_uniform_block!(SceneFogColorBlock, fog_color);
+vec4_uniform_block!(SceneFogColorBlock, fog_color);
 #[derive(Clone, Copy)]
 pub struct SceneParamsBlock {
     pub params: [f32; 4],
 }
-implement_uniform_block!(SceneParamsBlock, params);
+vec4_uniform_block!(SceneParamsBlock, params);
 #[derive(Clone, Copy)]
 pub struct SceneConstsBlock {
     pub consts: [f32; 4],
 }
-implement_uniform_block!(SceneConstsBlock, consts);
\ No newline at end of file
+vec4_uniform_block!(SceneConstsBlock, consts);
@@ -0,0 +1,277 @@
+use std::fmt;
+use std::path::Path;
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// On-disk representation of a `Scene`, written as RON.
+///
+/// `version` is checked against `SCENE_FORMAT_VERSION` on load. Objects are listed in
+/// draw order with their `ObjectId`, which boolean op trees use to refer to them. Objects and
+/// groups name their parent group, if any, and their transforms are relative to it.
+#[derive(Serialize, Deserialize)]
+pub struct SceneFile {
+    pub version: u32,
+    pub camera: Camera,
+    pub fog_color: Rgb,
+    pub params: SceneParams,
+    pub materials: Vec<Material>,
+    pub groups: Vec<SceneFileGroup>,
+    pub objects: Vec<SceneFileObject>,
+    pub lights: Vec<Light>,
+    pub bool_ops: Vec<BooleanOp>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneFileObject {
+    pub id: ObjectId,
+    pub primitive: Primitive,
+    pub transform: Transform,
+    pub material: MaterialId,
+    pub parent: Option<GroupId>,
+    #[serde(default)]
+    pub modifiers: Vec<Modifier>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SceneFileGroup {
+    pub id: GroupId,
+    pub name: String,
+    pub transform: Transform,
+    pub parent: Option<GroupId>,
+}
+
+
+#[derive(Debug)]
+pub enum SceneError {
+    Io(std::io::Error),
+    Parse(ron::error::SpannedError),
+    Serialize(ron::Error),
+    UnsupportedVersion(u32),
+    InvalidObject(usize),
+    DuplicateObject(usize),
+    InvalidMaterial(usize),
+    InvalidParent(usize),
+    ParentCycle(usize),
+    CsgTooDeep(usize),
+    InvalidShape(usize),
+    InvalidScale(usize),
+    NonUniformScale(usize),
+    InvalidLight(usize),
+    Asset(String, String),
+}
+
+impl fmt::Display for SceneError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{}", e),
+            Self::Parse(e) => write!(f, "{}", e),
+            Self::Serialize(e) => write!(f, "{}", e),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported scene format version {} (expected {})", v, SCENE_FORMAT_VERSION),
+            Self::InvalidObject(i) => write!(f, "boolean op refers to object {} which does not exist", i),
+            Self::DuplicateObject(i) => write!(f, "object or group id {} is used more than once", i),
+            Self::InvalidMaterial(i) => write!(f, "object uses material {} which does not exist", i),
+            Self::InvalidParent(i) => write!(f, "parent group {} does not exist", i),
+            Self::ParentCycle(i) => write!(f, "group {} is its own ancestor", i),
+            Self::CsgTooDeep(slots) => write!(f, "boolean op needs {} stack slots, at most {} are supported", slots, MAX_CSG_STACK),
+            Self::InvalidScale(i) => write!(f, "object or group {} has a scale that isn't positive", i),
+            Self::NonUniformScale(i) => write!(f, "group {} has a scale that differs between axes", i),
+            Self::InvalidShape(i) => write!(f, "object {} has a star with fewer than 2 points or a sharpness outside 2 to its points", i),
+            Self::InvalidLight(i) => write!(f, "spot light {} has a cone angle outside 0 to pi or an inner angle wider than its outer one", i),
+            Self::Asset(path, e) => write!(f, "could not load {}: {}", path, e),
+        }
+    }
+}
+
+impl std::error::Error for SceneError {}
+
+impl From<std::io::Error> for SceneError {
+    fn from(e: std::io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<ron::error::SpannedError> for SceneError {
+    fn from(e: ron::error::SpannedError) -> Self {
+        Self::Parse(e)
+    }
+}
+
+impl From<ron::Error> for SceneError {
+    fn from(e: ron::Error) -> Self {
+        Self::Serialize(e)
+    }
+}
+
+
+impl SceneFile {
+    pub fn from_scene(scene: &Scene) -> Self {
+        Self {
+            version: SCENE_FORMAT_VERSION,
+            camera: scene.camera.clone(),
+            fog_color: scene.fog_color,
+            params: scene.params,
+            materials: scene.materials.clone(),
+            groups: scene.group_ids().iter().zip(scene.groups.iter()).map(|(id, group)| SceneFileGroup {
+                id: *id,
+                name: group.name.clone(),
+                transform: group.transform,
+                parent: group.parent,
+            }).collect(),
+            objects: scene.ids().iter().enumerate().map(|(i, id)| SceneFileObject {
+                id: *id,
+                primitive: scene.objects[i].clone(),
+                transform: scene.obj_transforms[i],
+                material: scene.obj_materials[i],
+                parent: scene.obj_parents[i],
+                modifiers: scene.obj_modifiers[i].clone(),
+            }).collect(),
+            lights: scene.lights.clone(),
+            bool_ops: scene.bool_ops.clone(),
+        }
+    }
+    /// Builds the scene the file describes, loading the heightmaps, fonts and voxel grids it
+    /// names. Relative asset paths are found in `dir`, the scene file's directory, or the
+    /// working directory without one, and the scene keeps them joined onto it.
+    pub fn into_scene(self, dir: Option<&Path>) -> Result<Scene, SceneError> {
+        check_version(self.version)?;
+        let mut scene = Scene::new();
+        scene.set_camera(self.camera);
+        scene.fog_color = self.fog_color;
+        scene.params = self.params;
+        scene.materials = self.materials;
+        // a zero scale divides by zero in the world to local transform, and a negative one mirrors
+        // the distance field inside out
+        let positive = |t: &Transform| t.scale.x > 0.0 && t.scale.y > 0.0 && t.scale.z > 0.0;
+        for group in self.groups {
+            if scene.contains_group(group.id) {
+                return Err(SceneError::DuplicateObject(group.id.0));
+            }
+            if !positive(&group.transform) {
+                return Err(SceneError::InvalidScale(group.id.0));
+            }
+            if !group.transform.has_uniform_scale() {
+                return Err(SceneError::NonUniformScale(group.id.0));
+            }
+            scene.add_group_with_id(group.id, Group { name: group.name, transform: group.transform, parent: group.parent });
+        }
+        for (id, group) in scene.group_ids().iter().zip(scene.groups.iter()) {
+            if let Some(parent) = group.parent.filter(|p| !scene.contains_group(*p)) {
+                return Err(SceneError::InvalidParent(parent.0));
+            }
+            // a chain longer than the number of groups must loop
+            let mut ancestor = group.parent;
+            for _ in 0..scene.groups.len() {
+                ancestor = ancestor.and_then(|p| scene.group(p).unwrap().parent);
+            }
+            if ancestor.is_some() {
+                return Err(SceneError::ParentCycle(id.0));
+            }
+        }
+        for mut obj in self.objects {
+            load_assets(&mut obj.primitive, dir)?;
+            if scene.contains(obj.id) {
+                return Err(SceneError::DuplicateObject(obj.id.0));
+            }
+            if obj.material.0 >= scene.materials.len() {
+                return Err(SceneError::InvalidMaterial(obj.material.0));
+            }
+            if let Some(trap) = obj.primitive.trap_material().filter(|m| m.0 >= scene.materials.len()) {
+                return Err(SceneError::InvalidMaterial(trap.0));
+            }
+            if let Some(parent) = obj.parent.filter(|p| !scene.contains_group(*p)) {
+                return Err(SceneError::InvalidParent(parent.0));
+            }
+            if !positive(&obj.transform) {
+                return Err(SceneError::InvalidScale(obj.id.0));
+            }
+            if obj.primitive.shape().is_some_and(|shape| !shape.is_valid()) {
+                return Err(SceneError::InvalidShape(obj.id.0));
+            }
+            scene.add_with_id(obj.id, obj.primitive, obj.transform, obj.material, obj.parent);
+            *scene.obj_modifiers.last_mut().unwrap() = obj.modifiers;
+        }
+        for (i, light) in self.lights.into_iter().enumerate() {
+            if !light.is_valid() {
+                return Err(SceneError::InvalidLight(i));
+            }
+            scene.add_light(light);
+        }
+        for op in self.bool_ops {
+            if let Some(id) = op.objects().into_iter().find(|id| !scene.contains(*id)) {
+                return Err(SceneError::InvalidObject(id.0));
+            }
+            scene.add_bool_op(op)?;
+        }
+        Ok(scene)
+    }
+}
+
+fn check_version(version: u32) -> Result<(), SceneError> {
+    if version != SCENE_FORMAT_VERSION {
+        return Err(SceneError::UnsupportedVersion(version));
+    }
+    Ok(())
+}
+
+// deserializing a primitive only reads the paths of its files, so read them
+fn load_assets(primitive: &mut Primitive, dir: Option<&Path>) -> Result<(), SceneError> {
+    if let (Some(path), Some(dir)) = (primitive.asset_path_mut(), dir) {
+        *path = dir.join(&*path).to_string_lossy().into_owned();
+    }
+    let path = primitive.asset_path_mut().cloned().unwrap_or_default();
+    let failed = |e: &dyn std::error::Error| SceneError::Asset(path.clone(), e.to_string());
+    match primitive {
+        Primitive::Terrain(HeightSource::Image(map), ..) => *map = Heightmap::load(map.path()).map_err(|e| failed(&e))?,
+        Primitive::Text(text, ..) => *text = Text::new(text.string(), text.font(), text.size()).map_err(|e| failed(&e))?,
+        Primitive::VoxelSdf(grid) => *grid = grid.reload().map_err(|e| failed(&e))?,
+        _ => {},
+    }
+    Ok(())
+}
+
+
+impl Scene {
+    pub fn from_ron(src: &str) -> Result<Self, SceneError> {
+        Self::from_ron_in(src, None)
+    }
+    fn from_ron_in(src: &str, dir: Option<&Path>) -> Result<Self, SceneError> {
+        // the rest of a file from an unsupported version may not parse, so check it first
+        #[derive(Deserialize)]
+        struct Version {
+            version: u32,
+        }
+        check_version(ron::from_str::<Version>(src)?.version)?;
+        let file: SceneFile = ron::from_str(src)?;
+        file.into_scene(dir)
+    }
+    pub fn to_ron(&self) -> Result<String, SceneError> {
+        let config = ron::ser::PrettyConfig::new();
+        Ok(ron::ser::to_string_pretty(&SceneFile::from_scene(self), config)?)
+    }
+    /// Reads a scene file. Asset paths in it (heightmaps, fonts and voxel grids) that are
+    /// relative are found next to the file, and the scene keeps their absolute paths.
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self, SceneError> {
+        let path = std::path::absolute(path)?;
+        let src = std::fs::read_to_string(&path)?;
+        Self::from_ron_in(&src, path.parent())
+    }
+    /// Writes a scene file. Assets in the file's directory or below it are written relative
+    /// to it, so the directory can be moved as a whole, and others with their absolute paths.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SceneError> {
+        let path = std::path::absolute(path)?;
+        let dir = path.parent().unwrap_or(Path::new(""));
+        let mut file = SceneFile::from_scene(self);
+        for obj in file.objects.iter_mut() {
+            // an unsaved voxel grid has no path, and fails to serialize below
+            if let Some(asset) = obj.primitive.asset_path_mut().filter(|asset| !asset.is_empty()) {
+                let absolute = std::path::absolute(&*asset)?;
+                *asset = absolute.strip_prefix(dir).unwrap_or(&absolute).to_string_lossy().into_owned();
+            }
+        }
+        let config = ron::ser::PrettyConfig::new();
+        std::fs::write(&path, ron::ser::to_string_pretty(&file, config)?)?;
+        Ok(())
+    }
+}
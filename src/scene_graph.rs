@@ -0,0 +1,180 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// Stable handle to a group in a `Scene`.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct GroupId(pub usize);
+
+/// A node in the scene hierarchy without geometry of its own. Its transform is applied to
+/// every object and group under it, and its scale must be uniform (see `Transform::child`).
+#[derive(Clone, PartialEq, Debug)]
+pub struct Group {
+    pub name: String,
+    pub transform: Transform,
+    pub parent: Option<GroupId>,
+}
+
+/// Anything that can be placed in the scene hierarchy.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SceneNode {
+    Object(ObjectId),
+    Group(GroupId),
+}
+
+impl From<ObjectId> for SceneNode {
+    fn from(id: ObjectId) -> Self {
+        Self::Object(id)
+    }
+}
+
+impl From<GroupId> for SceneNode {
+    fn from(id: GroupId) -> Self {
+        Self::Group(id)
+    }
+}
+
+
+impl Scene {
+    pub fn add_group(&mut self, name: &str, transform: Transform) -> GroupId {
+        let id = GroupId(self.uid_counter);
+        self.add_group_with_id(id, Group { name: name.into(), transform, parent: None });
+        id
+    }
+    pub(crate) fn add_group_with_id(&mut self, id: GroupId, group: Group) {
+        self.groups.push(group);
+        self.group_ids.push(id);
+        self.uid_counter = self.uid_counter.max(id.0 + 1);
+    }
+    /// Adds an object under `parent`, with `transform` relative to the group.
+    pub fn add_child(&mut self, parent: GroupId, object: Primitive, transform: Transform, material: MaterialId) -> ObjectId {
+        let id = ObjectId(self.uid_counter);
+        self.add_with_id(id, object, transform, material, Some(parent));
+        id
+    }
+    /// Removes a group. Its children move up to its parent and keep their world placement.
+    pub fn remove_group(&mut self, id: GroupId) -> Option<Group> {
+        let index = self.group_index_of(id)?;
+        let parent = self.groups[index].parent;
+        for child in self.children(Some(id)) {
+            self.set_parent(child, parent);
+        }
+        self.group_ids.remove(index);
+        Some(self.groups.remove(index))
+    }
+    pub fn group_index_of(&self, id: GroupId) -> Option<usize> {
+        self.group_ids.iter().position(|x| *x == id)
+    }
+    pub fn contains_group(&self, id: GroupId) -> bool {
+        self.group_ids.contains(&id)
+    }
+    /// Group handles, parallel to `groups`.
+    pub fn group_ids(&self) -> &[GroupId] {
+        &self.group_ids
+    }
+    /// Every group, parallel to `group_ids`.
+    pub fn groups(&self) -> &[Group] {
+        &self.groups
+    }
+    pub fn group(&self, id: GroupId) -> Option<&Group> {
+        self.group_index_of(id).map(|i| &self.groups[i])
+    }
+    pub fn group_mut(&mut self, id: GroupId) -> Option<&mut Group> {
+        self.group_index_of(id).map(|i| &mut self.groups[i])
+    }
+    pub fn contains_node(&self, node: SceneNode) -> bool {
+        match node {
+            SceneNode::Object(id) => self.contains(id),
+            SceneNode::Group(id) => self.contains_group(id),
+        }
+    }
+    pub fn parent(&self, node: SceneNode) -> Option<GroupId> {
+        match node {
+            SceneNode::Object(id) => self.index_of(id).and_then(|i| self.obj_parents[i]),
+            SceneNode::Group(id) => self.group(id).and_then(|g| g.parent),
+        }
+    }
+    /// Direct children of `parent`, or the top level nodes for `None`. Groups come first.
+    pub fn children(&self, parent: Option<GroupId>) -> Vec<SceneNode> {
+        let groups = self.group_ids.iter().zip(self.groups.iter())
+            .filter(|(_, g)| g.parent == parent)
+            .map(|(id, _)| SceneNode::Group(*id));
+        let objects = self.obj_ids.iter().zip(self.obj_parents.iter())
+            .filter(|(_, p)| **p == parent)
+            .map(|(id, _)| SceneNode::Object(*id));
+        groups.chain(objects).collect()
+    }
+    /// Whether `node` is `group` or somewhere below it.
+    pub fn is_descendant(&self, node: SceneNode, group: GroupId) -> bool {
+        if node == SceneNode::Group(group) {
+            return true;
+        }
+        let mut parent = self.parent(node);
+        while let Some(p) = parent {
+            if p == group {
+                return true;
+            }
+            parent = self.group(p).and_then(|g| g.parent);
+        }
+        false
+    }
+    /// Moves `node` under `parent` (or to the top level for `None`), adjusting its local
+    /// transform so it stays where it is in the world. Returns `false` and leaves the scene
+    /// unchanged if either doesn't exist or the move would make a group its own ancestor.
+    pub fn set_parent(&mut self, node: SceneNode, parent: Option<GroupId>) -> bool {
+        if !self.contains_node(node) {
+            return false;
+        }
+        if let Some(p) = parent {
+            if !self.contains_group(p) {
+                return false;
+            }
+            if let SceneNode::Group(g) = node {
+                if self.is_descendant(SceneNode::Group(p), g) {
+                    return false;
+                }
+            }
+        }
+        let world = self.world_transform(node).unwrap();
+        let local = match parent {
+            Some(p) => self.world_transform(SceneNode::Group(p)).unwrap().relative(&world),
+            None => world,
+        };
+        match node {
+            SceneNode::Object(id) => {
+                let i = self.index_of(id).unwrap();
+                self.obj_parents[i] = parent;
+                self.obj_transforms[i] = local;
+            },
+            SceneNode::Group(id) => {
+                let group = self.group_mut(id).unwrap();
+                group.parent = parent;
+                group.transform = local;
+            },
+        }
+        true
+    }
+    /// Transform from the node's space to the world, composed down the hierarchy.
+    pub fn world_transform(&self, node: SceneNode) -> Option<Transform> {
+        match node {
+            SceneNode::Object(id) => self.index_of(id).map(|i| self.object_world_transform(i)),
+            SceneNode::Group(id) => self.group_index_of(id).map(|i| self.group_world_transform(i)),
+        }
+    }
+    /// World transform of the object at `index` in the object list.
+    pub fn object_world_transform(&self, index: usize) -> Transform {
+        self.parent_world_transform(self.obj_parents[index]).child(&self.obj_transforms[index])
+    }
+    fn group_world_transform(&self, index: usize) -> Transform {
+        let group = &self.groups[index];
+        self.parent_world_transform(group.parent).child(&group.transform)
+    }
+    fn parent_world_transform(&self, parent: Option<GroupId>) -> Transform {
+        match parent.and_then(|p| self.group_index_of(p)) {
+            Some(i) => self.group_world_transform(i),
+            None => Transform::none(),
+        }
+    }
+}
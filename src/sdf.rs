@@ -0,0 +1,967 @@
+use super::*;
+
+
+
+// * CPU versions of the distance functions in fragment.glsl. Each function mirrors the GLSL
+// * function of the same name, so keep the two in sync when changing either.
+
+/// Result of evaluating the scene at a point, like the shader's
+/// vec4(distance, material a, material b, blend).
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct SdfResult {
+    pub distance: f32,
+    pub material_a: MaterialId,
+    pub material_b: MaterialId,
+    pub blend: f32,
+}
+
+impl SdfResult {
+    pub fn new(distance: f32, material: MaterialId) -> Self {
+        Self {
+            distance,
+            material_a: material,
+            material_b: material,
+            blend: 0.0,
+        }
+    }
+    /// The surface material, mirrors `get_material`.
+    pub fn material(&self, materials: &[Material]) -> Material {
+        let a = materials[self.material_a.0];
+        if self.blend <= 0.0 {
+            return a;
+        }
+        let b = materials[self.material_b.0];
+        let t = self.blend;
+        let mix = |x: f32, y: f32| x + (y - x) * t;
+        Material {
+            albedo: a.albedo.lerp(b.albedo, t),
+            roughness: mix(a.roughness, b.roughness),
+            metallic: mix(a.metallic, b.metallic),
+            specular: mix(a.specular, b.specular),
+            emissive: a.emissive.lerp(b.emissive, t),
+            opacity: mix(a.opacity, b.opacity),
+        }
+    }
+    fn dominant(&self) -> MaterialId {
+        if self.blend < 0.5 { self.material_a } else { self.material_b }
+    }
+}
+
+
+// GLSL sign(), which unlike f32::signum is 0 at 0
+fn sign(x: f32) -> f32 {
+    if x > 0.0 {
+        1.0
+    } else if x < 0.0 {
+        -1.0
+    } else {
+        0.0
+    }
+}
+
+fn xz(p: Vec3) -> Vec2 {
+    vec2(p.x, p.z)
+}
+
+fn mix_materials(a: SdfResult, b: SdfResult, distance: f32, h: f32) -> SdfResult {
+    if h <= 0.0 {
+        return SdfResult { distance, ..a };
+    }
+    if h >= 1.0 {
+        return SdfResult { distance, ..b };
+    }
+    SdfResult {
+        distance,
+        material_a: a.dominant(),
+        material_b: b.dominant(),
+        blend: h,
+    }
+}
+
+fn op_union(a: SdfResult, b: SdfResult) -> SdfResult {
+    if a.distance < b.distance { a } else { b }
+}
+fn op_intersect(a: SdfResult, b: SdfResult) -> SdfResult {
+    if a.distance > b.distance { a } else { b }
+}
+fn op_difference(a: SdfResult, b: SdfResult) -> SdfResult {
+    if a.distance > -b.distance { a } else { SdfResult { distance: -b.distance, ..b } }
+}
+// GLSL mod(), which unlike % takes the sign of y
+fn glsl_mod(x: f32, y: f32) -> f32 {
+    x - y * (x / y).floor()
+}
+
+// h is how much of b's material shows, across a band of width 2k around where a and b meet
+fn blend_weight(a: f32, b: f32, k: f32) -> f32 {
+    (0.5 + 0.5 * (a - b) / k.max(1e-6)).clamp(0.0, 1.0)
+}
+
+fn op_smooth_union(a: SdfResult, b: SdfResult, k: f32) -> SdfResult {
+    let k = k.max(1e-6);
+    let h = blend_weight(a.distance, b.distance, k);
+    let t = a.distance + (b.distance - a.distance) * h - k * h * (1.0 - h);
+    mix_materials(a, b, t, h)
+}
+fn op_smooth_intersect(a: SdfResult, b: SdfResult, k: f32) -> SdfResult {
+    let k = k.max(1e-6);
+    let h = blend_weight(b.distance, a.distance, k);
+    let t = a.distance + (b.distance - a.distance) * h + k * h * (1.0 - h);
+    mix_materials(a, b, t, h)
+}
+fn op_smooth_difference(a: SdfResult, b: SdfResult, k: f32) -> SdfResult {
+    let k = k.max(1e-6);
+    let h = blend_weight(-b.distance, a.distance, k);
+    let t = a.distance + (-b.distance - a.distance) * h + k * h * (1.0 - h);
+    mix_materials(a, b, t, h)
+}
+fn op_chamfer(a: SdfResult, b: SdfResult, r: f32) -> SdfResult {
+    let t = a.distance.min(b.distance).min((a.distance - r + b.distance) * 0.5f32.sqrt());
+    mix_materials(a, b, t, blend_weight(a.distance, b.distance, r))
+}
+fn op_stairs(a: SdfResult, b: SdfResult, r: f32, n: f32) -> SdfResult {
+    let (da, db) = (a.distance, b.distance);
+    let s = r / n.max(1.0);
+    let u = db - r;
+    let t = da.min(db).min(0.5 * (u + da + (glsl_mod(u - da + s, 2.0 * s) - s).abs()));
+    mix_materials(a, b, t, blend_weight(da, db, r))
+}
+fn op_columns(a: SdfResult, b: SdfResult, r: f32, n: f32) -> SdfResult {
+    let (da, db) = (a.distance, b.distance);
+    let mut t = da.min(db);
+    if da < r && db < r {
+        let n = n.max(1.0);
+        let sqrt2 = 2.0f32.sqrt();
+        let column_radius = r * sqrt2 / ((n - 1.0) * 2.0 + sqrt2);
+        // rotate 45 degrees and move onto the diagonal the columns sit on
+        let px = (da + db) * 0.5f32.sqrt() - sqrt2 / 2.0 * r + column_radius * sqrt2;
+        let mut py = (db - da) * 0.5f32.sqrt();
+        if glsl_mod(n, 2.0) == 1.0 {
+            py += column_radius;
+        }
+        let size = column_radius * 2.0;
+        py = glsl_mod(py + column_radius, size) - column_radius;
+        t = t.min((px * px + py * py).sqrt() - column_radius).min(px);
+    }
+    mix_materials(a, b, t, blend_weight(da, db, r))
+}
+fn op_xor(a: SdfResult, b: SdfResult) -> SdfResult {
+    let outer = op_union(a, b);
+    let inner = op_intersect(a, b);
+    if outer.distance > -inner.distance { outer } else { SdfResult { distance: -inner.distance, ..inner } }
+}
+fn op_morph(a: SdfResult, b: SdfResult, t: f32) -> SdfResult {
+    let t = t.clamp(0.0, 1.0);
+    mix_materials(a, b, a.distance + (b.distance - a.distance) * t, t)
+}
+
+/// Mirrors `bool_op_sd`, taking the operator as returned by `BooleanOp::get`.
+pub(crate) fn bool_op_sd(op: [f32; 3], a: SdfResult, b: SdfResult) -> SdfResult {
+    let k = op[1];
+    match op[0] as i32 {
+        1 => op_union(a, b),
+        2 => op_intersect(a, b),
+        3 => op_difference(a, b),
+        4 => op_smooth_union(a, b, k),
+        5 => op_smooth_intersect(a, b, k),
+        6 => op_smooth_difference(a, b, k),
+        7 => op_chamfer(a, b, k),
+        8 => op_stairs(a, b, k, op[2]),
+        9 => op_columns(a, b, k, op[2]),
+        10 => op_xor(a, b),
+        11 => op_morph(a, b, k),
+        _ => SdfResult::new(0.0, MaterialId(0)),
+    }
+}
+
+fn sphere_sd(p: Vec3, r: f32) -> f32 {
+    p.length() - r
+}
+
+fn plane_sd(p: Vec3, n: Vec3, h: f32) -> f32 {
+    p.dot(n) + h
+}
+
+fn box_sd(p: Vec3, b: Vec3, r: f32) -> f32 {
+    let q = p.abs() - b;
+    q.max(0.0).length() + q.x.max(q.y.max(q.z)).min(0.0) - r
+}
+
+fn boxframe_sd(p: Vec3, b: Vec3, e: f32) -> f32 {
+    let p = p.abs() - b;
+    let q = (p + e).abs() - e;
+    let edge = |v: Vec3| v.max(0.0).length() + v.x.max(v.y.max(v.z)).min(0.0);
+    edge(vec3(p.x, q.y, q.z))
+        .min(edge(vec3(q.x, p.y, q.z)))
+        .min(edge(vec3(q.x, q.y, p.z)))
+}
+
+fn torus_sd(p: Vec3, tx: f32, ty: f32) -> f32 {
+    let q = vec2(xz(p).length() - tx, p.y);
+    q.length() - ty
+}
+
+fn capped_torus_sd(p: Vec3, scx: f32, scy: f32, ra: f32, rb: f32) -> f32 {
+    let p = vec3(p.x.abs(), p.y, p.z);
+    let pxy = vec2(p.x, p.y);
+    let k = if scy * p.x > scx * p.y { pxy.dot(vec2(scx, scy)) } else { pxy.length() };
+    (p.dot(p) + ra * ra - 2.0 * ra * k).sqrt() - rb
+}
+
+fn link_sd(p: Vec3, le: f32, r1: f32, r2: f32) -> f32 {
+    let q = vec3(p.x, (p.y.abs() - le).max(0.0), p.z);
+    vec2(vec2(q.x, q.y).length() - r1, q.z).length() - r2
+}
+
+fn cone_sd(p: Vec3, t: f32, h: f32) -> f32 {
+    let c = vec2(t.sin(), t.cos());
+    let q = vec2(c.x / c.y, -1.0) * h;
+
+    let w = vec2(xz(p).length(), p.y);
+    let a = w - q * (w.dot(q) / q.dot(q)).clamp(0.0, 1.0);
+    let b = w - q * vec2((w.x / q.x).clamp(0.0, 1.0), 1.0);
+    let k = sign(q.y);
+    let d = a.dot(a).min(b.dot(b));
+    let s = (k * (w.x * q.y - w.y * q.x)).max(k * (w.y - q.y));
+    d.sqrt() * sign(s)
+}
+
+fn hex_prism_sd(p: Vec3, hx: f32, hy: f32) -> f32 {
+    let k = vec3(-0.8660254, 0.5, 0.57735);
+    let p = p.abs();
+    let kxy = vec2(k.x, k.y);
+    let pxy = vec2(p.x, p.y);
+    let pxy = pxy - kxy * (2.0 * kxy.dot(pxy).min(0.0));
+    let d = vec2(
+        (pxy - vec2(pxy.x.clamp(-k.z * hx, k.z * hx), hx)).length() * sign(pxy.y - hx),
+        p.z - hy,
+    );
+    d.x.max(d.y).min(0.0) + d.max(0.0).length()
+}
+
+fn tri_prism_sd(p: Vec3, hx: f32, hy: f32) -> f32 {
+    let q = p.abs();
+    (q.z - hy).max((q.x * 0.866025 + p.y * 0.5).max(-p.y) - hx * 0.5)
+}
+
+fn capsule_sd(p: Vec3, h: f32, r: f32) -> f32 {
+    let p = vec3(p.x, p.y - p.y.clamp(0.0, h), p.z);
+    p.length() - r
+}
+
+fn capped_cylinder_sd(p: Vec3, h: f32, r: f32) -> f32 {
+    let d = vec2(xz(p).length(), p.y).abs() - vec2(r, h);
+    d.x.max(d.y).min(0.0) + d.max(0.0).length()
+}
+
+fn round_cylinder_sd(p: Vec3, ra: f32, rb: f32, h: f32) -> f32 {
+    let d = vec2(xz(p).length() - 2.0 * ra + rb, p.y.abs() - h);
+    d.x.max(d.y).min(0.0) + d.max(0.0).length() - rb
+}
+
+fn capped_cone_sd(p: Vec3, h: f32, r1: f32, r2: f32) -> f32 {
+    let q = vec2(xz(p).length(), p.y);
+    let k1 = vec2(r2, h);
+    let k2 = vec2(r2 - r1, 2.0 * h);
+    let ca = vec2(q.x - q.x.min(if q.y < 0.0 { r1 } else { r2 }), q.y.abs() - h);
+    let cb = q - k1 + k2 * ((k1 - q).dot(k2) / k2.dot(k2)).clamp(0.0, 1.0);
+    let s = if cb.x < 0.0 && ca.y < 0.0 { -1.0 } else { 1.0 };
+    s * ca.dot(ca).min(cb.dot(cb)).sqrt()
+}
+
+fn solid_angle_sd(p: Vec3, a: f32, ra: f32) -> f32 {
+    let c = vec2(a.sin(), a.cos());
+    let q = vec2(xz(p).length(), p.y);
+    let l = q.length() - ra;
+    let m = (q - c * q.dot(c).clamp(0.0, ra)).length();
+    l.max(m * sign(c.y * q.x - c.x * q.y))
+}
+
+fn cut_sphere_sd(p: Vec3, r: f32, h: f32) -> f32 {
+    let w = (r * r - h * h).sqrt();
+
+    let q = vec2(xz(p).length(), p.y);
+    let s = ((h - r) * q.x * q.x + w * w * (h + r - 2.0 * q.y)).max(h * q.x - w * q.y);
+    if s < 0.0 {
+        q.length() - r
+    } else if q.x < w {
+        h - q.y
+    } else {
+        (q - vec2(w, h)).length()
+    }
+}
+
+fn cut_hollow_sphere_sd(p: Vec3, r: f32, h: f32, t: f32) -> f32 {
+    let w = (r * r - h * h).sqrt();
+
+    let q = vec2(xz(p).length(), p.y);
+    (if h * q.x < w * q.y { (q - vec2(w, h)).length() } else { (q.length() - r).abs() }) - t
+}
+
+fn death_star_sd(p2: Vec3, ra: f32, rb: f32, d: f32) -> f32 {
+    let a = (ra * ra - rb * rb + d * d) / (2.0 * d);
+    let b = (ra * ra - a * a).max(0.0).sqrt();
+
+    let p = vec2(p2.x, vec2(p2.y, p2.z).length());
+    if p.x * b - p.y * a > d * (b - p.y).max(0.0) {
+        (p - vec2(a, b)).length()
+    } else {
+        (p.length() - ra).max(-((p - vec2(d, 0.0)).length() - rb))
+    }
+}
+
+fn round_cone_sd(p: Vec3, r1: f32, r2: f32, h: f32) -> f32 {
+    let b = (r1 - r2) / h;
+    let a = (1.0 - b * b).sqrt();
+
+    let q = vec2(xz(p).length(), p.y);
+    let k = q.dot(vec2(-b, a));
+    if k < 0.0 {
+        return q.length() - r1;
+    }
+    if k > a * h {
+        return (q - vec2(0.0, h)).length() - r2;
+    }
+    q.dot(vec2(a, b)) - r1
+}
+
+fn ellipsoid_sd(p: Vec3, r: Vec3) -> f32 {
+    let k0 = (p / r).length();
+    let k1 = (p / (r * r)).length();
+    k0 * (k0 - 1.0) / k1
+}
+
+fn rhombus_sd(p: Vec3, la: f32, lb: f32, h: f32, ra: f32) -> f32 {
+    let p = p.abs();
+    let b = vec2(la, lb);
+    let f = (sign(b.dot(b - xz(p) * 2.0)) / b.dot(b)).clamp(-1.0, 1.0);
+    let q = vec2(
+        (xz(p) - b * vec2(1.0 - f, 1.0 + f) * 0.5).length() * sign(p.x * b.y + p.z * b.x - b.x * b.y) - ra,
+        p.y - h,
+    );
+    q.x.max(q.y).min(0.0) + q.max(0.0).length()
+}
+
+fn octahedron_sd(p: Vec3, s: f32) -> f32 {
+    let p = p.abs();
+    let m = p.x + p.y + p.z - s;
+    let q = if 3.0 * p.x < m {
+        p
+    } else if 3.0 * p.y < m {
+        vec3(p.y, p.z, p.x)
+    } else if 3.0 * p.z < m {
+        vec3(p.z, p.x, p.y)
+    } else {
+        return m * 0.57735027;
+    };
+
+    let k = (0.5 * (q.z - q.y + s)).clamp(0.0, s);
+    vec3(q.x, q.y - s + k, q.z - k).length()
+}
+
+fn pyramid_sd(p: Vec3, h: f32) -> f32 {
+    let m2 = h * h + 0.25;
+
+    let (mut px, mut pz) = (p.x.abs(), p.z.abs());
+    if pz > px {
+        std::mem::swap(&mut px, &mut pz);
+    }
+    px -= 0.5;
+    pz -= 0.5;
+
+    let q = vec3(pz, h * p.y - 0.5 * px, h * px + 0.5 * p.y);
+
+    let s = (-q.x).max(0.0);
+    let t = ((q.y - 0.5 * pz) / (m2 + 0.25)).clamp(0.0, 1.0);
+
+    let a = m2 * (q.x + s) * (q.x + s) + q.y * q.y;
+    let b = m2 * (q.x + 0.5 * t) * (q.x + 0.5 * t) + (q.y - m2 * t) * (q.y - m2 * t);
+
+    let d2 = if q.y.min(-q.x * m2 - q.y * 0.5) > 0.0 { 0.0 } else { a.min(b) };
+
+    ((d2 + q.z * q.z) / m2).sqrt() * sign(q.z.max(-p.y))
+}
+
+fn triangle_sd(p: Vec3, a: Vec3, b: Vec3, c: Vec3) -> f32 {
+    let ba = b - a; let pa = p - a;
+    let cb = c - b; let pb = p - b;
+    let ac = a - c; let pc = p - c;
+    let nor = ba.cross(ac);
+
+    let i = ba * (ba.dot(pa) / ba.dot(ba)).clamp(0.0, 1.0) - pa;
+    let j = cb * (cb.dot(pb) / cb.dot(cb)).clamp(0.0, 1.0) - pb;
+    let k = ac * (ac.dot(pc) / ac.dot(ac)).clamp(0.0, 1.0) - pc;
+
+    let outside = sign(ba.cross(nor).dot(pa)) + sign(cb.cross(nor).dot(pb)) + sign(ac.cross(nor).dot(pc)) < 2.0;
+    if outside {
+        i.dot(i).min(j.dot(j)).min(k.dot(k)).sqrt()
+    } else {
+        (nor.dot(pa) * nor.dot(pa) / nor.dot(nor)).sqrt()
+    }
+}
+
+// the fractals return (distance, orbit trap), with the trap in [0, 1]
+
+fn mandelbulb_sd(p: Vec3, power: f32, iterations: u32, bailout: f32) -> (f32, f32) {
+    let mut w = p;
+    let mut m = w.dot(w);
+    let mut dz = 1.0;
+    let mut trap = m;
+    for _ in 0..iterations {
+        dz = power * m.powf((power - 1.0) * 0.5) * dz + 1.0;
+        let r = m.sqrt().max(1e-6);
+        let b = power * (w.y / r).clamp(-1.0, 1.0).acos();
+        let a = power * w.x.atan2(w.z);
+        w = p + vec3(b.sin() * a.sin(), b.cos(), b.sin() * a.cos()) * r.powf(power);
+        m = w.dot(w);
+        trap = trap.min(m);
+        if m > bailout * bailout {
+            break;
+        }
+    }
+    // points that never leave the origin would take the log of 0
+    let m = m.max(1e-12);
+    (0.25 * m.ln() * m.sqrt() / dz, trap.sqrt().clamp(0.0, 1.0))
+}
+
+fn menger_sd(p: Vec3, size: f32, iterations: u32) -> (f32, f32) {
+    let p = p / size;
+    let mut d = box_sd(p, vec3(1., 1., 1.), 0.0);
+    let mut s = 1.0;
+    let mut trap = 0.0;
+    for i in 0..iterations {
+        let a = vec3(glsl_mod(p.x * s, 2.0), glsl_mod(p.y * s, 2.0), glsl_mod(p.z * s, 2.0)) - 1.0;
+        s *= 3.0;
+        let r = (vec3(1., 1., 1.) - a.abs() * 3.0).abs();
+        let da = r.x.max(r.y);
+        let db = r.y.max(r.z);
+        let dc = r.z.max(r.x);
+        let c = (da.min(db).min(dc) - 1.0) / s;
+        // the trap is the level that carved the surface here
+        if c > d {
+            d = c;
+            trap = (i + 1) as f32 / iterations as f32;
+        }
+    }
+    (d * size, trap)
+}
+
+fn sierpinski_sd(p: Vec3, size: f32, iterations: u32) -> (f32, f32) {
+    let mut p = p / size;
+    let mut trap = p.dot(p);
+    for _ in 0..iterations {
+        // fold towards the (1, 1, 1) corner, then scale about it
+        if p.x + p.y < 0.0 { (p.x, p.y) = (-p.y, -p.x); }
+        if p.x + p.z < 0.0 { (p.x, p.z) = (-p.z, -p.x); }
+        if p.y + p.z < 0.0 { (p.y, p.z) = (-p.z, -p.y); }
+        p = p * 2.0 - 1.0;
+        trap = trap.min(p.dot(p));
+    }
+    let tetra = (-p.x - p.y - p.z).max(p.x + p.y - p.z).max((-p.x + p.y + p.z).max(p.x - p.y + p.z));
+    let d = (tetra - 1.0) / 3f32.sqrt() * 2f32.powi(-(iterations as i32));
+    (d * size, (trap.sqrt() / 3f32.sqrt()).clamp(0.0, 1.0))
+}
+
+// quaternions are (xyz, w) with w the real part, like Quat. p is the slice through the
+// real, i and j axes, the purely imaginary slice is a plain blob
+fn julia_sd(p: Vec3, c: Quat, iterations: u32, bailout: f32) -> (f32, f32) {
+    let (mut v, mut s) = (vec3(p.y, p.z, 0.0), p.x);
+    let mut md2 = 1.0;
+    let mut mz2 = p.dot(p);
+    let mut trap = mz2;
+    for _ in 0..iterations {
+        md2 *= 4.0 * mz2;
+        (v, s) = (v * (2.0 * s) + vec3(c.x, c.y, c.z), s * s - v.dot(v) + c.w);
+        mz2 = v.dot(v) + s * s;
+        trap = trap.min(mz2);
+        if mz2 > bailout * bailout {
+            break;
+        }
+    }
+    // points that never leave the origin would divide 0 by 0
+    let (mz2, md2) = (mz2.max(1e-12), md2.max(1e-12));
+    (0.25 * (mz2 / md2).sqrt() * mz2.ln(), trap.sqrt().clamp(0.0, 1.0))
+}
+
+
+// 2D shapes, in the XY plane
+
+fn circle_sd(p: Vec2, r: f32) -> f32 {
+    p.length() - r
+}
+
+fn rounded_rect_sd(p: Vec2, b: Vec2, r: f32) -> f32 {
+    let q = p.abs() - b + vec2(r, r);
+    q.x.max(q.y).min(0.0) + q.max(0.0).length() - r
+}
+
+// folds the polygon's edges into the squared distance `d` and the inside sign `s`
+fn polygon_edges(p: Vec2, v: &[Vec2], d: &mut f32, s: &mut f32) {
+    let mut j = v.len().max(1) - 1;
+    for i in 0..v.len() {
+        let e = v[j] - v[i];
+        let w = p - v[i];
+        let b = w - e * (w.dot(e) / e.dot(e).max(1e-12)).clamp(0.0, 1.0);
+        *d = d.min(b.dot(b));
+        // crossing number, flipped each time an edge crosses the ray to +x
+        let c = [p.y >= v[i].y, p.y < v[j].y, e.x * w.y > e.y * w.x];
+        if c.iter().all(|x| *x) || c.iter().all(|x| !*x) {
+            *s = -*s;
+        }
+        j = i;
+    }
+}
+
+fn polygon_sd(p: Vec2, v: &[Vec2]) -> f32 {
+    if v.is_empty() {
+        return 1e20;
+    }
+    let (mut d, mut s) = (1e20, 1.0);
+    polygon_edges(p, v, &mut d, &mut s);
+    s * d.sqrt()
+}
+
+fn contours_sd(p: Vec2, contours: &[Vec<Vec2>]) -> f32 {
+    let (mut d, mut s) = (1e20, 1.0);
+    for v in contours {
+        polygon_edges(p, v, &mut d, &mut s);
+    }
+    s * d.sqrt()
+}
+
+fn star_sd(p: Vec2, r: f32, n: f32, m: f32) -> f32 {
+    // fewer than 2 points or a sharpness outside 2 to n divides by zero below
+    let n = n.max(2.0);
+    let m = m.clamp(2.0, n);
+    let an = PI / n;
+    let en = PI / m;
+    let acs = vec2(an.cos(), an.sin());
+    let ecs = vec2(en.cos(), en.sin());
+    let bn = glsl_mod(p.x.atan2(p.y), 2.0 * an) - an;
+    let mut p = vec2(bn.cos(), bn.sin().abs()) * p.length();
+    p = p - acs * r;
+    p = p + ecs * (-p.dot(ecs)).clamp(0.0, r * acs.y / ecs.y);
+    p.length() * sign(p.x)
+}
+
+fn arc_sd(p: Vec2, aperture: f32, ra: f32, rb: f32) -> f32 {
+    let sc = vec2(aperture.sin(), aperture.cos());
+    let p = vec2(p.x.abs(), p.y);
+    let d = if sc.y * p.x > sc.x * p.y { (p - sc * ra).length() } else { (p.length() - ra).abs() };
+    d - rb
+}
+
+fn bezier_sd(pos: Vec2, a: Vec2, b: Vec2, c: Vec2, thickness: f32) -> f32 {
+    let dot2 = |v: Vec2| v.dot(v);
+    let aa = b - a;
+    let bb = a - b * 2.0 + c;
+    // a control point halfway along the chord makes a straight segment
+    if bb.dot(bb) < 1e-8 {
+        let pa = pos - a;
+        let ca = c - a;
+        return (pa - ca * (pa.dot(ca) / ca.dot(ca).max(1e-12)).clamp(0.0, 1.0)).length() - thickness;
+    }
+    let cc = aa * 2.0;
+    let d = a - pos;
+    let kk = 1.0 / bb.dot(bb);
+    let kx = kk * aa.dot(bb);
+    let ky = kk * (2.0 * aa.dot(aa) + d.dot(bb)) / 3.0;
+    let kz = kk * d.dot(aa);
+    let p = ky - kx * kx;
+    let p3 = p * p * p;
+    let q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    let h = q * q + 4.0 * p3;
+    let res = if h >= 0.0 {
+        let h = h.sqrt();
+        let x = vec2((h - q) / 2.0, (-h - q) / 2.0);
+        let uv = vec2(sign(x.x) * x.x.abs().powf(1.0 / 3.0), sign(x.y) * x.y.abs().powf(1.0 / 3.0));
+        let t = (uv.x + uv.y - kx).clamp(0.0, 1.0);
+        dot2(d + (cc + bb * t) * t)
+    } else {
+        let z = (-p).sqrt();
+        let v = (q / (p * z * 2.0)).clamp(-1.0, 1.0).acos() / 3.0;
+        let m = v.cos();
+        let n = v.sin() * 3f32.sqrt();
+        let t1 = ((m + m) * z - kx).clamp(0.0, 1.0);
+        let t2 = ((-n - m) * z - kx).clamp(0.0, 1.0);
+        dot2(d + (cc + bb * t1) * t1).min(dot2(d + (cc + bb * t2) * t2))
+    };
+    res.sqrt() - thickness
+}
+
+fn ellipse_sd(p: Vec2, ab: Vec2) -> f32 {
+    let p = p.abs();
+    let q = ab * (p - ab);
+    let mut w = if q.x < q.y { PI / 2.0 } else { 0.0 };
+    // newton iterations on the angle of the closest point
+    for _ in 0..5 {
+        let u = ab * vec2(w.cos(), w.sin());
+        let v = ab * vec2(-w.sin(), w.cos());
+        w += (p - u).dot(v) / ((p - u).dot(u) + v.dot(v));
+    }
+    let d = (p - ab * vec2(w.cos(), w.sin())).length();
+    let e = p / ab;
+    if e.dot(e) > 1.0 { d } else { -d }
+}
+
+impl Shape2D {
+    /// Signed distance from `p` to the outline, mirrors `shape_sd`.
+    pub fn distance(&self, p: Vec2) -> f32 {
+        match self {
+            Self::Circle(radius) => circle_sd(p, *radius),
+            Self::RoundedRectangle(half, radius) => rounded_rect_sd(p, *half, *radius),
+            Self::Polygon(points) => polygon_sd(p, points),
+            Self::Star(radius, points, sharpness) => star_sd(p, *radius, *points as f32, *sharpness),
+            Self::Arc(aperture, radius, thickness) => arc_sd(p, *aperture, *radius, *thickness),
+            Self::Bezier(a, b, c, thickness) => bezier_sd(p, *a, *b, *c, *thickness),
+            Self::Ellipse(radii) => ellipse_sd(p, *radii),
+            Self::Contours(contours) => contours_sd(p, contours),
+        }
+    }
+}
+
+fn extrude_sd(p: Vec3, d: f32, h: f32, r: f32) -> f32 {
+    let w = vec2(d, p.z.abs() - h);
+    w.x.max(w.y).min(0.0) + w.max(0.0).length() - r
+}
+
+// the 2D point a revolution evaluates its shape at, x away from the axis and y along it
+fn revolve_point(p: Vec3, axis: Axis, offset: f32) -> Vec2 {
+    let (i, j) = axis.others();
+    vec2(vec2(p[i], p[j]).length() - offset, p[axis])
+}
+
+
+// terrain
+
+// integer hash of a lattice point to [-1, 1], done in u32 arithmetic so the GLSL matches exactly
+fn lattice_hash(x: i32, z: i32, seed: u32) -> f32 {
+    let mut h = (x as u32).wrapping_mul(0x8da6b343) ^ (z as u32).wrapping_mul(0xd8163841) ^ seed.wrapping_mul(0xcb1ab31f);
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x7feb352d);
+    h ^= h >> 15;
+    h = h.wrapping_mul(0x846ca68b);
+    h ^= h >> 16;
+    (h >> 8) as f32 / 8388608.0 - 1.0
+}
+
+fn value_noise(p: Vec2, seed: u32) -> f32 {
+    let (ix, iz) = (p.x.floor(), p.y.floor());
+    let f = vec2(p.x - ix, p.y - iz);
+    let u = f * f * (vec2(3., 3.) - f * 2.0);
+    let (ix, iz) = (ix as i32, iz as i32);
+    let a = lattice_hash(ix, iz, seed);
+    let b = lattice_hash(ix + 1, iz, seed);
+    let c = lattice_hash(ix, iz + 1, seed);
+    let d = lattice_hash(ix + 1, iz + 1, seed);
+    a + (b - a) * u.x + (c - a) * u.y + (a - b - c + d) * u.x * u.y
+}
+
+// normalized to [-1, 1], each octave reseeded so they don't line up
+fn fbm(p: Vec2, octaves: u32, lacunarity: f32, gain: f32, seed: u32) -> f32 {
+    let (mut sum, mut amp, mut freq, mut norm) = (0.0, 1.0, 1.0, 0.0);
+    for i in 0..octaves {
+        sum += amp * value_noise(p * freq, seed.wrapping_add(i));
+        norm += amp;
+        amp *= gain;
+        freq *= lacunarity;
+    }
+    sum / f32::max(norm, 1e-6)
+}
+
+impl Heightmap {
+    /// Bilinearly filtered sample at `uv` in pixels, clamped to the image, mirrors `heightmap_height`.
+    pub fn bilinear(&self, uv: Vec2) -> f32 {
+        let (w, h) = (self.width(), self.height());
+        let u = uv.x.clamp(0.0, (w - 1) as f32);
+        let v = uv.y.clamp(0.0, (h - 1) as f32);
+        let (x0, y0) = ((u.floor() as u32).min(w.max(2) - 2), (v.floor() as u32).min(h.max(2) - 2));
+        let (x1, y1) = ((x0 + 1).min(w - 1), (y0 + 1).min(h - 1));
+        let (fx, fy) = (u - x0 as f32, v - y0 as f32);
+        let top = self.sample(x0, y0) + (self.sample(x1, y0) - self.sample(x0, y0)) * fx;
+        let bottom = self.sample(x0, y1) + (self.sample(x1, y1) - self.sample(x0, y1)) * fx;
+        top + (bottom - top) * fy
+    }
+}
+
+impl HeightSource {
+    /// Height at `xz` as a 0 to 1 fraction of the terrain's height, mirrors `terrain_height`.
+    pub fn height(&self, xz: Vec2, size: Vec2) -> f32 {
+        match self {
+            Self::Fbm(octaves, lacunarity, gain, seed, frequency) => 0.5 + 0.5 * fbm(xz * *frequency, *octaves, *lacunarity, *gain, *seed),
+            Self::Image(map) => {
+                let uv = (xz / size * 0.5 + vec2(0.5, 0.5)) * vec2((map.width() - 1) as f32, (map.height() - 1) as f32);
+                map.bilinear(uv)
+            },
+        }
+    }
+}
+
+// a column over the terrain's XZ extent, cut off by the surface, with the height
+// distance scaled down by the step factor so it stays a bound on steep slopes.
+// Above the highest point the plain height above it is a better bound
+fn terrain_sd(p: Vec3, h: f32, top: f32, step: f32, size: Vec2) -> f32 {
+    let q = vec2(p.x.abs() - size.x, p.z.abs() - size.y);
+    let column = q.max(0.0).length() + q.x.max(q.y).min(0.0);
+    column.max(((p.y - h) * step).max(p.y - top))
+}
+
+impl VoxelGrid {
+    /// Trilinearly filtered sample at `p` in the grid's box, clamped to it, mirrors `voxel_sample`.
+    pub fn trilinear(&self, p: Vec3) -> f32 {
+        let (half, res) = (self.half_size(), self.resolution());
+        let q = (p / half * 0.5 + vec3(0.5, 0.5, 0.5)).clamp(0.0, 1.0);
+        let mut i = [0u32; 3];
+        let mut f = [0f32; 3];
+        for axis in 0..3 {
+            let u = q[axis] * (res[axis] - 1) as f32;
+            i[axis] = (u.floor() as u32).min(res[axis] - 2);
+            f[axis] = u - i[axis] as f32;
+        }
+        let lerp = |a: f32, b: f32, t: f32| a + (b - a) * t;
+        let row = |y: u32, z: u32| lerp(self.sample(i[0], y, z), self.sample(i[0] + 1, y, z), f[0]);
+        let slice = |z: u32| lerp(row(i[1], z), row(i[1] + 1, z), f[1]);
+        lerp(slice(i[2]), slice(i[2] + 1), f[2])
+    }
+}
+
+// the grid inside its box, and outside it whichever bound is tighter of the distance to the
+// box and the grid's distance at the nearest point on the box less the way to that point
+fn voxel_sd(p: Vec3, d: f32, half: Vec3) -> f32 {
+    let outside = (p.abs() - half).max(0.0).length();
+    if outside > 0.0 { outside.max(d - outside) } else { d }
+}
+
+
+// rotates p about `axis` (0, 1 or 2) by `angle`
+fn rotate_about(p: Point3, axis: usize, angle: f32) -> Point3 {
+    let (s, c) = angle.sin_cos();
+    let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+    let mut q = p;
+    q[i] = c * p[i] - s * p[j];
+    q[j] = s * p[i] + c * p[j];
+    q
+}
+
+// GLSL round() is implementation defined at .5, so the shader rounds with floor
+fn repeat(x: f32, spacing: f32, limit: f32) -> f32 {
+    if spacing <= 0.0 {
+        return x;
+    }
+    x - spacing * (x / spacing + 0.5).floor().max(-limit).min(limit)
+}
+
+/// Applies a modifier packed by `Modifier::get_data` to a point, mirrors `modify_point`.
+pub(crate) fn modify_point(m: [[f32; 4]; 2], p: Point3) -> Point3 {
+    let [a, b] = m;
+    let v = vec3(a[1], a[2], a[3]);
+    match a[0] as i32 {
+        1 => vec3(repeat(p.x, v.x, f32::INFINITY), repeat(p.y, v.y, f32::INFINITY), repeat(p.z, v.z, f32::INFINITY)),
+        2 => vec3(repeat(p.x, v.x, b[0]), repeat(p.y, v.y, b[1]), repeat(p.z, v.z, b[2])),
+        3 => {
+            let h = p.dot(v) - b[0];
+            if h < 0.0 { p - v * (2.0 * h) } else { p }
+        },
+        4 => rotate_about(p, a[1] as usize, a[2] * p[a[1] as usize]),
+        5 => rotate_about(p, a[1] as usize, a[2] * p[(a[1] as usize + 1) % 3]),
+        6 => p - vec3(p.x.max(-v.x).min(v.x), p.y.max(-v.y).min(v.y), p.z.max(-v.z).min(v.z)),
+        _ => p,
+    }
+}
+
+/// Applies the distance part of a packed modifier, mirrors `modify_distance`. `p` is the
+/// point the modifier was applied at.
+pub(crate) fn modify_distance(m: [[f32; 4]; 2], p: Point3, d: f32) -> f32 {
+    let a = m[0];
+    // how far twists and bends move a point, per unit it moves along the axis they vary over
+    let stretch = || {
+        let axis = a[1] as usize;
+        a[2].abs() * vec2(p[(axis + 1) % 3], p[(axis + 2) % 3]).length()
+    };
+    match a[0] as i32 {
+        // divided by the largest stretch of space, so the distance stays a bound
+        4 => {
+            let s = stretch();
+            d * 2.0 / (s + (s * s + 4.0).sqrt())
+        },
+        5 => d / (1.0 + stretch()),
+        7 => d.abs() - a[1],
+        8 => d - a[1],
+        _ => d,
+    }
+}
+
+/// The distance part of `count` packed modifiers read by `modifier`, innermost first, mirrors
+/// `modify_distances`. Twists and bends need the point they were applied at, which is worked
+/// out again from `p`, the point before any of the modifiers.
+fn modify_distances(modifier: impl Fn(usize) -> [[f32; 4]; 2], count: usize, p: Point3, d: f32) -> f32 {
+    (0..count).rev().fold(d, |d, m| {
+        let data = modifier(m);
+        let at = match data[0][0] as i32 {
+            4 | 5 => (0..m).fold(p, |p, n| modify_point(modifier(n), p)),
+            _ => p,
+        };
+        modify_distance(data, at, d)
+    })
+}
+
+impl Primitive {
+    /// Signed distance from `p`, given in the primitive's own space, to its surface.
+    pub fn distance(&self, p: Point3) -> f32 {
+        self.distance_and_trap(p).0
+    }
+    /// The distance along with the orbit trap the fractals are coloured by, 0 for the rest.
+    pub fn distance_and_trap(&self, p: Point3) -> (f32, f32) {
+        let dist = match *self {
+            Self::Sphere(rad) => sphere_sd(p, rad),
+            Self::Plane(normal, k) => plane_sd(p, normal, k),
+            Self::Cuboid(dims, rounding) => box_sd(p, dims, rounding),
+            Self::BoxFrame(dims, edge) => boxframe_sd(p, dims, edge),
+            Self::Torus(in_rad, out_rad) => torus_sd(p, in_rad, out_rad),
+            Self::Horseshoe(in_rad, out_rad, capa, capb) => capped_torus_sd(p, capa, capb, in_rad, out_rad),
+            Self::Link(length, rad1, rad2) => link_sd(p, length, rad1, rad2),
+            Self::Cone(angle, height) => cone_sd(p, angle, height),
+            Self::HexagonalPrism(radius, height) => hex_prism_sd(p, radius, height),
+            Self::TriangularPrism(radius, height) => tri_prism_sd(p, radius, height),
+            Self::Capsule(height, radius) => capsule_sd(p, height, radius),
+            Self::CappedCylinder(height, radius) => capped_cylinder_sd(p, height, radius),
+            Self::RoundedCylinder(rad1, rad2, height) => round_cylinder_sd(p, rad1, rad2, height),
+            Self::CappedCone(height, rad1, rad2) => capped_cone_sd(p, height, rad1, rad2),
+            Self::SolidAngle(angle, rounding) => solid_angle_sd(p, angle, rounding),
+            Self::CutSphere(radius, height) => cut_sphere_sd(p, radius, height),
+            Self::CutHollowSphere(radius, height, thickness) => cut_hollow_sphere_sd(p, radius, height, thickness),
+            Self::DeathStar(radius1, radius2, d) => death_star_sd(p, radius1, radius2, d),
+            Self::RoundCone(rad1, rad2, height) => round_cone_sd(p, rad1, rad2, height),
+            Self::Ellipsoid(radii) => ellipsoid_sd(p, radii),
+            Self::Rhombus(la, lb, height, ra) => rhombus_sd(p, la, lb, height, ra),
+            Self::Octahedron(side) => octahedron_sd(p, side),
+            Self::Pyramid(height) => pyramid_sd(p, height),
+            Self::Triangle(p1, p2, p3) => triangle_sd(p, p1, p2, p3),
+            Self::Mandelbulb(power, iterations, bailout, _) => return mandelbulb_sd(p, power, iterations, bailout),
+            Self::MengerSponge(size, iterations, _) => return menger_sd(p, size, iterations),
+            Self::SierpinskiTetrahedron(size, iterations, _) => return sierpinski_sd(p, size, iterations),
+            Self::JuliaQuaternion(c, iterations, bailout, _) => return julia_sd(p, c, iterations, bailout),
+            Self::Extrude(ref shape, depth, rounding) => extrude_sd(p, shape.distance(vec2(p.x, p.y)), depth, rounding),
+            Self::Revolve(ref shape, axis, offset) => shape.distance(revolve_point(p, axis, offset)),
+            Self::Text(ref text, depth, bevel) => {
+                // inset by the bevel, so rounding the edges doesn't thicken the glyphs
+                let bevel = bevel.min(depth);
+                extrude_sd(p, text.outline().distance(vec2(p.x, p.y)) + bevel, depth - bevel, bevel)
+            },
+            Self::Terrain(ref source, size, height) => terrain_sd(p, height * source.height(vec2(p.x, p.z), size), height, source.step(size, height), size),
+            Self::VoxelSdf(ref grid) => voxel_sd(p, grid.trilinear(p), grid.half_size()),
+        };
+        (dist, 0.0)
+    }
+}
+
+impl Transform {
+    /// Maps a world space point into the object's space with the matrix from `get_data`, as `get_sd` does.
+    pub fn local_point(&self, p: Point3) -> Point3 {
+        apply_matrix(&self.get_data(), p)
+    }
+}
+
+fn apply_matrix(m: &[[f32; 4]; 4], p: Point3) -> Point3 {
+    vec3(
+        m[0][0] * p.x + m[1][0] * p.y + m[2][0] * p.z + m[3][0],
+        m[0][1] * p.x + m[1][1] * p.y + m[2][1] * p.z + m[3][1],
+        m[0][2] * p.x + m[1][2] * p.y + m[2][2] * p.z + m[3][2],
+    )
+}
+
+impl Scene {
+    /// Mirrors `get_sd` for the object at `index` in the object list, placed by `program`.
+    pub fn object_sd(&self, program: &CsgProgram, index: usize, p: Point3) -> SdfResult {
+        let transform = &program.transforms[index];
+        let modifiers = &self.obj_modifiers[index];
+        let start = apply_matrix(transform, p);
+        let local = modifiers.iter().fold(start, |p, m| modify_point(m.get_data(), p));
+        let (dist, trap) = self.objects[index].distance_and_trap(local);
+        let dist = modify_distances(|m| modifiers[m].get_data(), modifiers.len(), start, dist);
+        let material = self.obj_materials[index];
+        SdfResult {
+            distance: dist * transform[3][3],
+            material_a: material,
+            material_b: self.objects[index].trap_material().unwrap_or(material),
+            blend: trap,
+        }
+    }
+    /// Runs a program from `csg_program` the way `scene_sd` does. Callers evaluating many
+    /// points can compile the program once and reuse it.
+    /// Returns `None` for an empty scene, where the shader reports a miss.
+    pub fn evaluate_program(&self, program: &CsgProgram, p: Point3) -> Option<SdfResult> {
+        self.run_program(program, p, |_, _| {})
+    }
+    /// Distance to each object's own surface where it is used in `program`, so with the
+    /// point moved by the modifiers of the ops around it. Indexed like `objects`.
+    pub fn object_distances(&self, program: &CsgProgram, p: Point3) -> Vec<f32> {
+        let mut distances = vec![1e20f32; self.objects.len()];
+        self.run_program(program, p, |index, res| distances[index] = distances[index].min(res.distance.abs()));
+        distances
+    }
+    fn run_program(&self, csg: &CsgProgram, p: Point3, mut on_object: impl FnMut(usize, &SdfResult)) -> Option<SdfResult> {
+        let program = &csg.instructions;
+        let mut stack: Vec<SdfResult> = Vec::with_capacity(MAX_CSG_STACK);
+        let mut points: Vec<Point3> = Vec::with_capacity(MAX_CSG_STACK);
+        let mut p = p;
+        let modifier = |start: usize, i: usize| [program[start + 1 + 2 * i], program[start + 2 + 2 * i]];
+        let mut i = 0;
+        while i < program.len() {
+            let ins = program[i];
+            match ins[0] as i32 {
+                1 => {
+                    let res = self.object_sd(csg, ins[1] as usize, p);
+                    on_object(ins[1] as usize, &res);
+                    stack.push(res);
+                    i += 2 * ins[2] as usize;
+                },
+                2 => {
+                    let b = stack.pop().unwrap();
+                    let a = stack.pop().unwrap();
+                    stack.push(bool_op_sd([ins[1], ins[2], ins[3]], a, b));
+                },
+                3 => {
+                    points.push(p);
+                    p = (0..ins[1] as usize).fold(p, |p, m| modify_point(modifier(i, m), p));
+                    i += 2 * ins[1] as usize;
+                },
+                _ => {
+                    let top = stack.last_mut().unwrap();
+                    p = points.pop().unwrap();
+                    top.distance = modify_distances(|m| modifier(ins[1] as usize, m), ins[2] as usize, p, top.distance);
+                },
+            }
+            i += 1;
+        }
+        stack.first().copied()
+    }
+    /// Compiles the scene and evaluates it at `p`. Callers evaluating many points should
+    /// compile it once with `csg_program` and use `evaluate_program`.
+    pub fn evaluate(&self, p: Point3) -> Option<SdfResult> {
+        self.evaluate_program(&self.csg_program(), p)
+    }
+    /// Signed distance from `p` to the scene, `1e20` if the scene is empty. This compiles the
+    /// scene on every call, so in a loop use `csg_program` and `evaluate_program` instead.
+    pub fn distance(&self, p: Point3) -> f32 {
+        self.evaluate(p).map_or(1e20, |res| res.distance)
+    }
+    /// Samples the distance to the objects under `node` (see `subtree_program`) on a grid of
+    /// `resolution` points spanning the box of half size `half_size` around `centre` in world
+    /// space. A `Primitive::VoxelSdf` of the result placed at `centre` stands in for the subtree.
+    pub fn bake(&self, node: SceneNode, centre: Point3, half_size: Vec3, resolution: [u32; 3]) -> VoxelGrid {
+        let program = self.subtree_program(node);
+        let [w, h, d] = resolution.map(|n| n.max(2));
+        let cell = |i: u32, n: u32, half: f32| -half + 2.0 * half * i as f32 / (n - 1) as f32;
+        let mut samples = Vec::with_capacity((w * h * d) as usize);
+        for z in 0..d {
+            for y in 0..h {
+                for x in 0..w {
+                    let p = centre + vec3(cell(x, w, half_size.x), cell(y, h, half_size.y), cell(z, d, half_size.z));
+                    samples.push(self.evaluate_program(&program, p).map_or(1e20, |res| res.distance));
+                }
+            }
+        }
+        VoxelGrid::new([w, h, d], half_size, samples)
+    }
+}
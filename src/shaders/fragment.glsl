@@ -1,11 +1,10 @@
 #version 410
-#define MAX_OBJECTS 32
-#define MAX_BOOL_OPS 32
-#define MAX_LIGHTS 8
+#define MAX_CSG_STACK 16
 
 #define MAX_STEPS 128
 #define MAX_DIST 100.0
 #define MIN_DIST 0.0001
+#define PI 3.14159265359
 
 
 in vec2 fragCoord;
@@ -23,11 +22,73 @@ uniform scene_fog_color { vec4 fog_color; };
 uniform scene_params { vec4 params; };
 uniform scene_consts { vec4 consts; };
 
-uniform scene_objects { mat4 objects[MAX_OBJECTS]; };
-uniform scene_lights { vec4 lights[MAX_LIGHTS]; };
-uniform scene_bool_ops { vec2 bool_ops[MAX_BOOL_OPS]; };
-uniform scene_transforms { mat4 transformations[MAX_BOOL_OPS]; };
+// consts = (object count, csg instruction count, light count, -)
+// objects and transforms are 4 texels (one mat4) per object, csg one texel per instruction
+uniform samplerBuffer scene_objects;
+uniform samplerBuffer scene_transforms;
+uniform samplerBuffer scene_csg;
+// four texels per light, see Light::get_data
+uniform samplerBuffer scene_lights;
+// three texels per material, see Material::get_data
+uniform samplerBuffer scene_materials;
+// 2D shapes of extrusions and revolutions, see Shape2D::get_data
+uniform samplerBuffer scene_shapes;
+// terrain height images, four samples to a texel, see Heightmap::get_data
+uniform samplerBuffer scene_heightmaps;
+// distance grids stacked along z, filtered trilinearly, see Scene::get_voxels
+uniform sampler3D scene_voxels;
+
+// Distance results are vec4(distance, material a, material b, blend), where the surface
+// material is mix(material a, material b, blend). A miss has material a = -1.
+struct Material {
+    vec3 albedo;
+    float roughness;
+    float metallic;
+    float specular;
+    vec3 emissive;
+    float opacity;
+};
+
+
+mat4 fetch_mat4(samplerBuffer buf, int index) {
+    return mat4(
+        texelFetch(buf, index * 4),
+        texelFetch(buf, index * 4 + 1),
+        texelFetch(buf, index * 4 + 2),
+        texelFetch(buf, index * 4 + 3)
+    );
+}
+
+
+Material fetch_material(int index) {
+    vec4 a = texelFetch(scene_materials, index * 3);
+    vec4 b = texelFetch(scene_materials, index * 3 + 1);
+    vec4 c = texelFetch(scene_materials, index * 3 + 2);
+    return Material(a.rgb, a.w, b.w, c.x, b.rgb, c.y);
+}
+
+Material get_material(vec3 m) {
+    Material a = fetch_material(int(m.x));
+    if (m.z <= 0.0) return a;
+    Material b = fetch_material(int(m.y));
+    float t = m.z;
+    return Material(
+        mix(a.albedo, b.albedo, t),
+        mix(a.roughness, b.roughness, t),
+        mix(a.metallic, b.metallic, t),
+        mix(a.specular, b.specular, t),
+        mix(a.emissive, b.emissive, t),
+        mix(a.opacity, b.opacity, t)
+    );
+}
 
+// Blends the materials of two results with weight h towards b. Results that are
+// already blends collapse to their dominant material first.
+vec3 mix_materials(vec4 a, vec4 b, float h) {
+    if (h <= 0.0) return a.yzw;
+    if (h >= 1.0) return b.yzw;
+    return vec3((a.w < 0.5) ? a.y : a.z, (b.w < 0.5) ? b.y : b.z, h);
+}
 
 vec4 op_union(vec4 a, vec4 b) {
     return (a.x < b.x) ? a : b;
@@ -36,41 +97,63 @@ vec4 op_intersect(vec4 a, vec4 b) {
     return (a.x > b.x) ? a : b;
 }
 vec4 op_difference(vec4 a, vec4 b) {
-    return (a.x > -b.x) ? a : -b;
+    return (a.x > -b.x) ? a : vec4(-b.x, b.yzw);
 }
-vec4 op_smooth_union(vec4 a, vec4 b, float k) {
-    float t = -(log(exp(k * -a.x) + exp(k * -b.x)) / k);
-    return vec4(t, mix(a.yzw, b.yzw, (t - a.x) / (-b.x - a.x)));
+// h is how much of b's material shows, across a band of width 2k around where a and b meet
+float blend_weight(float a, float b, float k) {
+    return clamp(0.5 + 0.5 * (a - b) / max(k, 1e-6), 0.0, 1.0);
 }
 
-mat4 rotate_mat(vec3 theta) {
-    float yaw = theta.x;
-    float pitch = theta.y;
-    float roll = theta.z;
-
-    float cos_yaw = cos(yaw);
-    float sin_yaw = sin(yaw);
-    float cos_pitch = cos(pitch);
-    float sin_pitch = sin(pitch);
-    float cos_roll = cos(roll);
-    float sin_roll = sin(roll);
-
-    return mat4(
-        vec4(cos_yaw, -sin_yaw, 0.0, 0.0),
-        vec4(sin_yaw, cos_yaw, 0.0, 0.0),
-        vec4(0.0, 0.0, 1.0, 0.0),
-        vec4(0.0, 0.0, 0.0, 1.0)
-    ) * mat4(
-        vec4(cos_pitch, 0.0, sin_pitch, 0.0),
-        vec4(0.0, 1.0, 0.0, 0.0),
-        vec4(-sin_pitch, 0.0, cos_pitch, 0.0),
-        vec4(0.0, 0.0, 0.0, 1.0)
-    ) * mat4(
-        vec4(1.0, 0.0, 0.0, 0.0),
-        vec4(0.0, cos_roll, -sin_roll, 0.0),
-        vec4(0.0, sin_roll, cos_roll, 0.0),
-        vec4(0.0, 0.0, 0.0, 1.0)
-    );
+vec4 op_smooth_union(vec4 a, vec4 b, float k) {
+    k = max(k, 1e-6);
+    float h = blend_weight(a.x, b.x, k);
+    float t = mix(a.x, b.x, h) - k * h * (1.0 - h);
+    return vec4(t, mix_materials(a, b, h));
+}
+vec4 op_smooth_intersect(vec4 a, vec4 b, float k) {
+    k = max(k, 1e-6);
+    float h = blend_weight(b.x, a.x, k);
+    float t = mix(a.x, b.x, h) + k * h * (1.0 - h);
+    return vec4(t, mix_materials(a, b, h));
+}
+vec4 op_smooth_difference(vec4 a, vec4 b, float k) {
+    k = max(k, 1e-6);
+    float h = blend_weight(-b.x, a.x, k);
+    float t = mix(a.x, -b.x, h) + k * h * (1.0 - h);
+    return vec4(t, mix_materials(a, b, h));
+}
+vec4 op_chamfer(vec4 a, vec4 b, float r) {
+    float t = min(min(a.x, b.x), (a.x - r + b.x) * sqrt(0.5));
+    return vec4(t, mix_materials(a, b, blend_weight(a.x, b.x, r)));
+}
+vec4 op_stairs(vec4 a, vec4 b, float r, float n) {
+    float s = r / max(n, 1.0);
+    float u = b.x - r;
+    float t = min(min(a.x, b.x), 0.5 * (u + a.x + abs(mod(u - a.x + s, 2.0 * s) - s)));
+    return vec4(t, mix_materials(a, b, blend_weight(a.x, b.x, r)));
+}
+vec4 op_columns(vec4 a, vec4 b, float r, float n) {
+    float t = min(a.x, b.x);
+    if (a.x < r && b.x < r) {
+        n = max(n, 1.0);
+        float column_radius = r * sqrt(2.0) / ((n - 1.0) * 2.0 + sqrt(2.0));
+        // rotate 45 degrees and move onto the diagonal the columns sit on
+        vec2 p = vec2(a.x + b.x, b.x - a.x) * sqrt(0.5);
+        p.x += -sqrt(2.0) / 2.0 * r + column_radius * sqrt(2.0);
+        if (mod(n, 2.0) == 1.0) {
+            p.y += column_radius;
+        }
+        p.y = mod(p.y + column_radius, column_radius * 2.0) - column_radius;
+        t = min(min(t, length(p) - column_radius), p.x);
+    }
+    return vec4(t, mix_materials(a, b, blend_weight(a.x, b.x, r)));
+}
+vec4 op_xor(vec4 a, vec4 b) {
+    return op_difference(op_union(a, b), op_intersect(a, b));
+}
+vec4 op_morph(vec4 a, vec4 b, float t) {
+    t = clamp(t, 0.0, 1.0);
+    return vec4(mix(a.x, b.x, t), mix_materials(a, b, t));
 }
 
 float sphere_sd(vec3 p, float r) {
@@ -235,7 +318,7 @@ float ellipsoid_sd(vec3 p, vec3 r) {
 float rhombus_sd(vec3 p, float la, float lb, float h, float ra) {
     p = abs(p);
     vec2 b = vec2(la,lb);
-    float f = clamp( (normalize(dot(b,b-2.0*p.xz)))/dot(b,b), -1.0, 1.0 );
+    float f = clamp( sign(dot(b,b-2.0*p.xz))/dot(b,b), -1.0, 1.0 );
     vec2 q = vec2(length(p.xz-0.5*b*vec2(1.0-f,1.0+f))*sign(p.x*b.y+p.z*b.x-b.x*b.y)-ra, p.y-h);
     return min(max(q.x,q.y),0.0) + length(max(q,0.0));
 }
@@ -297,13 +380,448 @@ float triangle_sd(vec3 p, vec3 a, vec3 b, vec3 c) {
 }
 
 
+// the fractals return (distance, orbit trap), with the trap in [0, 1]
+
+vec2 mandelbulb_sd(vec3 p, float power, int iterations, float bailout) {
+    vec3 w = p;
+    float m = dot(w, w);
+    float dz = 1.0;
+    float trap = m;
+    for (int i = 0; i < iterations; i++) {
+        dz = power * pow(m, (power - 1.0) * 0.5) * dz + 1.0;
+        float r = max(sqrt(m), 1e-6);
+        float b = power * acos(clamp(w.y / r, -1.0, 1.0));
+        float a = power * atan(w.x, w.z);
+        w = p + vec3(sin(b) * sin(a), cos(b), sin(b) * cos(a)) * pow(r, power);
+        m = dot(w, w);
+        trap = min(trap, m);
+        if (m > bailout * bailout) {
+            break;
+        }
+    }
+    // points that never leave the origin would take the log of 0
+    m = max(m, 1e-12);
+    return vec2(0.25 * log(m) * sqrt(m) / dz, clamp(sqrt(trap), 0.0, 1.0));
+}
+
+vec2 menger_sd(vec3 p, float size, int iterations) {
+    p /= size;
+    float d = box_sd(p, vec3(1.0), 0.0);
+    float s = 1.0;
+    float trap = 0.0;
+    for (int i = 0; i < iterations; i++) {
+        vec3 a = mod(p * s, 2.0) - 1.0;
+        s *= 3.0;
+        vec3 r = abs(1.0 - 3.0 * abs(a));
+        float da = max(r.x, r.y);
+        float db = max(r.y, r.z);
+        float dc = max(r.z, r.x);
+        float c = (min(da, min(db, dc)) - 1.0) / s;
+        // the trap is the level that carved the surface here
+        if (c > d) {
+            d = c;
+            trap = float(i + 1) / float(iterations);
+        }
+    }
+    return vec2(d * size, trap);
+}
+
+vec2 sierpinski_sd(vec3 p, float size, int iterations) {
+    p /= size;
+    float trap = dot(p, p);
+    for (int i = 0; i < iterations; i++) {
+        // fold towards the (1, 1, 1) corner, then scale about it
+        if (p.x + p.y < 0.0) p.xy = -p.yx;
+        if (p.x + p.z < 0.0) p.xz = -p.zx;
+        if (p.y + p.z < 0.0) p.yz = -p.zy;
+        p = p * 2.0 - 1.0;
+        trap = min(trap, dot(p, p));
+    }
+    float tetra = max(max(-p.x - p.y - p.z, p.x + p.y - p.z), max(-p.x + p.y + p.z, p.x - p.y + p.z));
+    float d = (tetra - 1.0) / sqrt(3.0) * pow(2.0, -float(iterations));
+    return vec2(d * size, clamp(sqrt(trap) / sqrt(3.0), 0.0, 1.0));
+}
+
+// quaternions are (xyz, w) with w the real part, like Quat. p is the slice through the
+// real, i and j axes, the purely imaginary slice is a plain blob
+vec2 julia_sd(vec3 p, vec4 c, int iterations, float bailout) {
+    vec4 z = vec4(p.y, p.z, 0.0, p.x);
+    float md2 = 1.0;
+    float mz2 = dot(z, z);
+    float trap = mz2;
+    for (int i = 0; i < iterations; i++) {
+        md2 *= 4.0 * mz2;
+        z = vec4(2.0 * z.w * z.xyz, z.w * z.w - dot(z.xyz, z.xyz)) + c;
+        mz2 = dot(z, z);
+        trap = min(trap, mz2);
+        if (mz2 > bailout * bailout) {
+            break;
+        }
+    }
+    // points that never leave the origin would divide 0 by 0
+    mz2 = max(mz2, 1e-12);
+    md2 = max(md2, 1e-12);
+    return vec2(0.25 * sqrt(mz2 / md2) * log(mz2), clamp(sqrt(trap), 0.0, 1.0));
+}
+
+
+// 2D shapes, in the XY plane
+
+float circle_sd(vec2 p, float r) {
+    return length(p) - r;
+}
+
+float rounded_rect_sd(vec2 p, vec2 b, float r) {
+    vec2 q = abs(p) - b + r;
+    return min(max(q.x, q.y), 0.0) + length(max(q, 0.0)) - r;
+}
+
+// folds the edges of the polygon at `start` (vertices two to a texel) into the squared
+// distance `d` and the inside sign `s`
+void polygon_edges(vec2 p, int start, int n, inout float d, inout float s) {
+    vec4 last = texelFetch(scene_shapes, start + (n - 1) / 2);
+    vec2 vj = (n % 2 == 1) ? last.xy : last.zw;
+    for (int i = 0; i < n; i++) {
+        vec4 pair = texelFetch(scene_shapes, start + i / 2);
+        vec2 vi = (i % 2 == 0) ? pair.xy : pair.zw;
+        vec2 e = vj - vi;
+        vec2 w = p - vi;
+        vec2 b = w - e * clamp(dot(w, e) / max(dot(e, e), 1e-12), 0.0, 1.0);
+        d = min(d, dot(b, b));
+        // crossing number, flipped each time an edge crosses the ray to +x
+        bvec3 c = bvec3(p.y >= vi.y, p.y < vj.y, e.x * w.y > e.y * w.x);
+        if (all(c) || all(not(c))) {
+            s = -s;
+        }
+        vj = vi;
+    }
+}
+
+float polygon_sd(vec2 p, int start, int n) {
+    if (n == 0) {
+        return 1e20;
+    }
+    float d = 1e20;
+    float s = 1.0;
+    polygon_edges(p, start, n, d, s);
+    return s * sqrt(d);
+}
+
+float contours_sd(vec2 p, int start, int count) {
+    float d = 1e20;
+    float s = 1.0;
+    int t = start;
+    for (int k = 0; k < count; k++) {
+        int n = int(texelFetch(scene_shapes, t).x);
+        vec4 bounds = texelFetch(scene_shapes, t + 1);
+        int points = t + 2;
+        t = points + (n + 1) / 2;
+        // contours that are further than the closest edge so far and can't change the
+        // sign (the ray to +x misses them) are skipped
+        vec2 q = max(max(bounds.xy - p, p - bounds.zw), 0.0);
+        bool crossed = p.y >= bounds.y && p.y < bounds.w && p.x <= bounds.z;
+        if (crossed || dot(q, q) < d) {
+            polygon_edges(p, points, n, d, s);
+        }
+    }
+    return s * sqrt(d);
+}
+
+float star_sd(vec2 p, float r, float n, float m) {
+    // fewer than 2 points or a sharpness outside 2 to n divides by zero below
+    n = max(n, 2.0);
+    m = clamp(m, 2.0, n);
+    float an = PI / n;
+    float en = PI / m;
+    vec2 acs = vec2(cos(an), sin(an));
+    vec2 ecs = vec2(cos(en), sin(en));
+    float bn = mod(atan(p.x, p.y), 2.0 * an) - an;
+    p = length(p) * vec2(cos(bn), abs(sin(bn)));
+    p -= r * acs;
+    p += ecs * clamp(-dot(p, ecs), 0.0, r * acs.y / ecs.y);
+    return length(p) * sign(p.x);
+}
+
+float arc_sd(vec2 p, float aperture, float ra, float rb) {
+    vec2 sc = vec2(sin(aperture), cos(aperture));
+    p.x = abs(p.x);
+    return ((sc.y * p.x > sc.x * p.y) ? length(p - sc * ra) : abs(length(p) - ra)) - rb;
+}
+
+float bezier_sd(vec2 pos, vec2 A, vec2 B, vec2 C, float thickness) {
+    vec2 a = B - A;
+    vec2 b = A - 2.0 * B + C;
+    // a control point halfway along the chord makes a straight segment
+    if (dot(b, b) < 1e-8) {
+        vec2 pa = pos - A;
+        vec2 ca = C - A;
+        return length(pa - ca * clamp(dot(pa, ca) / max(dot(ca, ca), 1e-12), 0.0, 1.0)) - thickness;
+    }
+    vec2 c = a * 2.0;
+    vec2 d = A - pos;
+    float kk = 1.0 / dot(b, b);
+    float kx = kk * dot(a, b);
+    float ky = kk * (2.0 * dot(a, a) + dot(d, b)) / 3.0;
+    float kz = kk * dot(d, a);
+    float res = 0.0;
+    float p = ky - kx * kx;
+    float p3 = p * p * p;
+    float q = kx * (2.0 * kx * kx - 3.0 * ky) + kz;
+    float h = q * q + 4.0 * p3;
+    if (h >= 0.0) {
+        h = sqrt(h);
+        vec2 x = (vec2(h, -h) - q) / 2.0;
+        vec2 uv = sign(x) * pow(abs(x), vec2(1.0 / 3.0));
+        float t = clamp(uv.x + uv.y - kx, 0.0, 1.0);
+        vec2 e = d + (c + b * t) * t;
+        res = dot(e, e);
+    } else {
+        float z = sqrt(-p);
+        float v = acos(clamp(q / (p * z * 2.0), -1.0, 1.0)) / 3.0;
+        float m = cos(v);
+        float n = sin(v) * sqrt(3.0);
+        vec2 t = clamp(vec2(m + m, -n - m) * z - kx, 0.0, 1.0);
+        vec2 e1 = d + (c + b * t.x) * t.x;
+        vec2 e2 = d + (c + b * t.y) * t.y;
+        res = min(dot(e1, e1), dot(e2, e2));
+    }
+    return sqrt(res) - thickness;
+}
 
-vec4 get_sd(vec3 pos, mat4 obj, int index) {
+float ellipse_sd(vec2 p, vec2 ab) {
+    p = abs(p);
+    vec2 q = ab * (p - ab);
+    float w = (q.x < q.y) ? PI / 2.0 : 0.0;
+    // newton iterations on the angle of the closest point
+    for (int i = 0; i < 5; i++) {
+        vec2 u = ab * vec2(cos(w), sin(w));
+        vec2 v = ab * vec2(-sin(w), cos(w));
+        w += dot(p - u, v) / (dot(p - u, u) + dot(v, v));
+    }
+    float d = length(p - ab * vec2(cos(w), sin(w)));
+    return (dot(p / ab, p / ab) > 1.0) ? d : -d;
+}
+
+// `start` is the shape's first texel in scene_shapes
+float shape_sd(vec2 p, int start) {
+    vec4 a = texelFetch(scene_shapes, start);
+    vec4 b = texelFetch(scene_shapes, start + 1);
+    int kind = int(a.x);
+    if (kind == 1) { // circle
+        return circle_sd(p, a.y);
+    } else if (kind == 2) { // rounded rectangle
+        return rounded_rect_sd(p, a.yz, a.w);
+    } else if (kind == 3) { // polygon
+        return polygon_sd(p, start + 2, int(a.y));
+    } else if (kind == 4) { // star
+        return star_sd(p, a.y, a.z, a.w);
+    } else if (kind == 5) { // arc
+        return arc_sd(p, a.y, a.z, a.w);
+    } else if (kind == 6) { // bezier
+        return bezier_sd(p, b.xy, b.zw, a.zw, a.y);
+    } else if (kind == 7) { // ellipse
+        return ellipse_sd(p, a.yz);
+    } else if (kind == 8) { // contours
+        return contours_sd(p, start + 2, int(a.y));
+    }
+    return 1e20;
+}
+
+float extrude_sd(vec3 p, float d, float h, float r) {
+    vec2 w = vec2(d, abs(p.z) - h);
+    return min(max(w.x, w.y), 0.0) + length(max(w, 0.0)) - r;
+}
+
+// the 2D point a revolution evaluates its shape at, x away from the axis and y along it
+vec2 revolve_point(vec3 p, int axis, float offset) {
+    return vec2(length(vec2(p[(axis + 1) % 3], p[(axis + 2) % 3])) - offset, p[axis]);
+}
+
+
+// terrain
+
+// integer hash of a lattice point to [-1, 1], in uint arithmetic to match the CPU exactly
+float lattice_hash(int x, int z, uint seed) {
+    uint h = (uint(x) * 0x8da6b343u) ^ (uint(z) * 0xd8163841u) ^ (seed * 0xcb1ab31fu);
+    h ^= h >> 16;
+    h *= 0x7feb352du;
+    h ^= h >> 15;
+    h *= 0x846ca68bu;
+    h ^= h >> 16;
+    return float(h >> 8) / 8388608.0 - 1.0;
+}
+
+float value_noise(vec2 p, uint seed) {
+    vec2 i = floor(p);
+    vec2 f = p - i;
+    vec2 u = f * f * (3.0 - 2.0 * f);
+    int ix = int(i.x);
+    int iz = int(i.y);
+    float a = lattice_hash(ix, iz, seed);
+    float b = lattice_hash(ix + 1, iz, seed);
+    float c = lattice_hash(ix, iz + 1, seed);
+    float d = lattice_hash(ix + 1, iz + 1, seed);
+    return a + (b - a) * u.x + (c - a) * u.y + (a - b - c + d) * u.x * u.y;
+}
+
+// normalized to [-1, 1], each octave reseeded so they don't line up
+float fbm(vec2 p, int octaves, float lacunarity, float gain, uint seed) {
+    float sum = 0.0;
+    float amp = 1.0;
+    float freq = 1.0;
+    float norm = 0.0;
+    for (int i = 0; i < octaves; i++) {
+        sum += amp * value_noise(p * freq, seed + uint(i));
+        norm += amp;
+        amp *= gain;
+        freq *= lacunarity;
+    }
+    return sum / max(norm, 1e-6);
+}
+
+float heightmap_sample(int start, int width, int x, int y) {
+    int k = y * width + x;
+    return texelFetch(scene_heightmaps, start + k / 4)[k % 4];
+}
+
+// bilinearly filtered, with uv in pixels and clamped to the image
+float heightmap_height(int start, int width, int height, vec2 uv) {
+    uv = clamp(uv, vec2(0.0), vec2(width - 1, height - 1));
+    int x0 = min(int(floor(uv.x)), max(width, 2) - 2);
+    int y0 = min(int(floor(uv.y)), max(height, 2) - 2);
+    int x1 = min(x0 + 1, width - 1);
+    int y1 = min(y0 + 1, height - 1);
+    vec2 f = uv - vec2(x0, y0);
+    float top = mix(heightmap_sample(start, width, x0, y0), heightmap_sample(start, width, x1, y0), f.x);
+    float bottom = mix(heightmap_sample(start, width, x0, y1), heightmap_sample(start, width, x1, y1), f.x);
+    return mix(top, bottom, f.y);
+}
+
+// height at xz as a 0 to 1 fraction, see Primitive::Terrain in as_data for the layout
+float terrain_height(vec2 xz, mat4 obj) {
+    vec2 size = obj[1].xy;
+    if (obj[0][3] == 0.0) {
+        return 0.5 + 0.5 * fbm(xz * obj[1][2], int(obj[2][0]), obj[2][1], obj[2][2], uint(obj[2][3]));
+    }
+    int width = int(obj[2][0]);
+    int height = int(obj[2][1]);
+    vec2 uv = (xz / size * 0.5 + 0.5) * vec2(width - 1, height - 1);
+    return heightmap_height(int(obj[3][2]), width, height, uv);
+}
+
+// a column over the terrain's XZ extent, cut off by the surface, with the height
+// distance scaled down by the step factor so it stays a bound on steep slopes.
+// Above the highest point the plain height above it is a better bound
+float terrain_sd(vec3 p, float h, float top, float step, vec2 size) {
+    vec2 q = abs(p.xz) - size;
+    float column = length(max(q, 0.0)) + min(max(q.x, q.y), 0.0);
+    return max(column, max((p.y - h) * step, p.y - top));
+}
+
+// filtered at p, clamped to the grid's box, see Primitive::VoxelSdf in as_data for the layout.
+// Sampling between texel centres keeps the filter off the neighbouring grids in the stack
+float voxel_sample(vec3 p, mat4 obj) {
+    vec3 half_size = obj[0].yzw;
+    vec3 texel = clamp(p / half_size * 0.5 + 0.5, 0.0, 1.0) * (obj[1].xyz - 1.0) + 0.5;
+    texel.z += obj[3][2];
+    return texture(scene_voxels, texel / vec3(textureSize(scene_voxels, 0))).r;
+}
+
+// the grid inside its box, and outside it whichever bound is tighter of the distance to the
+// box and the grid's distance at the nearest point on the box less the way to that point
+float voxel_sd(vec3 p, float d, vec3 half_size) {
+    float outside = length(max(abs(p) - half_size, 0.0));
+    return outside > 0.0 ? max(outside, d - outside) : d;
+}
+
+
+// rotates p about `axis` (0, 1 or 2) by `angle`
+vec3 rotate_about(vec3 p, int axis, float angle) {
+    float c = cos(angle);
+    float s = sin(angle);
+    int i = (axis + 1) % 3;
+    int j = (axis + 2) % 3;
+    vec3 q = p;
+    q[i] = c * p[i] - s * p[j];
+    q[j] = s * p[i] + c * p[j];
+    return q;
+}
+
+// round() is implementation defined at .5, so round with floor to match the CPU evaluator
+vec3 repeat(vec3 p, vec3 spacing, vec3 limit) {
+    vec3 q = p - spacing * clamp(floor(p / max(spacing, 1e-6) + 0.5), -limit, limit);
+    return mix(p, q, greaterThan(spacing, vec3(0.0)));
+}
+
+// modifiers are two texels inline in the csg program, see Modifier::get_data
+vec3 modify_point(vec3 p, vec4 a, vec4 b) {
+    int kind = int(a.x);
+    if (kind == 1) { // repeat
+        return repeat(p, a.yzw, vec3(1e20));
+    } else if (kind == 2) { // repeat limited
+        return repeat(p, a.yzw, b.xyz);
+    } else if (kind == 3) { // mirror
+        float h = dot(p, a.yzw) - b.x;
+        return (h < 0.0) ? p - 2.0 * h * a.yzw : p;
+    } else if (kind == 4) { // twist
+        int axis = int(a.y);
+        return rotate_about(p, axis, a.z * p[axis]);
+    } else if (kind == 5) { // bend
+        int axis = int(a.y);
+        return rotate_about(p, axis, a.z * p[(axis + 1) % 3]);
+    } else if (kind == 6) { // elongate
+        return p - clamp(p, -a.yzw, a.yzw);
+    }
+    return p;
+}
+
+// `p` is the point the modifier was applied at
+float modify_distance(float dist, vec4 a, vec3 p) {
+    int kind = int(a.x);
+    if (kind == 4 || kind == 5) { // twist, bend
+        // how far they move a point, per unit it moves along the axis they vary over
+        int axis = int(a.y);
+        float stretch = abs(a.z) * length(vec2(p[(axis + 1) % 3], p[(axis + 2) % 3]));
+        // divided by the largest stretch of space, so the distance stays a bound
+        return (kind == 4) ? dist * 2.0 / (stretch + sqrt(stretch * stretch + 4.0)) : dist / (1.0 + stretch);
+    } else if (kind == 7) { // onion
+        return abs(dist) - a.y;
+    } else if (kind == 8) { // round
+        return dist - a.y;
+    }
+    return dist;
+}
+
+// the distance part of `count` modifiers from csg program texel `start`, innermost first.
+// Twists and bends need the point they were applied at, which is worked out again from `p`,
+// the point before any of the modifiers.
+float modify_distances(float dist, vec3 p, int start, int count) {
+    for (int m = count - 1; m >= 0; m--) {
+        vec4 a = texelFetch(scene_csg, start + 2 * m);
+        vec3 q = p;
+        if (int(a.x) == 4 || int(a.x) == 5) {
+            for (int n = 0; n < m; n++) {
+                q = modify_point(q, texelFetch(scene_csg, start + 2 * n), texelFetch(scene_csg, start + 2 * n + 1));
+            }
+        }
+        dist = modify_distance(dist, a, q);
+    }
+    return dist;
+}
+
+// `modifiers` is the csg program texel of the object's first modifier
+vec4 get_sd(vec3 pos, int index, int modifiers, int modifier_count) {
     float dist = 0;
+    vec2 fractal = vec2(0.0);
+    mat4 obj = fetch_mat4(scene_objects, index);
     int obj_type = int(obj[0][0]);
-    mat4 trans_data = transformations[index];
-    mat4 rotation = rotate_mat(trans_data[1].xyz);
-    vec3 p = (rotation * vec4(pos - transformations[index][0].xyz, 1.0)).xyz;
+    // world to object space, with the distance scale in the unused [3][3], see Transform::get_data
+    mat4 world_to_local = fetch_mat4(scene_transforms, index);
+    vec3 start = (world_to_local * vec4(pos, 1.0)).xyz;
+    vec3 p = start;
+    for (int m = 0; m < modifier_count; m++) {
+        p = modify_point(p, texelFetch(scene_csg, modifiers + 2 * m), texelFetch(scene_csg, modifiers + 2 * m + 1));
+    }
     if (obj_type == 1) { // sphere
         dist = sphere_sd(p, obj[0][1]);
     } else if (obj_type == 2) { // plane
@@ -352,12 +870,43 @@ vec4 get_sd(vec3 pos, mat4 obj, int index) {
         dist = pyramid_sd(p, obj[0][1]);
     } else if (obj_type == 24) { // triangle
         dist = triangle_sd(p, obj[1].xyz, obj[2].xyz, vec3(obj[1][3], obj[2][3], obj[3][3]));
+    } else if (obj_type == 25) { // mandelbulb
+        fractal = mandelbulb_sd(p, obj[0][1], int(obj[0][2]), obj[0][3]);
+    } else if (obj_type == 26) { // menger sponge
+        fractal = menger_sd(p, obj[0][1], int(obj[0][2]));
+    } else if (obj_type == 27) { // sierpinski tetrahedron
+        fractal = sierpinski_sd(p, obj[0][1], int(obj[0][2]));
+    } else if (obj_type == 28) { // julia quaternion
+        fractal = julia_sd(p, obj[1], int(obj[0][2]), obj[0][3]);
+    } else if (obj_type == 29) { // extrusion
+        dist = extrude_sd(p, shape_sd(p.xy, int(obj[3][2])), obj[0][1], obj[0][2]);
+    } else if (obj_type == 31) { // text
+        // inset by the bevel, so rounding the edges doesn't thicken the glyphs
+        float bevel = min(obj[0][2], obj[0][1]);
+        dist = extrude_sd(p, shape_sd(p.xy, int(obj[3][2])) + bevel, obj[0][1] - bevel, bevel);
+    } else if (obj_type == 30) { // revolution
+        dist = shape_sd(revolve_point(p, int(obj[0][1]), obj[0][2]), int(obj[3][2]));
+    } else if (obj_type == 32) { // terrain
+        dist = terrain_sd(p, obj[0][1] * terrain_height(p.xz, obj), obj[0][1], obj[0][2], obj[1].xy);
+    } else if (obj_type == 33) { // voxel sdf
+        dist = voxel_sd(p, voxel_sample(p, obj), obj[0].yzw);
+    }
+    // the fractals blend towards the material in obj[3][1] by their orbit trap
+    float trap_material = obj[3][0];
+    if (obj_type >= 25 && obj_type <= 28) {
+        dist = fractal.x;
+        trap_material = obj[3][1];
     }
-    return vec4(dist, obj[3].xyz);
+    dist = modify_distances(dist, start, modifiers, modifier_count);
+    // distances in object space shrink by at most the smallest scale factor, so this
+    // is exact for uniform scale and a conservative bound for non-uniform scale
+    dist *= world_to_local[3][3];
+    return vec4(dist, obj[3][0], trap_material, fractal.y);
 }
 
 
-vec4 bool_op_sd(vec2 op, vec4 a, vec4 b) {
+// op is (operator, k, n), see BooleanOp::get
+vec4 bool_op_sd(vec3 op, vec4 a, vec4 b) {
     vec4 res = vec4(0);
     int op_type = int(op.x);
     float k = op.y;
@@ -374,57 +923,65 @@ vec4 bool_op_sd(vec2 op, vec4 a, vec4 b) {
         case 4: 
             res = op_smooth_union(a, b, k);
             break;
+        case 5:
+            res = op_smooth_intersect(a, b, k);
+            break;
+        case 6:
+            res = op_smooth_difference(a, b, k);
+            break;
+        case 7:
+            res = op_chamfer(a, b, k);
+            break;
+        case 8:
+            res = op_stairs(a, b, k, op.z);
+            break;
+        case 9:
+            res = op_columns(a, b, k, op.z);
+            break;
+        case 10:
+            res = op_xor(a, b);
+            break;
+        case 11:
+            res = op_morph(a, b, k);
+            break;
     }
-    // if (op_type < 2.0) {
-    //     res = op_union(a, b);
-    // }
-    // else if (op_type == 2.0) {
-    //     res = op_intersect(a, b);
-    // }
-    // else if (op_type == 3.0) {
-    //     res = op_difference(a, b);
-    // }
-    // else if (op_type == 4.0) {
-    //     res = op_smooth_union(a, b, k);
-    // }
     return res;
 }
 
 
+// csg_program is a post-order list of instructions:
+// (1, object index, -, -) pushes the object's distance,
+// (2, op type, k, -) pops two results and pushes bool_op_sd of them.
+// see BooleanOp::compile for the instruction format
 vec4 scene_sd(vec3 p) {
-    mat4 obj0 = objects[0];
-    vec4 pres = get_sd(p, obj0, 0);
-    vec4 res = vec4(1e20, -1, -1, -1);
-
-    int prev_bool_op = int(obj0[0][3]) - 1;
-    for (int i = 1; i < MAX_OBJECTS; i++) {
-        if (i >= int(consts.x)) break;
-        mat4 obj = objects[i];
-        float obj_type = obj[0][0];
-        if (obj_type == 0.0) {
-            res = op_union(res, pres);
-            break;
-        };
-        int bool_op_index = int(obj[0][3]) - 1;
-        vec4 d = get_sd(p, obj, i);
-
-        if (bool_op_index != prev_bool_op) {
-            res = op_union(res, pres);
-            pres = d;
-            // prev_bool_op = bool_op_index;
-            // continue;
-        }
-        else if (bool_op_index == -1) {
-            pres = bool_op_sd(vec2(1), pres, d);
-            // prev_bool_op = bool_op_index;
-            // continue;
+    vec4 stack[MAX_CSG_STACK];
+    vec3 points[MAX_CSG_STACK];
+    int sp = 0;
+    int pp = 0;
+    for (int i = 0; i < int(consts.y); i++) {
+        vec4 ins = texelFetch(scene_csg, i);
+        int kind = int(ins.x);
+        if (kind == 1) {
+            stack[sp] = get_sd(p, int(ins.y), i + 1, int(ins.z));
+            sp++;
+            i += 2 * int(ins.z);
+        } else if (kind == 2) {
+            sp--;
+            stack[sp - 1] = bool_op_sd(ins.yzw, stack[sp - 1], stack[sp]);
+        } else if (kind == 3) {
+            points[pp] = p;
+            pp++;
+            for (int m = 0; m < int(ins.y); m++) {
+                p = modify_point(p, texelFetch(scene_csg, i + 1 + 2 * m), texelFetch(scene_csg, i + 2 + 2 * m));
+            }
+            i += 2 * int(ins.y);
         } else {
-            vec2 bool_op = bool_ops[0];
-            pres = bool_op_sd(bool_op, pres, d);
+            pp--;
+            p = points[pp];
+            stack[sp - 1].x = modify_distances(stack[sp - 1].x, p, int(ins.y) + 1, int(ins.z));
         }
-        prev_bool_op = bool_op_index;
     }
-    return res;
+    return (sp > 0) ? stack[0] : vec4(1e20, -1, -1, -1);
 }
 
 vec4 march(vec3 origin, vec3 direction) {
@@ -446,10 +1003,10 @@ vec4 march(vec3 origin, vec3 direction) {
     return res;
 }
 
-float get_soft_shadow(vec3 ro, vec3 rd, float tmin, float tmax) {
+// w is the shadow hardness, larger values give a narrower penumbra
+float get_soft_shadow(vec3 ro, vec3 rd, float tmin, float tmax, float w) {
     float res = 1.0;
     float t = tmin;
-    float w = params.x;
     for (int i = 0; i < 24; i++) {
         float h = scene_sd(ro + rd * t).x;
         float s = clamp(w * h / t, 0.0, 1.0);
@@ -488,52 +1045,105 @@ vec3 get_normal(vec3 p) {
 	// 				  e.xxx * scene_sd(p + e.xxx).x );
 }
 
-vec3 get_light(vec3 p, vec3 rd, vec3 normal, vec3 color) {
-    float n_lights = 0;
-    vec3 total_light = vec3(0);
+#define LIGHT_POINT 0
+#define LIGHT_DIRECTIONAL 1
+#define LIGHT_SPOT 2
+#define LIGHT_AREA 3
+
+vec3 get_light(vec3 p, vec3 rd, vec3 normal, Material material) {
     float occ = (params.z != 0.0) ? get_ambient_occlusion(p, normal) : 1.0;
-    for (int i = 0; i < MAX_LIGHTS; i++) {
-        if (lights[i].w == 0) break;
-        vec3 light_pos = lights[i].xyz;
-        vec3 l = normalize(light_pos - p);
-        vec3 hal = normalize(l - rd);
+    vec3 total_light = material.albedo * vec3(0.03, 0.04, 0.1);
+    float shininess = exp2(10.0 * (1.0 - material.roughness));
+    vec3 f0 = mix(vec3(0.04), material.albedo, material.metallic);
+    for (int i = 0; i < int(consts.z); i++) {
+        vec4 position = texelFetch(scene_lights, i * 4);
+        vec4 direction = texelFetch(scene_lights, i * 4 + 1);
+        vec4 color = texelFetch(scene_lights, i * 4 + 2);
+        vec4 cones = texelFetch(scene_lights, i * 4 + 3);
+        int type = int(position.w);
+
+        vec3 l;
+        float light_dist;
+        float attenuation = 1.0;
+        if (type == LIGHT_DIRECTIONAL) {
+            l = -direction.xyz;
+            light_dist = MAX_DIST;
+        } else {
+            l = position.xyz - p;
+            light_dist = length(l);
+            l /= light_dist;
+            if (direction.w > 0.0) {
+                float falloff = clamp(1.0 - light_dist / direction.w, 0.0, 1.0);
+                attenuation = falloff * falloff;
+            }
+            if (type == LIGHT_SPOT) {
+                float cos_angle = dot(-l, direction.xyz);
+                // equal angles leave no edge to blend across, and smoothstep needs one
+                attenuation *= cones.x > cones.y ? smoothstep(cones.y, cones.x, cos_angle) : step(cones.y, cos_angle);
+            }
+        }
+        if (attenuation <= 0.0) continue;
+        vec3 h = normalize(l - rd);
 
         float dif = clamp(dot(normal, l), 0.0, 1.0);
         dif *= occ;
-        if (params.y != 0.0) {
-            dif *= get_soft_shadow(p, l, 0.02, 5.0);
+        if (params.y != 0.0 && dif > 0.0) {
+            float hardness = (type == LIGHT_AREA) ? light_dist / max(color.w, 0.001) : params.x;
+            dif *= get_soft_shadow(p, l, 0.02, min(light_dist, 5.0), hardness);
         }
+        vec3 radiance = color.rgb * attenuation;
 
-        vec3 directional = vec3(0.9, 0.9, 0.8) * dif;
-        vec3 ambient = vec3(0.03, 0.04, 0.1);
-
-        float spec = pow(clamp(dot(normal, hal), 0.0, 1.0), 16.0);
+        float spec = pow(clamp(dot(normal, h), 0.0, 1.0), shininess);
         spec *= dif;
-        spec *= 0.04 + 0.96 * pow(clamp(1.0 - dot(hal, l), 0.0, 1.0), 5.0);
-        total_light += color * (directional + ambient);
-        total_light += 5.00 * spec;
-        n_lights += 1;
+        vec3 fresnel = f0 + (1.0 - f0) * pow(clamp(1.0 - dot(h, l), 0.0, 1.0), 5.0);
+        total_light += material.albedo * radiance * dif * (1.0 - material.metallic);
+        total_light += 5.00 * material.specular * spec * fresnel * radiance;
     }
-    total_light /= n_lights;
-    return total_light;
+    return total_light + material.emissive;
 }
 
-vec3 render(vec3 rd) {
-    vec3 color = fog_color.xyz - max(rd.y, 0.0) * 0.4;
+vec3 get_background(vec3 rd) {
+    return fog_color.xyz - max(rd.y, 0.0) * 0.4;
+}
 
+// Shades a march result, or returns the background if it missed.
+vec3 shade(vec3 origin, vec3 rd, vec4 res, out Material material) {
+    material = Material(vec3(0), 0.0, 0.0, 0.0, vec3(0), 1.0);
+    if (res.y < -0.5) return get_background(rd);
+
+    vec3 pos = origin + rd * res.x;
+    vec3 normal = get_normal(pos);
+    material = get_material(res.yzw);
+    vec3 color = get_light(pos, rd, normal, material);
+
+    float fog_start = 4.0;
+    float fog_thickness = 8.0;
+    float fog_strength = 1.0 / (1 + exp(-(pos.z / fog_thickness) + fog_start));
+    return mix(color, fog_color.xyz, fog_strength);
+}
+
+// Marches from a point on a surface to where the ray leaves the object again.
+vec3 march_through(vec3 pos, vec3 rd) {
+    float dist = 0.01;
+    for (int i = 0; i < MAX_STEPS / 2; i++) {
+        float d = -scene_sd(pos + rd * dist).x;
+        if (d < MIN_DIST) break;
+        dist += max(d, 0.01);
+    }
+    return pos + rd * (dist + 0.01);
+}
+
+vec3 render(vec3 rd) {
     vec4 res = march(camera_origin, rd);
-    float dist = res.x;
-    vec3 material = res.yzw;
-
-    if (material.x > -0.5) {
-        vec3 pos = camera_origin + rd * dist;
-        vec3 normal = get_normal(pos);
-        color = get_light(pos, rd, normal, material);
-
-        float fog_start = 4.0;
-        float fog_thickness = 8.0;
-        float fog_strength = 1.0 / (1 + exp(-(pos.z / fog_thickness) + fog_start));
-        color = mix(color, fog_color.xyz, fog_strength);
+    Material material;
+    vec3 color = shade(camera_origin, rd, res, material);
+
+    // translucent surfaces show one layer of what is behind them
+    if (material.opacity < 1.0) {
+        vec3 behind = march_through(camera_origin + rd * res.x, rd);
+        Material behind_material;
+        vec3 behind_color = shade(behind, rd, march(behind, rd), behind_material);
+        color = mix(behind_color, color, material.opacity);
     }
     // vec3 reflection = reflect(rd, normal);
 
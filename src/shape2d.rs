@@ -0,0 +1,118 @@
+use serde::{Serialize, Deserialize};
+use super::*;
+
+
+
+/// A 2D outline in the XY plane, lifted into 3D by `Primitive::Extrude` and `Primitive::Revolve`.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum Shape2D {
+    Circle(f32), // Radius
+    RoundedRectangle(Vec2, f32), // Half Size, Corner Radius
+    Polygon(Vec<Vec2>), // Vertices, in either winding order
+    Star(f32, u32, f32), // Radius, Points, Sharpness (2 to Points, Points is a regular polygon)
+    Arc(f32, f32, f32), // Half Aperture From +Y, Radius, Thickness
+    Bezier(Vec2, Vec2, Vec2, f32), // Start, Control Point, End, Thickness
+    Ellipse(Vec2), // Radii
+    Contours(Vec<Vec<Vec2>>), // Closed Polygons, Filled By The Even-Odd Rule So Inner Ones Cut Holes
+}
+
+impl Shape2D {
+    pub fn id(&self) -> f32 {
+        match &self {
+            Self::Circle(..) => 1.,
+            Self::RoundedRectangle(..) => 2.,
+            Self::Polygon(..) => 3.,
+            Self::Star(..) => 4.,
+            Self::Arc(..) => 5.,
+            Self::Bezier(..) => 6.,
+            Self::Ellipse(..) => 7.,
+            Self::Contours(..) => 8.,
+        }
+    }
+    pub fn as_str(&self) -> &str {
+        match &self {
+            Self::Circle(..) => "Circle",
+            Self::RoundedRectangle(..) => "Rounded Rectangle",
+            Self::Polygon(..) => "Polygon",
+            Self::Star(..) => "Star",
+            Self::Arc(..) => "Arc",
+            Self::Bezier(..) => "Bezier",
+            Self::Ellipse(..) => "Ellipse",
+            Self::Contours(..) => "Contours",
+        }
+    }
+    /// One of each shape, sized to fit in a unit circle.
+    pub fn defaults() -> Vec<Self> {
+        vec![
+            Self::Circle(0.5),
+            Self::RoundedRectangle(vec2(0.6, 0.4), 0.1),
+            Self::Polygon(vec![vec2(-0.6, -0.5), vec2(0.6, -0.5), vec2(0.2, 0.0), vec2(0.5, 0.5), vec2(-0.4, 0.4)]),
+            Self::Star(0.7, 5, 3.0),
+            Self::Arc(2.0, 0.5, 0.1),
+            Self::Bezier(vec2(-0.6, -0.4), vec2(0.0, 1.0), vec2(0.6, -0.4), 0.1),
+            Self::Ellipse(vec2(0.7, 0.4)),
+            Self::Contours(vec![
+                vec![vec2(-0.6, -0.6), vec2(0.6, -0.6), vec2(0.6, 0.6), vec2(-0.6, 0.6)],
+                vec![vec2(-0.3, -0.3), vec2(0.3, -0.3), vec2(0.0, 0.3)],
+            ]),
+        ]
+    }
+    /// Whether the shape's parameters are in range. A star needs at least 2 points and a
+    /// sharpness from 2 to its number of points.
+    pub fn is_valid(&self) -> bool {
+        match self {
+            Self::Star(_, points, sharpness) => *points >= 2 && (2.0..=*points as f32).contains(sharpness),
+            _ => true,
+        }
+    }
+    /// Packed for the `scene_shapes` buffer texture as (id, a, b, c), (d, e, f, g),
+    /// followed by a polygon's vertices two to a texel. Each of the contours is a
+    /// (vertex count, 0, 0, 0) texel, its bounds as (min x, min y, max x, max y), then its vertices.
+    pub fn get_data(&self) -> Vec<[f32; 4]> {
+        let vertices = |points: &[Vec2]| points.chunks(2).map(|pair| {
+            let b = pair.get(1).copied().unwrap_or(pair[0]);
+            [pair[0].x, pair[0].y, b.x, b.y]
+        }).collect::<Vec<_>>();
+        match self {
+            Self::Circle(radius) => vec![
+                [self.id(), *radius, 0.0, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::RoundedRectangle(half, radius) => vec![
+                [self.id(), half.x, half.y, *radius],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Polygon(points) => {
+                let mut data = vec![
+                    [self.id(), points.len() as f32, 0.0, 0.0],
+                    [0.0, 0.0, 0.0, 0.0]];
+                data.extend(vertices(points));
+                data
+            },
+            Self::Star(radius, points, sharpness) => vec![
+                [self.id(), *radius, *points as f32, *sharpness],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Arc(aperture, radius, thickness) => vec![
+                [self.id(), *aperture, *radius, *thickness],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Bezier(a, b, c, thickness) => vec![
+                [self.id(), *thickness, c.x, c.y],
+                [a.x, a.y, b.x, b.y]],
+            Self::Ellipse(radii) => vec![
+                [self.id(), radii.x, radii.y, 0.0],
+                [0.0, 0.0, 0.0, 0.0]],
+            Self::Contours(contours) => {
+                let mut data = vec![
+                    [self.id(), contours.len() as f32, 0.0, 0.0],
+                    [0.0, 0.0, 0.0, 0.0]];
+                for points in contours {
+                    let (min, max) = points.iter().fold((vec2(1e20, 1e20), vec2(-1e20, -1e20)), |(min, max), p| {
+                        (vec2(min.x.min(p.x), min.y.min(p.y)), vec2(max.x.max(p.x), max.y.max(p.y)))
+                    });
+                    data.push([points.len() as f32, 0.0, 0.0, 0.0]);
+                    data.push([min.x, min.y, max.x, max.y]);
+                    data.extend(vertices(points));
+                }
+                data
+            },
+        }
+    }
+}
@@ -0,0 +1,140 @@
+use serde::{Serialize, Deserialize};
+use std::path::Path;
+use super::*;
+
+
+
+// steepest slope of one octave of value noise along each axis, with smoothstep
+// interpolation (slope 1.5) between values 2 apart
+const NOISE_SLOPE: f32 = 3.0;
+
+
+/// Where a `Primitive::Terrain` gets its heights from, as a 0 to 1 fraction of its height.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+pub enum HeightSource {
+    Fbm(u32, f32, f32, u32, f32), // Octaves, Lacunarity, Gain, Seed, Frequency (Features Per Unit)
+    Image(Heightmap),
+}
+
+impl HeightSource {
+    /// Bound on how steep the normalized heights get, per scene unit along XZ, for a
+    /// terrain of half size `size`.
+    pub fn lipschitz(&self, size: Vec2) -> f32 {
+        match self {
+            Self::Fbm(octaves, lacunarity, gain, _, frequency) => {
+                let (mut amp, mut freq, mut slope, mut norm) = (1.0, 1.0, 0.0, 0.0);
+                for _ in 0..*octaves {
+                    slope += amp * freq;
+                    norm += amp;
+                    amp *= gain;
+                    freq *= lacunarity;
+                }
+                // heights are 0.5 + 0.5 * fbm, and the axes' slopes add up to at most sqrt(2) times the largest
+                0.5 * frequency * NOISE_SLOPE * 2f32.sqrt() * slope / f32::max(norm, 1e-6)
+            },
+            Self::Image(map) => {
+                let cell = vec2(2.0 * size.x / (map.width.max(2) - 1) as f32, 2.0 * size.y / (map.height.max(2) - 1) as f32);
+                vec2(map.max_step.x / cell.x, map.max_step.y / cell.y).length()
+            },
+        }
+    }
+    /// Factor the distance above the surface is scaled by so a march can't overshoot a slope,
+    /// for a terrain `height` tall.
+    pub fn step(&self, size: Vec2, height: f32) -> f32 {
+        1.0 / (1.0 + (height * self.lipschitz(size)).powi(2)).sqrt()
+    }
+}
+
+
+/// A grayscale height image, loaded from `path` and kept with its samples. Pixels map onto the
+/// terrain's XZ extent with columns along +X and rows along +Z, black at the bottom and white at the top.
+/// Deserializing one only reads its path, and `SceneFile::into_scene` loads the image.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "HeightmapSpec", into = "HeightmapSpec")]
+pub struct Heightmap {
+    path: String,
+    width: u32,
+    height: u32,
+    samples: Vec<f32>,
+    // largest difference between neighbouring samples along each axis
+    max_step: Vec2,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+struct HeightmapSpec {
+    path: String,
+}
+
+impl From<HeightmapSpec> for Heightmap {
+    fn from(spec: HeightmapSpec) -> Self {
+        Self::new(&spec.path, 0, 0, Vec::new())
+    }
+}
+
+impl From<Heightmap> for HeightmapSpec {
+    fn from(map: Heightmap) -> Self {
+        Self { path: map.path }
+    }
+}
+
+impl Heightmap {
+    /// Loads a PNG of any colour type and bit depth, using its luminance as the height.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let mut decoder = png::Decoder::new(std::io::BufReader::new(std::fs::File::open(Path::new(path))?));
+        decoder.set_transformations(png::Transformations::EXPAND);
+        let mut reader = decoder.read_info()?;
+        let mut buf = vec![0; reader.output_buffer_size()];
+        let info = reader.next_frame(&mut buf)?;
+        let channels = info.color_type.samples();
+        let values: Vec<f32> = match info.bit_depth {
+            png::BitDepth::Sixteen => buf[..info.buffer_size()].chunks(2)
+                .map(|c| u16::from_be_bytes([c[0], c[1]]) as f32 / 65535.0)
+                .collect(),
+            _ => buf[..info.buffer_size()].iter().map(|c| *c as f32 / 255.0).collect(),
+        };
+        let samples = values.chunks(channels).map(|c| match channels {
+            1 | 2 => c[0],
+            _ => 0.2126 * c[0] + 0.7152 * c[1] + 0.0722 * c[2],
+        }).collect();
+        Ok(Self::new(path, info.width, info.height, samples))
+    }
+    fn new(path: &str, width: u32, height: u32, samples: Vec<f32>) -> Self {
+        let at = |x: u32, y: u32| samples[(y * width + x) as usize];
+        let mut max_step = vec2(0., 0.);
+        for y in 0..height {
+            for x in 0..width {
+                if x + 1 < width {
+                    max_step.x = max_step.x.max((at(x + 1, y) - at(x, y)).abs());
+                }
+                if y + 1 < height {
+                    max_step.y = max_step.y.max((at(x, y + 1) - at(x, y)).abs());
+                }
+            }
+        }
+        Self { path: path.into(), width, height, samples, max_step }
+    }
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub(crate) fn path_mut(&mut self) -> &mut String {
+        &mut self.path
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn sample(&self, x: u32, y: u32) -> f32 {
+        self.samples[(y * self.width + x) as usize]
+    }
+    /// Packed for the `scene_heightmaps` buffer texture, four samples to a texel row by row.
+    pub fn get_data(&self) -> Vec<[f32; 4]> {
+        self.samples.chunks(4).map(|c| {
+            let mut texel = [0.0; 4];
+            texel[..c.len()].copy_from_slice(c);
+            texel
+        }).collect()
+    }
+}
@@ -0,0 +1,177 @@
+use serde::{Serialize, Deserialize};
+use std::fmt;
+use super::*;
+
+
+
+// straight segments each glyph curve is flattened into
+const QUAD_SEGMENTS: usize = 4;
+const CUBIC_SEGMENTS: usize = 6;
+
+
+/// A line (or lines) of text laid out in a font, kept with its glyph outlines so it doesn't
+/// need laying out again whenever the scene is drawn. The outline is centred on the origin
+/// in the XY plane, with `size` the font's em height in scene units, and reads left to right
+/// from the -Z side, facing a camera that looks along +Z. Deserializing text only reads its
+/// string and font, and `SceneFile::into_scene` lays it out.
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "TextSpec", into = "TextSpec")]
+pub struct Text {
+    string: String,
+    font: String,
+    size: f32,
+    outline: Shape2D,
+}
+
+#[derive(Serialize, Deserialize)]
+struct TextSpec {
+    string: String,
+    font: String,
+    size: f32,
+}
+
+impl From<TextSpec> for Text {
+    fn from(spec: TextSpec) -> Self {
+        Self { string: spec.string, font: spec.font, size: spec.size, outline: Shape2D::Contours(Vec::new()) }
+    }
+}
+
+impl From<Text> for TextSpec {
+    fn from(text: Text) -> Self {
+        Self { string: text.string, font: text.font, size: text.size }
+    }
+}
+
+#[derive(Debug)]
+pub enum FontError {
+    Read(String, std::io::Error),
+    Parse(String),
+}
+
+impl fmt::Display for FontError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Read(font, e) => write!(f, "could not read font {}: {}", font, e),
+            Self::Parse(font) => write!(f, "{} is not a TrueType or OpenType font", font),
+        }
+    }
+}
+
+impl std::error::Error for FontError {}
+
+
+impl Text {
+    /// Lays out `string` in `font`, which is either the bundled font's name (`FONT_NAME`)
+    /// or a path to a font file. Newlines start a new line, and characters the font has no
+    /// glyph for show its missing glyph box.
+    pub fn new(string: &str, font: &str, size: f32) -> Result<Self, FontError> {
+        let data = if font == FONT_NAME {
+            FONT_PATH.to_vec()
+        } else {
+            std::fs::read(font).map_err(|e| FontError::Read(font.into(), e))?
+        };
+        let face = ttf_parser::Face::parse(&data, 0).map_err(|_| FontError::Parse(font.into()))?;
+        Ok(Self {
+            string: string.into(),
+            font: font.into(),
+            size,
+            outline: layout(&face, string, size),
+        })
+    }
+    /// Text in the bundled font.
+    pub fn bundled(string: &str, size: f32) -> Self {
+        Self::new(string, FONT_NAME, size).unwrap()
+    }
+    pub fn string(&self) -> &str {
+        &self.string
+    }
+    pub fn font(&self) -> &str {
+        &self.font
+    }
+    /// The font file, `None` for the bundled font.
+    pub(crate) fn font_path_mut(&mut self) -> Option<&mut String> {
+        Some(&mut self.font).filter(|font| font.as_str() != FONT_NAME)
+    }
+    pub fn size(&self) -> f32 {
+        self.size
+    }
+    /// The glyph outlines, as `Shape2D::Contours`.
+    pub fn outline(&self) -> &Shape2D {
+        &self.outline
+    }
+}
+
+
+fn layout(face: &ttf_parser::Face, string: &str, size: f32) -> Shape2D {
+    let scale = size / face.units_per_em() as f32;
+    let line_height = (face.ascender() - face.descender() + face.line_gap()) as f32;
+    let mut builder = ContourBuilder { contours: Vec::new(), origin: vec2(0., 0.), last: vec2(0., 0.) };
+    for (line, chars) in string.lines().enumerate() {
+        builder.origin = vec2(0., -(line as f32) * line_height);
+        for c in chars.chars() {
+            let glyph = face.glyph_index(c).unwrap_or(ttf_parser::GlyphId(0));
+            face.outline_glyph(glyph, &mut builder);
+            builder.origin.x += face.glyph_hor_advance(glyph).unwrap_or(0) as f32;
+        }
+    }
+    // centre the text's bounds on the origin, go from font units to scene units, and mirror
+    // X so the text faces -Z
+    let points = builder.contours.iter().flatten();
+    let (min, max) = points.fold((vec2(1e20, 1e20), vec2(-1e20, -1e20)), |(min, max), p| {
+        (vec2(min.x.min(p.x), min.y.min(p.y)), vec2(max.x.max(p.x), max.y.max(p.y)))
+    });
+    let centre = (min + max) * 0.5;
+    let contours = builder.contours.into_iter()
+        .map(|contour| contour.into_iter().map(|p| (p - centre) * vec2(-scale, scale)).collect())
+        .collect();
+    Shape2D::Contours(contours)
+}
+
+struct ContourBuilder {
+    contours: Vec<Vec<Vec2>>,
+    origin: Vec2,
+    last: Vec2,
+}
+
+impl ContourBuilder {
+    fn push(&mut self, p: Vec2) {
+        self.last = p;
+        if let Some(contour) = self.contours.last_mut() {
+            contour.push(self.origin + p);
+        }
+    }
+}
+
+impl ttf_parser::OutlineBuilder for ContourBuilder {
+    fn move_to(&mut self, x: f32, y: f32) {
+        self.contours.push(Vec::new());
+        self.push(vec2(x, y));
+    }
+    fn line_to(&mut self, x: f32, y: f32) {
+        self.push(vec2(x, y));
+    }
+    fn quad_to(&mut self, x1: f32, y1: f32, x: f32, y: f32) {
+        let (a, b, c) = (self.last, vec2(x1, y1), vec2(x, y));
+        for i in 1..=QUAD_SEGMENTS {
+            let t = i as f32 / QUAD_SEGMENTS as f32;
+            let s = 1.0 - t;
+            self.push(a * (s * s) + b * (2.0 * s * t) + c * (t * t));
+        }
+    }
+    fn curve_to(&mut self, x1: f32, y1: f32, x2: f32, y2: f32, x: f32, y: f32) {
+        let (a, b, c, d) = (self.last, vec2(x1, y1), vec2(x2, y2), vec2(x, y));
+        for i in 1..=CUBIC_SEGMENTS {
+            let t = i as f32 / CUBIC_SEGMENTS as f32;
+            let s = 1.0 - t;
+            self.push(a * (s * s * s) + b * (3.0 * s * s * t) + c * (3.0 * s * t * t) + d * (t * t * t));
+        }
+    }
+    fn close(&mut self) {
+        // the polygon closes itself, so drop a last point that repeats the first
+        if let Some(contour) = self.contours.last_mut() {
+            if contour.len() > 1 && contour.first() == contour.last() {
+                contour.pop();
+            }
+        }
+    }
+}
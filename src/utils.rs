@@ -1,10 +1,8 @@
-#[allow(dead_code)]
-#[path = "constants.rs"] mod constants;
-pub use constants::*;
 pub use std::f32::consts::{PI, TAU};
+use serde::{Serialize, Deserialize};
 
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Serialize, Deserialize)]
 pub enum Axis {
     X,
     Y,
@@ -47,5 +45,5 @@ pub fn clamp(x: f32, min: f32, max: f32) -> f32{
     if x > max {
         return max;
     }
-    return x;
+    x
 }
\ No newline at end of file
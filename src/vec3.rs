@@ -1,12 +1,12 @@
-#[allow(dead_code)]
 use std::ops::{Add, Sub, Mul, Div, Neg, Index, IndexMut};
-#[path = "utils.rs"] mod utils;
-pub use utils::*;
+use serde::{Serialize, Deserialize};
+use super::*;
 
 
 
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "[f32; 2]", into = "[f32; 2]")]
 pub struct Vec2 {
     pub x: f32,
     pub y: f32,
@@ -79,6 +79,18 @@ impl Neg for Vec2 {
     }
 }
 
+impl From<[f32; 2]> for Vec2 {
+    fn from(v: [f32; 2]) -> Self {
+        Self::new(v[0], v[1])
+    }
+}
+
+impl From<Vec2> for [f32; 2] {
+    fn from(v: Vec2) -> Self {
+        [v.x, v.y]
+    }
+}
+
 impl Index<usize> for Vec2 {
     type Output = f32;
     fn index(&self, index: usize) -> &Self::Output {
@@ -124,8 +136,8 @@ impl IndexMut<Axis> for Vec2 {
 impl Vec2 {
     pub fn new(x: f32, y: f32) -> Self {
         Self {
-            x: x,
-            y: y,
+            x,
+            y,
         }
     }
     pub fn origin() -> Self {
@@ -149,12 +161,30 @@ impl Vec2 {
         self.length_squared().sqrt()
     }
     pub fn normalize(&self) -> Self {
-        self.clone() / self.length()
+        *self / self.length()
+    }
+    pub fn abs(&self) -> Self {
+        Self {
+            x: self.x.abs(),
+            y: self.y.abs(),
+        }
+    }
+    pub fn max(&self, cap: f32) -> Self {
+        Self {
+            x: self.x.max(cap),
+            y: self.y.max(cap),
+        }
+    }
+    pub fn min(&self, cap: f32) -> Self {
+        Self {
+            x: self.x.min(cap),
+            y: self.y.min(cap),
+        }
     }
     pub fn dot(&self, other: Self) -> f32 {
         self.x * other.x + self.y * other.y
     }
-    pub fn to_tuple(&self) -> (f32, f32) {
+    pub fn to_tuple(self) -> (f32, f32) {
         (self.x, self.y)
     }
 }
@@ -163,7 +193,8 @@ impl Vec2 {
 
 
 
-#[derive(Clone, Copy, PartialEq)]
+#[derive(Clone, Copy, PartialEq, Debug, Serialize, Deserialize)]
+#[serde(from = "[f32; 3]", into = "[f32; 3]")]
 pub struct Vec3 {
     pub x: f32,
     pub y: f32,
@@ -265,6 +296,18 @@ impl Neg for Vec3 {
     }
 }
 
+impl From<[f32; 3]> for Vec3 {
+    fn from(v: [f32; 3]) -> Self {
+        Self::new(v[0], v[1], v[2])
+    }
+}
+
+impl From<Vec3> for [f32; 3] {
+    fn from(v: Vec3) -> Self {
+        v.to_tuple()
+    }
+}
+
 impl Index<usize> for Vec3 {
     type Output = f32;
     fn index(&self, index: usize) -> &Self::Output {
@@ -312,9 +355,9 @@ impl IndexMut<Axis> for Vec3 {
 impl Vec3 {
     pub fn new(x: f32, y: f32, z: f32) -> Self {
         Self {
-            x: x,
-            y: y,
-            z: z
+            x,
+            y,
+            z
         }
     }
     pub fn origin() -> Self {
@@ -340,7 +383,7 @@ impl Vec3 {
         self.length_squared().sqrt()
     }
     pub fn normalize(&self) -> Self {
-        self.clone() / self.length()
+        *self / self.length()
     }
     pub fn abs(&self) -> Self {
         Self {
@@ -383,22 +426,22 @@ impl Vec3 {
             z: self.x * other.y - self.y * other.x
         }
     }
-    pub fn to_tuple(&self) -> [f32; 3] {
+    pub fn to_tuple(self) -> [f32; 3] {
         [self.x, self.y, self.z]
     }
 }
 
 pub fn vec3(x: f32, y: f32, z: f32) -> Vec3 {
     Vec3 {
-        x: x,
-        y: y,
-        z: z
+        x,
+        y,
+        z
     }
 }
 pub fn vec2(x: f32, y: f32) -> Vec2 {
     Vec2 {
-        x: x,
-        y: y
+        x,
+        y
     }
 }
 
@@ -417,6 +460,7 @@ pub type Point3 = Vec3;
 
 
 
+#[allow(clippy::upper_case_acronyms)]
 #[derive(Clone)]
 pub struct ONB {
     pub u: Vec3,
@@ -431,9 +475,9 @@ impl ONB {
         let v = w.cross(a).normalize();
         let u = w.cross(v);
         Self {
-            u: u,
-            v: v,
-            w: w
+            u,
+            v,
+            w
         }
     }
     pub fn local(&self, a: Vec3) -> Vec3 {
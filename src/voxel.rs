@@ -0,0 +1,158 @@
+use serde::{Serialize, Deserialize};
+use std::io::Write;
+use super::*;
+
+
+
+// start of the files `VoxelGrid::save` writes
+const MAGIC: &[u8; 8] = b"VOXELSDF";
+// magic, resolution and half size
+const HEADER_BYTES: usize = 8 + 3 * 4 + 3 * 4;
+
+
+/// A dense grid of signed distances over the box from `-half_size` to `half_size`, with
+/// samples at the corners of its cells, so the first and last samples along each axis lie
+/// on the box's faces. Kept with the file it was loaded from or saved to, which has to exist
+/// before the grid can be written to a scene file. Deserializing a grid only reads where its
+/// file is, and `SceneFile::into_scene` loads it.
+#[derive(Clone, PartialEq, Debug, Deserialize)]
+#[serde(from = "VoxelGridSpec")]
+pub struct VoxelGrid {
+    path: String,
+    // loaded with `load_raw`, so the scene file has to say how big it is
+    headerless: bool,
+    resolution: [u32; 3],
+    half_size: Vec3,
+    samples: Vec<f32>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum VoxelGridSpec {
+    File(String),
+    Raw {
+        path: String,
+        resolution: (u32, u32, u32),
+        half_size: Vec3,
+    },
+}
+
+impl From<VoxelGridSpec> for VoxelGrid {
+    fn from(spec: VoxelGridSpec) -> Self {
+        let (path, headerless, resolution, half_size) = match spec {
+            VoxelGridSpec::File(path) => (path, false, [0; 3], vec3(0., 0., 0.)),
+            VoxelGridSpec::Raw { path, resolution: (x, y, z), half_size } => (path, true, [x, y, z], half_size),
+        };
+        Self { path, headerless, resolution, half_size, samples: Vec::new() }
+    }
+}
+
+impl Serialize for VoxelGrid {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        if self.path.is_empty() {
+            return Err(serde::ser::Error::custom("a voxel grid has to be saved with VoxelGrid::save before the scene it is in"));
+        }
+        let [x, y, z] = self.resolution;
+        let spec = if self.headerless {
+            VoxelGridSpec::Raw { path: self.path.clone(), resolution: (x, y, z), half_size: self.half_size }
+        } else {
+            VoxelGridSpec::File(self.path.clone())
+        };
+        spec.serialize(serializer)
+    }
+}
+
+fn invalid_data(message: String) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::InvalidData, message)
+}
+
+// samples are found by dividing by the half size, so it has to be positive and finite
+fn valid_half_size(half_size: Vec3) -> bool {
+    [half_size.x, half_size.y, half_size.z].iter().all(|x| x.is_finite() && *x > 0.0)
+}
+
+impl VoxelGrid {
+    /// A grid of `samples`, x varying fastest, then y, then z. Not backed by a file until saved.
+    pub fn new(resolution: [u32; 3], half_size: Vec3, samples: Vec<f32>) -> Self {
+        assert!(resolution.iter().all(|n| *n >= 2), "a voxel grid needs at least 2 samples along each axis");
+        assert!(valid_half_size(half_size), "a voxel grid needs a positive, finite half size");
+        assert_eq!(samples.len(), resolution.iter().map(|n| *n as usize).product::<usize>(), "sample count doesn't match the grid's resolution");
+        Self { path: String::new(), headerless: false, resolution, half_size, samples }
+    }
+    /// Loads a grid written by `save`: `VOXELSDF`, then the resolution as three little endian
+    /// `u32`s and the half size as three little endian `f32`s, followed by the samples as
+    /// `load_raw` reads them.
+    pub fn load(path: &str) -> std::io::Result<Self> {
+        let bytes = std::fs::read(path)?;
+        if bytes.len() < HEADER_BYTES || &bytes[..8] != MAGIC {
+            return Err(invalid_data(format!("{} is not a voxel grid written by VoxelGrid::save", path)));
+        }
+        let word = |i: usize| [bytes[8 + 4 * i], bytes[9 + 4 * i], bytes[10 + 4 * i], bytes[11 + 4 * i]];
+        let resolution = [0, 1, 2].map(|i| u32::from_le_bytes(word(i)));
+        let half_size = vec3(f32::from_le_bytes(word(3)), f32::from_le_bytes(word(4)), f32::from_le_bytes(word(5)));
+        let samples = read_samples(path, &bytes[HEADER_BYTES..], resolution, half_size)?;
+        Ok(Self { path: path.into(), headerless: false, resolution, half_size, samples })
+    }
+    /// Loads a headerless dense grid of little endian `f32` distances, x varying fastest,
+    /// then y, then z, such as other tools write. Distances are in the grid's own units.
+    pub fn load_raw(path: &str, resolution: [u32; 3], half_size: Vec3) -> std::io::Result<Self> {
+        let samples = read_samples(path, &std::fs::read(path)?, resolution, half_size)?;
+        Ok(Self { path: path.into(), headerless: true, resolution, half_size, samples })
+    }
+    /// Writes the grid to `path` in the format `load` reads, and keeps it as the grid's file.
+    pub fn save(&mut self, path: &str) -> std::io::Result<()> {
+        let mut file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        file.write_all(MAGIC)?;
+        for n in self.resolution {
+            file.write_all(&n.to_le_bytes())?;
+        }
+        for x in [self.half_size.x, self.half_size.y, self.half_size.z] {
+            file.write_all(&x.to_le_bytes())?;
+        }
+        for sample in self.samples.iter() {
+            file.write_all(&sample.to_le_bytes())?;
+        }
+        file.flush()?;
+        self.path = path.into();
+        self.headerless = false;
+        Ok(())
+    }
+    /// The file the grid was loaded from or last saved to, empty for a grid that hasn't been saved.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+    pub(crate) fn path_mut(&mut self) -> &mut String {
+        &mut self.path
+    }
+    /// Reads the grid again from its file, the way it was first loaded.
+    pub(crate) fn reload(&self) -> std::io::Result<Self> {
+        if self.headerless {
+            Self::load_raw(&self.path, self.resolution, self.half_size)
+        } else {
+            Self::load(&self.path)
+        }
+    }
+    pub fn resolution(&self) -> [u32; 3] {
+        self.resolution
+    }
+    pub fn half_size(&self) -> Vec3 {
+        self.half_size
+    }
+    pub fn sample(&self, x: u32, y: u32, z: u32) -> f32 {
+        let [w, h, _] = self.resolution.map(|n| n as usize);
+        self.samples[(z as usize * h + y as usize) * w + x as usize]
+    }
+}
+
+fn read_samples(path: &str, bytes: &[u8], resolution: [u32; 3], half_size: Vec3) -> std::io::Result<Vec<f32>> {
+    if !valid_half_size(half_size) {
+        return Err(invalid_data(format!("{} has a half size of {:?}, which isn't positive and finite", path, half_size)));
+    }
+    // a header can claim a grid too big to address, which is never the size of the file
+    let size = resolution.iter().try_fold(4usize, |size, n| size.checked_mul(*n as usize));
+    if resolution.iter().any(|n| *n < 2) || size != Some(bytes.len()) {
+        return Err(invalid_data(format!(
+            "{} has {} bytes of samples, not a {}x{}x{} grid of f32s", path, bytes.len(), resolution[0], resolution[1], resolution[2])));
+    }
+    Ok(bytes.chunks(4).map(|c| f32::from_le_bytes([c[0], c[1], c[2], c[3]])).collect())
+}
@@ -0,0 +1,837 @@
+//! Golden image tests. Each case renders a small scene on the CPU and compares it against
+//! `tests/golden/<name>.png`. Run with `UPDATE_GOLDEN=1` to rewrite the golden images after
+//! an intended visual change. Failing cases write `<name>.actual.png` and `<name>.diff.png`
+//! to `target/golden-diff`. Where there's an EGL device to draw on (Mesa's llvmpipe will do),
+//! the shaders render every case too and are held to the same images, with failures written
+//! to `target/golden-diff/gpu`.
+use gpu_raymarching::*;
+use glutin::api::egl::{context::PossiblyCurrentContext, device::Device, display::Display};
+use glutin::config::{ConfigSurfaceTypes, ConfigTemplateBuilder};
+use glutin::context::{ContextApi, ContextAttributesBuilder, Version};
+use glutin::prelude::*;
+use std::ffi::CString;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::OnceLock;
+
+
+
+const WIDTH: u32 = 80;
+const HEIGHT: u32 = 60;
+/// Colour difference (CIE76 delta E) above which a pixel counts as changed.
+const PIXEL_TOLERANCE: f32 = 4.0;
+/// Fraction of changed pixels a case may have, to absorb float differences along edges.
+const CHANGED_PIXEL_TOLERANCE: f32 = 0.005;
+/// The same for shader renders. GPU transcendentals are less precise than the CPU's, and the
+/// fractals magnify that into scattered pixels across their detail.
+const GPU_CHANGED_PIXEL_TOLERANCE: f32 = 0.02;
+
+
+fn base_scene() -> Scene {
+    let mut scene = Scene::new();
+    scene.set_camera(Camera::new(point3(0., 1.3, 0.), point3(0., 1.3, 1.), 0.0, 1.0));
+    scene.add_light(Light::point(point3(3., 5., -2.)));
+    scene
+}
+
+fn floor(scene: &mut Scene) {
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0.), Transform::none(), white);
+}
+
+fn primitive_scene(primitive: Primitive) -> Scene {
+    let mut scene = base_scene();
+    floor(&mut scene);
+    let red = scene.add_material(Material::new(rgb(1.0, 0.3, 0.2)));
+    scene.add(primitive, Transform::transrot(vec3(0., 1., 3.), Quat::from_euler(vec3(0.4, 0.3, 0.2))), red);
+    scene
+}
+
+fn primitive_cases() -> Vec<(&'static str, Scene)> {
+    let tilted_plane = {
+        let mut scene = base_scene();
+        let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+        scene.add(Primitive::Plane(vec3(0.2, 1., -0.3).normalize(), 0.5), Transform::none(), white);
+        scene
+    };
+    vec![
+        ("sphere", primitive_scene(Primitive::Sphere(0.75))),
+        ("plane", tilted_plane),
+        ("cuboid", primitive_scene(Primitive::Cuboid(vec3(0.5, 0.4, 0.6), 0.05))),
+        ("box_frame", primitive_scene(Primitive::BoxFrame(vec3(0.6, 0.5, 0.5), 0.05))),
+        ("torus", primitive_scene(Primitive::Torus(0.6, 0.2))),
+        ("horseshoe", primitive_scene(Primitive::Horseshoe(0.6, 0.15, 2.0f32.sin(), 2.0f32.cos()))),
+        ("link", primitive_scene(Primitive::Link(0.3, 0.4, 0.12))),
+        ("cone", primitive_scene(Primitive::Cone(0.5, 1.0))),
+        ("hexagonal_prism", primitive_scene(Primitive::HexagonalPrism(0.5, 0.4))),
+        ("triangular_prism", primitive_scene(Primitive::TriangularPrism(0.9, 0.4))),
+        ("capsule", primitive_scene(Primitive::Capsule(0.8, 0.3))),
+        ("capped_cylinder", primitive_scene(Primitive::CappedCylinder(0.5, 0.4))),
+        ("rounded_cylinder", primitive_scene(Primitive::RoundedCylinder(0.3, 0.1, 0.5))),
+        ("capped_cone", primitive_scene(Primitive::CappedCone(0.5, 0.5, 0.2))),
+        ("solid_angle", primitive_scene(Primitive::SolidAngle(0.6, 0.8))),
+        ("cut_sphere", primitive_scene(Primitive::CutSphere(0.7, 0.2))),
+        ("cut_hollow_sphere", primitive_scene(Primitive::CutHollowSphere(0.7, 0.2, 0.05))),
+        ("death_star", primitive_scene(Primitive::DeathStar(0.7, 0.5, 0.6))),
+        ("round_cone", primitive_scene(Primitive::RoundCone(0.4, 0.15, 0.8))),
+        ("ellipsoid", primitive_scene(Primitive::Ellipsoid(vec3(0.8, 0.5, 0.4)))),
+        ("rhombus", primitive_scene(Primitive::Rhombus(0.7, 0.4, 0.1, 0.05))),
+        ("octahedron", primitive_scene(Primitive::Octahedron(0.7))),
+        ("pyramid", primitive_scene(Primitive::Pyramid(1.0))),
+        ("triangle", primitive_scene(Primitive::Triangle(point3(-0.7, -0.5, 0.), point3(0.7, -0.4, 0.2), point3(0., 0.6, -0.1)))),
+        // orbit traps blend from red to the white floor material
+        ("mandelbulb", primitive_scene(Primitive::Mandelbulb(8.0, 8, 2.0, MaterialId(0)))),
+        ("menger_sponge", primitive_scene(Primitive::MengerSponge(0.6, 4, MaterialId(0)))),
+        ("sierpinski_tetrahedron", primitive_scene(Primitive::SierpinskiTetrahedron(0.7, 8, MaterialId(0)))),
+        ("julia_quaternion", primitive_scene(Primitive::JuliaQuaternion(Quat::new(0.6, 0.2, 0.2, -0.2), 10, 4.0, MaterialId(0)))),
+    ]
+}
+
+fn shape_cases() -> Vec<(&'static str, Scene)> {
+    let [circle, rounded_rectangle, polygon, star, arc, bezier, ellipse, contours]: [Shape2D; 8] = Shape2D::defaults().try_into().unwrap();
+    let extrude = |shape: Shape2D| primitive_scene(Primitive::Extrude(shape, 0.2, 0.02));
+    vec![
+        ("extrude_circle", extrude(circle.clone())),
+        ("extrude_rounded_rectangle", extrude(rounded_rectangle.clone())),
+        ("extrude_polygon", extrude(polygon)),
+        ("extrude_star", extrude(star.clone())),
+        ("extrude_arc", extrude(arc)),
+        ("extrude_bezier", extrude(bezier)),
+        ("extrude_ellipse", extrude(ellipse)),
+        ("extrude_contours", extrude(contours)),
+        ("revolve_circle", primitive_scene(Primitive::Revolve(Shape2D::Circle(0.2), Axis::Y, 0.5))),
+        ("revolve_rounded_rectangle", primitive_scene(Primitive::Revolve(rounded_rectangle, Axis::Z, 0.3))),
+        ("revolve_star", primitive_scene(Primitive::Revolve(star, Axis::X, 0.0))),
+        ("revolve_offset_circle", primitive_scene(Primitive::Revolve(circle, Axis::Y, 0.3))),
+        ("text", primitive_scene(Primitive::Text(Text::bundled("Ag@", 0.8), 0.1, 0.01))),
+    ]
+}
+
+/// A 33x33 grayscale PNG of a ridged hill, written to the temp dir for the heightmap cases.
+/// Written once per test run, since the terrain tests run in parallel and would otherwise
+/// read it while another one rewrites it.
+fn heightmap_png() -> String {
+    static PATH: OnceLock<String> = OnceLock::new();
+    PATH.get_or_init(write_heightmap_png).clone()
+}
+
+fn write_heightmap_png() -> String {
+    let size = 33;
+    let pixels: Vec<u8> = (0..size * size).map(|i| {
+        let (x, y) = ((i % size) as f32 / 16.0 - 1.0, (i / size) as f32 / 16.0 - 1.0);
+        let hill = (-2.0 * (x * x + y * y)).exp() * (0.8 + 0.2 * (8.0 * x).sin());
+        (hill * 255.0).round() as u8
+    }).collect();
+    let path = std::env::temp_dir().join(format!("golden_heightmap_{}.png", std::process::id()));
+    let mut encoder = png::Encoder::new(std::fs::File::create(&path).unwrap(), size as u32, size as u32);
+    encoder.set_color(png::ColorType::Grayscale);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.write_header().unwrap().write_image_data(&pixels).unwrap();
+    path.to_str().unwrap().into()
+}
+
+fn terrain_scene(source: HeightSource, height: f32) -> Scene {
+    let mut scene = base_scene();
+    let grass = scene.add_material(Material::new(rgb(0.3, 0.6, 0.25)));
+    // kept below eye level, rays that skim along the slopes for long run out of steps
+    scene.add(Primitive::Terrain(source, vec2(4., 4.), height), Transform::translation(vec3(0., -1.5, 6.)), grass);
+    scene
+}
+
+fn terrain_cases() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("terrain_fbm", terrain_scene(HeightSource::Fbm(4, 2.0, 0.5, 7, 0.35), 1.5)),
+        ("terrain_heightmap", terrain_scene(HeightSource::Image(Heightmap::load(&heightmap_png()).unwrap()), 2.0)),
+    ]
+}
+
+/// A twisted, rounded box beside a sphere, grouped so they can be baked together.
+fn voxel_source() -> (Scene, GroupId) {
+    let mut scene = Scene::new();
+    let group = scene.add_group("source", Transform::none());
+    let twisted = scene.add_child(group, Primitive::Cuboid(vec3(0.35, 0.7, 0.35), 0.0), Transform::translation(vec3(-0.3, 0., 0.)), MaterialId(0));
+    scene.modifiers_mut(twisted).unwrap().extend([Modifier::Twist(Axis::Y, 1.5), Modifier::Round(0.05)]);
+    scene.add_child(group, Primitive::Sphere(0.4), Transform::translation(vec3(0.5, -0.3, 0.)), MaterialId(0));
+    // not part of the group, so left out of the bake
+    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0.), Transform::none(), MaterialId(0));
+    (scene, group)
+}
+
+fn voxel_cases() -> Vec<(&'static str, Scene)> {
+    let (source, group) = voxel_source();
+    let grid = source.bake(SceneNode::Group(group), point3(0., 0., 0.), vec3(1., 1., 1.), [32, 32, 32]);
+    let mut stretched = primitive_scene(Primitive::VoxelSdf(grid.clone()));
+    let object = stretched.ids()[1];
+    stretched.transform_mut(object).unwrap().scale = vec3(1.2, 0.8, 1.0);
+    vec![
+        ("voxel_sdf", primitive_scene(Primitive::VoxelSdf(grid))),
+        ("voxel_sdf_scaled", stretched),
+    ]
+}
+
+fn bool_op_scene(operator: BooleanOpType) -> Scene {
+    let mut scene = base_scene();
+    floor(&mut scene);
+    let green = scene.add_material(Material::new(rgb(0.2, 1.0, 0.2)));
+    let red = scene.add_material(Material::new(rgb(1.0, 0.2, 0.2)));
+    let sphere = scene.add(Primitive::Sphere(0.6), Transform::translation(vec3(-0.2, 1., 3.)), green);
+    let cuboid = scene.add(Primitive::Cuboid(vec3(0.45, 0.45, 0.45), 0.0), Transform::transrot(vec3(0.3, 1., 3.), Quat::from_euler(vec3(0.5, 0.3, 0.0))), red);
+    scene.add_bool_op(BooleanOp::new(operator, vec![sphere.into(), cuboid.into()])).unwrap();
+    scene
+}
+
+fn bool_op_cases() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("union", bool_op_scene(BooleanOpType::Union)),
+        ("intersect", bool_op_scene(BooleanOpType::Intersect)),
+        ("subtract", bool_op_scene(BooleanOpType::Subtract)),
+        ("smooth_union", bool_op_scene(BooleanOpType::SmoothUnion(0.3))),
+        ("smooth_intersect", bool_op_scene(BooleanOpType::SmoothIntersect(0.3))),
+        ("smooth_subtract", bool_op_scene(BooleanOpType::SmoothSubtract(0.3))),
+        ("chamfer", bool_op_scene(BooleanOpType::Chamfer(0.2))),
+        ("stairs", bool_op_scene(BooleanOpType::Stairs(0.3, 4.0))),
+        ("columns", bool_op_scene(BooleanOpType::Columns(0.3, 4.0))),
+        ("xor", bool_op_scene(BooleanOpType::Xor)),
+        ("morph", bool_op_scene(BooleanOpType::Morph(0.5))),
+    ]
+}
+
+fn scale_scene(scale: Vec3) -> Scene {
+    let mut scene = base_scene();
+    floor(&mut scene);
+    let red = scene.add_material(Material::new(rgb(1.0, 0.3, 0.2)));
+    scene.add(Primitive::Torus(0.5, 0.2), Transform::new(vec3(0., 1., 3.), Quat::from_euler(vec3(0.4, 0.3, 0.2)), scale), red);
+    scene
+}
+
+fn scale_cases() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("uniform_scale", scale_scene(vec3(1.5, 1.5, 1.5))),
+        ("non_uniform_scale", scale_scene(vec3(1.6, 0.5, 1.0))),
+    ]
+}
+
+// a table of four legs under a rotated, scaled group, nested in a group that moves it
+fn hierarchy_scene() -> Scene {
+    let mut scene = base_scene();
+    floor(&mut scene);
+    let wood = scene.add_material(Material::new(rgb(0.8, 0.5, 0.3)));
+    let room = scene.add_group("Room", Transform::translation(vec3(0.2, 0., 3.5)));
+    let table = scene.add_group("Table", Transform::new(vec3(0., 0.6, 0.), Quat::from_euler(vec3(0., 0., 0.5)), vec3(1.2, 1.2, 1.2)));
+    scene.group_mut(table).unwrap().parent = Some(room);
+    scene.add_child(table, Primitive::Cuboid(vec3(0.6, 0.05, 0.4), 0.02), Transform::none(), wood);
+    for (x, z) in [(-0.5, -0.3), (0.5, -0.3), (-0.5, 0.3), (0.5, 0.3)] {
+        scene.add_child(table, Primitive::CappedCylinder(0.3, 0.05), Transform::translation(vec3(x, -0.3, z)), wood);
+    }
+    scene
+}
+
+fn hierarchy_cases() -> Vec<(&'static str, Scene)> {
+    vec![
+        ("hierarchy", hierarchy_scene()),
+    ]
+}
+
+fn modifier_scene(primitive: Primitive, modifiers: Vec<Modifier>) -> Scene {
+    let mut scene = base_scene();
+    floor(&mut scene);
+    let red = scene.add_material(Material::new(rgb(1.0, 0.3, 0.2)));
+    let id = scene.add(primitive, Transform::translation(vec3(0., 1., 3.)), red);
+    *scene.modifiers_mut(id).unwrap() = modifiers;
+    scene
+}
+
+fn modifier_cases() -> Vec<(&'static str, Scene)> {
+    // a shell with its front cut away, so the onion's inside shows
+    let onion = {
+        let mut scene = modifier_scene(Primitive::Sphere(0.7), vec![Modifier::Onion(0.05)]);
+        let cut = scene.add(Primitive::Cuboid(vec3(1., 1., 0.5), 0.0), Transform::translation(vec3(0., 1., 2.5)), MaterialId(1));
+        let shell = scene.ids()[1];
+        scene.add_bool_op(BooleanOp::new(BooleanOpType::Subtract, vec![shell.into(), cut.into()])).unwrap();
+        scene
+    };
+    // a field of spheres on the floor, with the camera between two rows
+    let repeat = {
+        let mut scene = modifier_scene(Primitive::Sphere(0.25), vec![Modifier::Repeat(vec3(1., 0., 1.))]);
+        let object = scene.ids()[1];
+        *scene.transform_mut(object).unwrap() = Transform::translation(vec3(0.5, 0.4, 3.));
+        scene
+    };
+    // modifiers on a subtree act on the combined shape, in world space
+    let subtree = {
+        let mut scene = bool_op_scene(BooleanOpType::Union);
+        let op = scene.remove_bool_op(0).unwrap();
+        scene.add_bool_op(op.with_modifiers(vec![Modifier::Mirror(vec3(1., 0., 0.), 0.), Modifier::Round(0.1)])).unwrap();
+        scene
+    };
+    vec![
+        ("repeat", repeat),
+        ("repeat_limited", modifier_scene(Primitive::Cuboid(vec3(0.2, 0.2, 0.2), 0.02), vec![Modifier::RepeatLimited(vec3(0.6, 0.6, 0.), vec3(1., 1., 0.))])),
+        ("mirror", modifier_scene(Primitive::Horseshoe(0.5, 0.1, 1.0f32.sin(), 1.0f32.cos()), vec![Modifier::Mirror(vec3(0., 1., 0.), 0.)])),
+        ("twist", modifier_scene(Primitive::Cuboid(vec3(0.3, 0.8, 0.3), 0.0), vec![Modifier::Twist(Axis::Y, 1.5)])),
+        ("bend", modifier_scene(Primitive::Cuboid(vec3(0.9, 0.15, 0.15), 0.0), vec![Modifier::Bend(Axis::Z, 0.8)])),
+        ("elongate", modifier_scene(Primitive::Sphere(0.3), vec![Modifier::Elongate(vec3(0.5, 0., 0.2))])),
+        ("onion", onion),
+        ("round", modifier_scene(Primitive::Cuboid(vec3(0.5, 0.4, 0.5), 0.0), vec![Modifier::Round(0.15)])),
+        ("subtree_modifiers", subtree),
+    ]
+}
+
+fn shading_cases() -> Vec<(&'static str, Scene)> {
+    let names = [
+        ("shadows_ao", true, true),
+        ("shadows", true, false),
+        ("ao", false, true),
+        ("no_shadows_no_ao", false, false),
+    ];
+    let spot = |name, inner_angle| {
+        let mut scene = bool_op_scene(BooleanOpType::SmoothUnion(0.3));
+        scene.add_light(Light::Spot {
+            position: point3(0., 4., 3.), direction: vec3(0., -1., 0.), color: rgb(1.0, 0.8, 0.6), intensity: 1.0, range: 0.0, inner_angle, outer_angle: 0.35,
+        });
+        (name, scene)
+    };
+    names.iter().map(|&(name, shadows, ambient_occlusion)| {
+        let mut scene = bool_op_scene(BooleanOpType::SmoothUnion(0.3));
+        scene.params.shadows = shadows;
+        scene.params.ambient_occlusion = ambient_occlusion;
+        (name, scene)
+    }).chain([spot("spot", 0.2), spot("spot_hard_edge", 0.35)]).collect()
+}
+
+
+// sRGB to CIELAB, with a D65 white point
+fn lab(c: Rgb) -> Vec3 {
+    let linear = |c: f32| if c <= 0.04045 { c / 12.92 } else { ((c + 0.055) / 1.055).powf(2.4) };
+    let (r, g, b) = (linear(c.x), linear(c.y), linear(c.z));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f32| if t > 0.008856 { t.cbrt() } else { 7.787 * t + 16.0 / 116.0 };
+    vec3(116.0 * f(y) - 16.0, 500.0 * (f(x) - f(y)), 200.0 * (f(y) - f(z)))
+}
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests").join("golden")
+}
+
+fn diff_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("target").join("golden-diff")
+}
+
+/// Renders and compares one case, returning a description of the failure if it doesn't match.
+fn check(name: &str, scene: &Scene) -> Option<String> {
+    let actual = scene.render(WIDTH, HEIGHT);
+    let golden_path = golden_dir().join(format!("{}.png", name));
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        std::fs::create_dir_all(golden_dir()).unwrap();
+        actual.save_png(&golden_path).unwrap();
+        return None;
+    }
+    compare(name, &actual, CHANGED_PIXEL_TOLERANCE, &diff_dir())
+}
+
+/// Compares a rendered case against its golden image, allowing `tolerance` of the pixels to
+/// differ, and writes the render and a diff to `dir` if it doesn't match.
+fn compare(name: &str, actual: &Image, tolerance: f32, dir: &Path) -> Option<String> {
+    std::fs::create_dir_all(dir).unwrap();
+    let golden_path = golden_dir().join(format!("{}.png", name));
+    let actual_path = dir.join(format!("{}.actual.png", name));
+    let golden = match Image::load_png(&golden_path) {
+        Ok(golden) => golden,
+        Err(e) => {
+            actual.save_png(&actual_path).unwrap();
+            return Some(format!("{}: can't load {} ({}), run with UPDATE_GOLDEN=1 to create it", name, golden_path.display(), e));
+        },
+    };
+    if (golden.width, golden.height) != (actual.width, actual.height) {
+        actual.save_png(&actual_path).unwrap();
+        return Some(format!("{}: golden image is {}x{}, expected {}x{}", name, golden.width, golden.height, WIDTH, HEIGHT));
+    }
+
+    let mut diff = Image::new(WIDTH, HEIGHT);
+    let mut changed = 0;
+    let mut worst: f32 = 0.0;
+    // quantized the same way as the saved golden image
+    let quantized = actual.to_rgb8();
+    for (i, (a, g)) in quantized.chunks(3).zip(golden.pixels.iter()).enumerate() {
+        let a = rgb(a[0] as f32 / 255.0, a[1] as f32 / 255.0, a[2] as f32 / 255.0);
+        let delta = (lab(a) - lab(*g)).length();
+        worst = worst.max(delta);
+        if delta > PIXEL_TOLERANCE {
+            changed += 1;
+            diff.pixels[i] = rgb(1.0, 0.0, 0.0);
+        } else {
+            diff.pixels[i] = *g * 0.3;
+        }
+    }
+    let allowed = (tolerance * actual.pixels.len() as f32) as usize;
+    if changed <= allowed {
+        return None;
+    }
+    actual.save_png(&actual_path).unwrap();
+    let diff_path = dir.join(format!("{}.diff.png", name));
+    diff.save_png(&diff_path).unwrap();
+    Some(format!("{}: {} pixels differ (at most {} allowed, worst delta E {:.1}), see {}",
+        name, changed, allowed, worst, diff_path.display()))
+}
+
+fn check_all(cases: Vec<(&'static str, Scene)>) {
+    let failures: Vec<String> = cases.iter().filter_map(|(name, scene)| check(name, scene)).collect();
+    assert!(failures.is_empty(), "golden images differ:\n{}", failures.join("\n"));
+}
+
+
+#[test]
+fn primitives() {
+    check_all(primitive_cases());
+}
+
+#[test]
+fn fractals_are_finite_at_the_origin() {
+    let fractals = [
+        Primitive::Mandelbulb(8.0, 8, 2.0, MaterialId(0)),
+        Primitive::JuliaQuaternion(Quat::new(0.0, 0.0, 0.0, 0.0), 10, 4.0, MaterialId(0)),
+    ];
+    for fractal in fractals {
+        let (distance, trap) = fractal.distance_and_trap(point3(0., 0., 0.));
+        assert!(distance.is_finite() && trap.is_finite(), "{} at the origin is {}, {}", fractal.as_str(), distance, trap);
+    }
+}
+
+#[test]
+fn shapes() {
+    check_all(shape_cases());
+}
+
+#[test]
+fn every_shape_has_a_case() {
+    let mut ids: Vec<i32> = shape_cases().iter()
+        .flat_map(|(_, scene)| scene.objects().iter().filter_map(|obj| obj.shape()).map(|shape| shape.id() as i32).collect::<Vec<_>>())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+}
+
+#[test]
+fn stars_out_of_range_are_clamped_and_rejected() {
+    for star in [Shape2D::Star(0.5, 0, 1.0), Shape2D::Star(0.5, 1, 2.0), Shape2D::Star(0.5, 5, 1.0), Shape2D::Star(0.5, 5, 6.0)] {
+        assert!(!star.is_valid());
+        assert!(star.distance(vec2(0.2, 0.3)).is_finite());
+        let scene = primitive_scene(Primitive::Extrude(star, 0.2, 0.02));
+        assert!(matches!(Scene::from_ron(&scene.to_ron().unwrap()), Err(SceneError::InvalidShape(_))));
+    }
+    assert!(Shape2D::Star(0.5, 2, 2.0).is_valid());
+}
+
+#[test]
+fn text_is_laid_out_again_when_loaded() {
+    let scene = primitive_scene(Primitive::Text(Text::bundled("Hi\nthere", 0.5), 0.1, 0.0));
+    let ron = scene.to_ron().unwrap();
+    assert!(!ron.contains("Contours"));
+    let loaded = Scene::from_ron(&ron).unwrap();
+    assert!(loaded.objects() == scene.objects());
+
+    let missing = ron.replace(&format!("font: \"{}\"", FONT_NAME), "font: \"no/such/font.ttf\"");
+    assert!(Scene::from_ron(&missing).is_err());
+}
+
+#[test]
+fn text_bevels_keep_the_glyph_size() {
+    let text = Text::bundled("H", 0.8);
+    let (depth, bevel) = (0.1, 0.03);
+    // a point inside a stroke, and one beside the glyph
+    let samples: Vec<Vec2> = (-20..=20).flat_map(|x| (-20..=20).map(move |y| vec2(x as f32, y as f32) * 0.02)).collect();
+    let inside = *samples.iter().min_by(|a, b| text.outline().distance(**a).total_cmp(&text.outline().distance(**b))).unwrap();
+    assert!(text.outline().distance(inside) < -bevel);
+    let beside = inside + vec2(1.0, 0.0);
+    let flat = Primitive::Text(text.clone(), depth, 0.0);
+    let bevelled = Primitive::Text(text, depth, bevel);
+    for p in [point3(inside.x, inside.y, depth + 0.2), point3(beside.x, beside.y, 0.0)] {
+        assert!((flat.distance(p) - bevelled.distance(p)).abs() < 1e-4, "{:?}: {} vs {}", p, flat.distance(p), bevelled.distance(p));
+    }
+}
+
+#[test]
+fn terrain() {
+    check_all(terrain_cases());
+}
+
+#[test]
+fn terrain_slopes_stay_within_their_bound() {
+    let size = vec2(4., 4.);
+    let sources = [
+        HeightSource::Fbm(5, 2.0, 0.5, 7, 0.6),
+        HeightSource::Fbm(3, 3.0, 0.7, 1, 2.0),
+        HeightSource::Image(Heightmap::load(&heightmap_png()).unwrap()),
+    ];
+    for source in sources.iter() {
+        let bound = source.lipschitz(size);
+        for i in 0..2000 {
+            let a = vec2((i % 50) as f32 * 0.161 - 4.0, (i / 50) as f32 * 0.2 - 4.0);
+            for d in [vec2(0.01, 0.), vec2(0., 0.01), vec2(0.007, -0.007)] {
+                let slope = (source.height(a + d, size) - source.height(a, size)).abs() / d.length();
+                assert!(slope <= bound * 1.001, "{:?} slope {} at {:?} over bound {}", source, slope, a, bound);
+            }
+        }
+    }
+}
+
+#[test]
+fn voxels() {
+    check_all(voxel_cases());
+}
+
+#[test]
+fn baked_voxels_follow_their_subtree() {
+    let (source, group) = voxel_source();
+    let half = vec3(1., 1., 1.);
+    let grid = source.bake(SceneNode::Group(group), point3(0., 0.2, 0.), half, [24, 24, 24]);
+    let program = source.subtree_program(SceneNode::Group(group));
+    // the plane runs through the box, but only the group's objects are baked
+    assert!(source.distance(point3(0., 0.2 - 0.9, 0.9)) < 0.0);
+    let cell = (half * (2.0 / 23.0)).length();
+    for i in 0..1000 {
+        let p = vec3((i % 10) as f32 * 0.2 - 0.9, (i / 10 % 10) as f32 * 0.2 - 0.9, (i / 100) as f32 * 0.2 - 0.9);
+        let baked = Primitive::VoxelSdf(grid.clone()).distance(p);
+        let actual = source.evaluate_program(&program, p + vec3(0., 0.2, 0.)).unwrap().distance;
+        assert!((baked - actual).abs() <= cell, "baked {} but {} at {:?}", baked, actual, p);
+    }
+    // outside the box the distance stays a bound on the distance to the grid's surface
+    let voxel = Primitive::VoxelSdf(grid);
+    for p in [vec3(3., 0., 0.), vec3(1.5, 1.5, -1.5), vec3(0., -1.2, 0.)] {
+        assert!(voxel.distance(p) >= (p.abs() - half).max(0.0).length() - 1e-4);
+    }
+}
+
+#[test]
+fn voxel_grids_load_from_their_file() {
+    let (source, group) = voxel_source();
+    let mut grid = source.bake(SceneNode::Group(group), point3(0., 0., 0.), vec3(1., 0.8, 0.6), [8, 6, 4]);
+    let unsaved = primitive_scene(Primitive::VoxelSdf(grid.clone()));
+    assert!(matches!(unsaved.to_ron(), Err(SceneError::Serialize(_))));
+
+    let path = std::env::temp_dir().join(format!("golden_voxels_{}.sdf", std::process::id()));
+    grid.save(path.to_str().unwrap()).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().len(), 32 + 8 * 6 * 4 * 4);
+    let scene = primitive_scene(Primitive::VoxelSdf(grid));
+    let loaded = Scene::from_ron(&scene.to_ron().unwrap()).unwrap();
+    assert!(loaded.objects() == scene.objects());
+}
+
+#[test]
+fn headerless_voxel_grids_load_with_their_size() {
+    let (source, group) = voxel_source();
+    let grid = source.bake(SceneNode::Group(group), point3(0., 0., 0.), vec3(1., 1., 1.), [8, 6, 4]);
+    let path = std::env::temp_dir().join(format!("golden_voxels_{}.raw", std::process::id()));
+    let bytes: Vec<u8> = (0..4).flat_map(|z| (0..6).flat_map(move |y| (0..8).map(move |x| (x, y, z))))
+        .flat_map(|(x, y, z)| grid.sample(x, y, z).to_le_bytes())
+        .collect();
+    std::fs::write(&path, bytes).unwrap();
+    let raw = VoxelGrid::load_raw(path.to_str().unwrap(), [8, 6, 4], vec3(1., 1., 1.)).unwrap();
+    let scene = primitive_scene(Primitive::VoxelSdf(raw));
+    let ron = scene.to_ron().unwrap();
+    assert!(ron.contains("resolution: (8, 6, 4)"));
+    let loaded = Scene::from_ron(&ron).unwrap();
+    assert!(loaded.objects() == scene.objects());
+
+    let wrong_size = ron.replace("resolution: (8, 6, 4)", "resolution: (8, 6, 5)");
+    assert!(Scene::from_ron(&wrong_size).is_err());
+    // without a header, the size can't come from the file
+    assert!(VoxelGrid::load(path.to_str().unwrap()).is_err());
+    let flat = ron.replace("half_size: (1.0, 1.0, 1.0)", "half_size: (1.0, 0.0, 1.0)");
+    assert!(matches!(Scene::from_ron(&flat), Err(SceneError::Asset(..))));
+}
+
+#[test]
+fn voxel_grid_headers_are_checked() {
+    let path = std::env::temp_dir().join(format!("golden_voxels_{}_header.sdf", std::process::id()));
+    let header = |resolution: [u32; 3], half_size: [f32; 3]| -> Vec<u8> {
+        let mut bytes = b"VOXELSDF".to_vec();
+        bytes.extend(resolution.iter().flat_map(|n| n.to_le_bytes()));
+        bytes.extend(half_size.iter().flat_map(|x| x.to_le_bytes()));
+        bytes
+    };
+    // the sample count overflows, and wraps to zero in 64 bits
+    std::fs::write(&path, header([1 << 31, 1 << 31, 4], [1., 1., 1.])).unwrap();
+    assert!(VoxelGrid::load(path.to_str().unwrap()).is_err());
+    for half_size in [[1., 0., 1.], [-1., 1., 1.], [1., 1., f32::NAN]] {
+        let mut bytes = header([2, 2, 2], half_size);
+        bytes.extend([0u8; 8 * 4]);
+        std::fs::write(&path, bytes).unwrap();
+        assert!(VoxelGrid::load(path.to_str().unwrap()).is_err());
+    }
+}
+
+#[test]
+fn relative_asset_paths_are_found_next_to_the_scene_file() {
+    let dir = std::env::temp_dir().join(format!("golden_assets_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::copy(heightmap_png(), dir.join("hill.png")).unwrap();
+    let (source, group) = voxel_source();
+    let mut grid = source.bake(SceneNode::Group(group), point3(0., 0., 0.), vec3(1., 1., 1.), [4, 4, 4]);
+    grid.save(dir.join("grid.sdf").to_str().unwrap()).unwrap();
+    let mut scene = terrain_scene(HeightSource::Image(Heightmap::load(dir.join("hill.png").to_str().unwrap()).unwrap()), 1.0);
+    scene.add(Primitive::VoxelSdf(grid), Transform::none(), MaterialId(0));
+    scene.save(dir.join("scene.ron")).unwrap();
+    let ron = std::fs::read_to_string(dir.join("scene.ron")).unwrap();
+    assert!(ron.contains("\"hill.png\"") && ron.contains("\"grid.sdf\""));
+
+    let loaded = Scene::load(dir.join("scene.ron")).unwrap();
+    assert_eq!(loaded.objects()[0].heightmap().unwrap().path(), dir.join("hill.png").to_str().unwrap());
+    loaded.save(dir.join("again.ron")).unwrap();
+    assert_eq!(std::fs::read_to_string(dir.join("again.ron")).unwrap(), ron);
+    // saved elsewhere, the assets are still found
+    let elsewhere = dir.join("elsewhere");
+    std::fs::create_dir_all(&elsewhere).unwrap();
+    loaded.save(elsewhere.join("scene.ron")).unwrap();
+    assert!(Scene::load(elsewhere.join("scene.ron")).unwrap().objects() == loaded.objects());
+    // outside a load, relative paths are still relative to the working directory
+    assert!(Scene::from_ron(&ron).is_err());
+}
+
+#[test]
+fn boolean_ops_deeper_than_the_shader_stack_are_rejected() {
+    let mut scene = Scene::new();
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    let mut op = BooleanOp::new(BooleanOpType::Union, vec![scene.add(Primitive::Sphere(0.5), Transform::none(), white).into()]);
+    // each op nested as a second child takes another stack slot, until there are too many
+    let error = loop {
+        let sphere = scene.add(Primitive::Sphere(0.5), Transform::none(), white);
+        op = BooleanOp::new(BooleanOpType::Union, vec![sphere.into(), op.into()]);
+        if let Err(e) = scene.add_bool_op(op.clone()) {
+            break e;
+        }
+    };
+    let deepest = scene.bool_ops().last().unwrap().depth();
+    assert!(matches!(error, SceneError::CsgTooDeep(slots) if slots == deepest + 2), "{}", error);
+    assert!(Scene::from_ron(&scene.to_ron().unwrap()).is_ok());
+}
+
+#[test]
+fn boolean_ops() {
+    check_all(bool_op_cases());
+}
+
+#[test]
+fn scale() {
+    check_all(scale_cases());
+}
+
+#[test]
+fn scales_that_arent_positive_are_rejected() {
+    for scale in [vec3(0., 1., 1.), vec3(1., -1., 1.)] {
+        let ron = scale_scene(scale).to_ron().unwrap();
+        assert!(matches!(Scene::from_ron(&ron), Err(SceneError::InvalidScale(_))));
+        let mut grouped = hierarchy_scene();
+        let group = grouped.group_ids()[0];
+        grouped.group_mut(group).unwrap().transform.scale = scale;
+        assert!(matches!(Scene::from_ron(&grouped.to_ron().unwrap()), Err(SceneError::InvalidScale(_))));
+    }
+}
+
+#[test]
+fn group_scales_that_arent_uniform_are_rejected() {
+    let mut scene = hierarchy_scene();
+    let group = scene.group_ids()[1];
+    scene.group_mut(group).unwrap().transform.scale = vec3(1.2, 1.0, 1.0);
+    assert!(matches!(Scene::from_ron(&scene.to_ron().unwrap()), Err(SceneError::NonUniformScale(_))));
+}
+
+#[test]
+fn hierarchy() {
+    check_all(hierarchy_cases());
+}
+
+#[test]
+fn modifiers() {
+    check_all(modifier_cases());
+}
+
+#[test]
+fn twists_and_bends_keep_distances_a_bound() {
+    let (cell, n) = (0.05, [24i32, 24, 8]);
+    let index = |[x, y, z]: [i32; 3]| (((z + n[2]) * (2 * n[1] + 1) + y + n[1]) * (2 * n[0] + 1) + x + n[0]) as usize;
+    let cells: Vec<[i32; 3]> = (-n[2]..=n[2]).flat_map(|z| (-n[1]..=n[1]).flat_map(move |y| (-n[0]..=n[0]).map(move |x| [x, y, z]))).collect();
+    for modifier in [Modifier::Twist(Axis::Y, 3.0), Modifier::Bend(Axis::Z, 1.5)] {
+        let mut scene = Scene::new();
+        let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+        let slab = scene.add(Primitive::Cuboid(vec3(0.8, 0.8, 0.2), 0.0), Transform::none(), white);
+        scene.modifiers_mut(slab).unwrap().push(modifier);
+        let distances: Vec<f32> = cells.iter().map(|c| scene.distance(vec3(c[0] as f32, c[1] as f32, c[2] as f32) * cell)).collect();
+        // no point nearer than a sample's distance is on the other side of the surface
+        for c in cells.iter().copied() {
+            let d = distances[index(c)];
+            let reach = ((d.abs() / cell) as i32).min(4);
+            for offset in (-reach..=reach).flat_map(|z| (-reach..=reach).flat_map(move |y| (-reach..=reach).map(move |x| [x, y, z]))) {
+                let q = [c[0] + offset[0], c[1] + offset[1], c[2] + offset[2]];
+                let gap = vec3(offset[0] as f32, offset[1] as f32, offset[2] as f32).length() * cell;
+                if (0..3).all(|i| q[i].abs() <= n[i]) && gap < d.abs() - 1e-3 {
+                    assert!(distances[index(q)].signum() == d.signum(), "{} distance {} at {:?} reaches past the surface", modifier.as_str(), d, c);
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn every_modifier_has_a_case() {
+    let mut ids: Vec<i32> = modifier_cases().iter()
+        .flat_map(|(_, scene)| {
+            let ops = scene.bool_ops().iter().flat_map(|op| op.modifiers.clone());
+            scene.ids().iter().flat_map(|id| scene.modifiers(*id).unwrap().to_vec()).chain(ops).map(|m| m.id() as i32).collect::<Vec<_>>()
+        })
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids, (1..=8).collect::<Vec<_>>());
+}
+
+#[test]
+fn reparenting_keeps_world_placement() {
+    let mut scene = hierarchy_scene();
+    let room = scene.group_ids()[0];
+    let table = scene.group_ids()[1];
+    let legs: Vec<ObjectId> = scene.ids()[2..].to_vec();
+    // flatten the hierarchy one step at a time, it should render the same throughout
+    scene.set_parent(SceneNode::Object(legs[0]), None);
+    scene.set_parent(SceneNode::Object(legs[1]), Some(room));
+    assert!(!scene.set_parent(SceneNode::Group(room), Some(table)));
+    scene.remove_group(table);
+    scene.remove_group(room);
+    assert!(scene.groups().is_empty());
+    assert!(scene.ids().iter().all(|id| scene.parent(SceneNode::Object(*id)).is_none()));
+    assert_eq!(check("hierarchy", &scene), None);
+}
+
+#[test]
+fn shadows_and_ambient_occlusion() {
+    check_all(shading_cases());
+}
+
+#[test]
+fn light_directions_are_normalized() {
+    let color = rgb(1.0, 1.0, 1.0);
+    let lights = [
+        Light::Directional { direction: vec3(0., -3., 4.), color, intensity: 1.0 },
+        Light::Spot { position: point3(0., 2., 0.), direction: vec3(0., -0.5, 0.), color, intensity: 1.0, range: 0.0, inner_angle: 0.3, outer_angle: 0.4 },
+        Light::Directional { direction: vec3(0., 0., 0.), color, intensity: 1.0 },
+    ];
+    for light in lights {
+        let [x, y, z, _] = light.get_data()[1];
+        assert!((vec3(x, y, z).length() - 1.0).abs() < 1e-6);
+    }
+}
+
+#[test]
+fn spots_with_equal_angles_have_a_hard_edge() {
+    let spot = |inner_angle, outer_angle| Light::Spot {
+        position: point3(0., 4., 3.), direction: vec3(0., -1., 0.), color: rgb(1.0, 1.0, 1.0), intensity: 1.0, range: 0.0, inner_angle, outer_angle,
+    };
+    let mut scene = Scene::new();
+    scene.set_camera(Camera::new(point3(0., 1.3, 0.), point3(0., 1.3, 1.), 0.0, 1.0));
+    floor(&mut scene);
+    scene.add_light(spot(0.3, 0.3));
+    let image = scene.render(WIDTH, HEIGHT);
+    assert!(image.pixels.iter().all(|c| c.x.is_finite() && c.y.is_finite() && c.z.is_finite()));
+
+    // an inner angle wider than the outer one is drawn as a hard edge, and rejected on load
+    assert_eq!(spot(0.5, 0.3).get_data()[3], spot(0.3, 0.3).get_data()[3]);
+    scene.add_light(spot(0.5, 0.3));
+    assert!(matches!(Scene::from_ron(&scene.to_ron().unwrap()), Err(SceneError::InvalidLight(1))));
+    let mut out_of_range = Scene::new();
+    out_of_range.add_light(spot(0.3, 4.0));
+    assert!(matches!(Scene::from_ron(&out_of_range.to_ron().unwrap()), Err(SceneError::InvalidLight(0))));
+}
+
+#[test]
+fn every_primitive_has_a_case() {
+    let mut ids: Vec<i32> = primitive_cases().iter().chain(shape_cases().iter()).chain(terrain_cases().iter())
+        .chain(voxel_cases().iter())
+        .flat_map(|(_, scene)| scene.objects().iter().map(|obj| obj.id() as i32).collect::<Vec<_>>())
+        .collect();
+    ids.sort();
+    ids.dedup();
+    assert_eq!(ids, (1..=33).collect::<Vec<_>>());
+}
+
+
+/// A windowless OpenGL context for glium, drawing only to textures.
+struct Headless {
+    context: PossiblyCurrentContext,
+    display: Display,
+}
+
+unsafe impl glium::backend::Backend for Headless {
+    fn swap_buffers(&self) -> Result<(), glium::SwapBuffersError> {
+        Ok(())
+    }
+    unsafe fn get_proc_address(&self, symbol: &str) -> *const std::ffi::c_void {
+        self.display.get_proc_address(&CString::new(symbol).unwrap())
+    }
+    fn get_framebuffer_dimensions(&self) -> (u32, u32) {
+        (WIDTH, HEIGHT)
+    }
+    fn is_current(&self) -> bool {
+        self.context.is_current()
+    }
+    unsafe fn make_current(&self) {
+        self.context.make_current_surfaceless().unwrap();
+    }
+}
+
+/// An OpenGL 4.1 context on the first EGL device, if there is one.
+fn headless() -> Option<Rc<glium::backend::Context>> {
+    let device = Device::query_devices().ok()?.next()?;
+    let display = unsafe { Display::with_device(&device, None) }.ok()?;
+    let template = ConfigTemplateBuilder::new().with_surface_type(ConfigSurfaceTypes::empty()).build();
+    let config = unsafe { display.find_configs(template) }.ok()?.next()?;
+    let attributes = ContextAttributesBuilder::new().with_context_api(ContextApi::OpenGl(Some(Version::new(4, 1)))).build(None);
+    let context = unsafe { display.create_context(&config, &attributes) }.ok()?.make_current_surfaceless().ok()?;
+    unsafe { glium::backend::Context::new(Headless { context, display }, true, Default::default()) }.ok()
+}
+
+#[test]
+fn shaders_match_the_cpu_renderer() {
+    let Some(context) = headless() else {
+        eprintln!("no EGL device, skipping");
+        return;
+    };
+    let mut gpu = GpuRenderer::new(&context).unwrap();
+    let cases = [primitive_cases(), shape_cases(), terrain_cases(), voxel_cases(), bool_op_cases(),
+        scale_cases(), hierarchy_cases(), modifier_cases(), shading_cases()].into_iter().flatten();
+    let failures: Vec<String> = cases.filter_map(|(name, scene)| {
+        let texture = glium::texture::Texture2d::empty(&context, WIDTH, HEIGHT).unwrap();
+        let mut target = glium::framebuffer::SimpleFrameBuffer::new(&context, &texture).unwrap();
+        gpu.draw(&context, &mut target, &scene, 0.0, [0.0; 4]).unwrap();
+        let rows: Vec<Vec<(u8, u8, u8, u8)>> = texture.read();
+        let mut actual = Image::new(WIDTH, HEIGHT);
+        // textures are read bottom row first
+        for (pixel, (r, g, b, _)) in actual.pixels.iter_mut().zip(rows.iter().rev().flatten()) {
+            *pixel = rgb(*r as f32 / 255.0, *g as f32 / 255.0, *b as f32 / 255.0);
+        }
+        compare(name, &actual, GPU_CHANGED_PIXEL_TOLERANCE, &diff_dir().join("gpu"))
+    }).collect();
+    assert!(failures.is_empty(), "shader renders differ from the golden images:\n{}", failures.join("\n"));
+}
+
+#[test]
+fn voxel_grids_too_deep_to_stack_are_reported() {
+    let Some(context) = headless() else {
+        eprintln!("no EGL device, skipping");
+        return;
+    };
+    let mut gpu = GpuRenderer::new(&context).unwrap();
+    let depth = MAX_VOXEL_TEXTURE_SIZE / 2 + 1;
+    let grid = VoxelGrid::new([2, 2, depth], vec3(1., 1., 1.), vec![0.0; 4 * depth as usize]);
+    let mut scene = base_scene();
+    scene.add(Primitive::VoxelSdf(grid.clone()), Transform::none(), MaterialId(0));
+    let second = scene.add(Primitive::VoxelSdf(grid), Transform::none(), MaterialId(0));
+    let texture = glium::texture::Texture2d::empty(&context, WIDTH, HEIGHT).unwrap();
+    let mut target = glium::framebuffer::SimpleFrameBuffer::new(&context, &texture).unwrap();
+    match gpu.draw(&context, &mut target, &scene, 0.0, [0.0; 4]) {
+        Err(DrawError::Upload(e)) => assert!(e.contains(&format!("object {}'s", second.0)), "{}", e),
+        other => panic!("expected an upload error, got {:?}", other),
+    }
+}
@@ -0,0 +1,114 @@
+//! Checks on meshing the distance field and writing the meshes out.
+use gpu_raymarching::*;
+
+
+
+/// Every edge is shared by exactly two triangles, running opposite ways in each.
+fn assert_closed(mesh: &Mesh) {
+    let mut edges = std::collections::HashMap::new();
+    for t in mesh.triangles.iter() {
+        for i in 0..3 {
+            *edges.entry((t[i], t[(i + 1) % 3])).or_insert(0) += 1;
+        }
+    }
+    for (&(a, b), count) in edges.iter() {
+        assert_eq!((*count, edges.get(&(b, a))), (1, Some(&1)), "edge {}-{} isn't shared by two triangles", a, b);
+    }
+}
+
+#[test]
+fn meshes_are_closed_and_face_out() {
+    let mut scene = Scene::new();
+    let red = scene.add_material(Material::new(rgb(1.0, 0.3, 0.2)));
+    scene.add(Primitive::Sphere(0.8), Transform::translation(vec3(0.1, 0.2, 0.)), red);
+    for method in [MeshMethod::MarchingCubes, MeshMethod::DualContouring] {
+        let mesh = scene.mesh(point3(-1., -1., -1.), point3(1., 1., 1.), 20, method);
+        assert!(mesh.triangles.len() > 500, "{:?}", method);
+        assert_closed(&mesh);
+        for (p, n) in mesh.positions.iter().zip(mesh.normals.iter()) {
+            assert!(scene.distance(*p).abs() < 0.02, "{:?} vertex {:?} off the surface", method, p);
+            assert!(n.dot((*p - vec3(0.1, 0.2, 0.)).normalize()) > 0.95);
+        }
+        for t in mesh.triangles.iter() {
+            let [a, b, c] = t.map(|i| mesh.positions[i as usize]);
+            assert!((b - a).cross(c - a).dot(a - vec3(0.1, 0.2, 0.)) > 0.0, "{:?} triangle faces in", method);
+        }
+        assert!(mesh.colors.iter().all(|c| *c == rgb(1.0, 0.3, 0.2)));
+    }
+}
+
+#[test]
+fn dual_contouring_keeps_box_corners_sharp() {
+    let mut scene = Scene::new();
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    scene.add(Primitive::Cuboid(vec3(0.53, 0.41, 0.37), 0.0), Transform::none(), white);
+    // a plane is cut off at the bounds into a closed slab
+    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0.), Transform::translation(vec3(0., -0.8, 0.)), white);
+    let mesh = scene.mesh(point3(-1., -1., -1.), point3(1., 1., 1.), 16, MeshMethod::DualContouring);
+    assert_closed(&mesh);
+    let nearest = |corner: Vec3| mesh.positions.iter().map(|p| (*p - corner).length()).fold(1e20f32, f32::min);
+    for i in 0..8 {
+        let corner = vec3(0.53, 0.41, 0.37) * vec3([-1., 1.][i & 1], [-1., 1.][i >> 1 & 1], [-1., 1.][i >> 2 & 1]);
+        assert!(nearest(corner) < 0.01, "corner {:?} is {} from the mesh", corner, nearest(corner));
+    }
+    assert!(nearest(vec3(1., -1., 1.)) < 0.01);
+    let rounded = scene.mesh(point3(-1., -1., -1.), point3(1., 1., 1.), 16, MeshMethod::MarchingCubes);
+    assert_closed(&rounded);
+}
+
+#[test]
+fn meshes_write_every_format() {
+    let mut scene = Scene::new();
+    let red = scene.add_material(Material::new(rgb(1.0, 0.0, 0.0)));
+    scene.add(Primitive::Cuboid(vec3(0.5, 0.5, 0.5), 0.1), Transform::none(), red);
+    let mesh = scene.mesh(point3(-1., -1., -1.), point3(1., 1., 1.), 8, MeshMethod::MarchingCubes);
+    let (v, t) = (mesh.positions.len(), mesh.triangles.len());
+    let write = |format| {
+        let mut bytes = Vec::new();
+        mesh.write(&mut bytes, format).unwrap();
+        bytes
+    };
+
+    let obj = String::from_utf8(write(MeshFormat::Obj)).unwrap();
+    assert_eq!(obj.lines().filter(|l| l.starts_with("v ")).count(), v);
+    assert_eq!(obj.lines().filter(|l| l.starts_with("f ")).count(), t);
+    assert!(obj.starts_with("v ") && obj.lines().next().unwrap().ends_with(" 1 0 0"));
+
+    assert_eq!(write(MeshFormat::Stl).len(), 84 + 50 * t);
+
+    let ply = write(MeshFormat::Ply);
+    let header_end = ply.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+    assert!(String::from_utf8_lossy(&ply[..header_end]).contains(&format!("element face {}", t)));
+    assert_eq!(ply.len() - header_end, v * 27 + t * 13);
+    assert_eq!(&ply[header_end + 24..header_end + 27], &[255, 0, 0]);
+
+    let gltf = String::from_utf8(write(MeshFormat::Gltf)).unwrap();
+    let buffer_len = v * 36 + t * 12;
+    assert!(gltf.contains(&format!(r#""byteLength":{},"uri":"data:application/octet-stream;base64,"#, buffer_len)));
+    assert!(gltf.ends_with("\"}]}"));
+
+    let glb = write(MeshFormat::Glb);
+    assert_eq!(&glb[..4], b"glTF");
+    assert_eq!(u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize, glb.len());
+    let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    assert_eq!(&glb[20 + json_len + 4..20 + json_len + 8], b"BIN\0");
+    assert_eq!(MeshFormat::from_path("out/Model.GLB"), Some(MeshFormat::Glb));
+    assert_eq!(MeshFormat::from_path("model.fbx"), None);
+}
+
+#[test]
+fn empty_meshes_write_an_empty_gltf_scene() {
+    let mesh = Scene::new().mesh(point3(-1., -1., -1.), point3(1., 1., 1.), 8, MeshMethod::MarchingCubes);
+    assert!(mesh.triangles.is_empty());
+    let mut gltf = Vec::new();
+    mesh.write(&mut gltf, MeshFormat::Gltf).unwrap();
+    let gltf = String::from_utf8(gltf).unwrap();
+    assert!(gltf.contains(r#""scenes":[{}]"#) && !gltf.contains("buffers"));
+
+    let mut glb = Vec::new();
+    mesh.write(&mut glb, MeshFormat::Glb).unwrap();
+    let json_len = u32::from_le_bytes(glb[12..16].try_into().unwrap()) as usize;
+    assert_eq!(u32::from_le_bytes(glb[8..12].try_into().unwrap()) as usize, glb.len());
+    assert_eq!(glb.len(), 20 + json_len);
+    assert_eq!(String::from_utf8_lossy(&glb[20..]).trim_end(), gltf);
+}
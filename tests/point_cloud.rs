@@ -0,0 +1,85 @@
+//! Checks on sampling point clouds from scenes and writing them out.
+use gpu_raymarching::*;
+
+
+
+fn two_spheres() -> (Scene, ObjectId, ObjectId) {
+    let mut scene = Scene::new();
+    let red = scene.add_material(Material::new(rgb(1.0, 0.0, 0.0)));
+    let blue = scene.add_material(Material::new(rgb(0.0, 0.0, 1.0)));
+    let small = scene.add(Primitive::Sphere(0.25), Transform::translation(vec3(-0.5, 0., 0.)), red);
+    let large = scene.add(Primitive::Sphere(0.5), Transform::translation(vec3(0.4, 0., 0.)), blue);
+    (scene, small, large)
+}
+
+#[test]
+fn point_samples_cover_the_surface_evenly() {
+    let (scene, small, large) = two_spheres();
+    let sampling = PointSampling {
+        min: point3(-1., -1., -1.),
+        max: point3(1., 1., 1.),
+        seed: 3,
+        surface: 4000,
+        near_surface: 500,
+        volume: 500,
+        near_surface_spread: 0.05,
+    };
+    let cloud = scene.sample_points(&sampling);
+    assert_eq!((cloud.len(), cloud.shortfall), (5000, 0));
+    let surface: Vec<usize> = (0..cloud.len()).filter(|i| cloud.kinds[*i] == SampleKind::Surface).collect();
+    assert_eq!(surface.len(), 4000);
+    for &i in surface.iter() {
+        let p = cloud.positions[i];
+        assert!(scene.distance(p).abs() < 1e-3, "{:?} is off the surface", p);
+        let centre = if cloud.objects[i] == small { vec3(-0.5, 0., 0.) } else { vec3(0.4, 0., 0.) };
+        assert!(cloud.normals[i].dot((p - centre).normalize()) > 0.99);
+        assert_eq!(cloud.colors[i], if cloud.objects[i] == small { rgb(1.0, 0.0, 0.0) } else { rgb(0.0, 0.0, 1.0) });
+    }
+    // the large sphere has four times the area, and each half of a sphere half of it
+    let on_large = surface.iter().filter(|i| cloud.objects[**i] == large).count() as f32 / 4000.0;
+    assert!((on_large - 0.8).abs() < 0.03, "{} of the samples on the large sphere", on_large);
+    let upper = surface.iter().filter(|i| cloud.positions[**i].y > 0.0).count() as f32 / 4000.0;
+    assert!((upper - 0.5).abs() < 0.03, "{} of the samples on the upper halves", upper);
+
+    for i in (0..cloud.len()).filter(|i| cloud.kinds[*i] != SampleKind::Surface) {
+        assert!((cloud.distances[i] - scene.distance(cloud.positions[i])).abs() < 1e-6);
+        if cloud.kinds[i] == SampleKind::NearSurface {
+            assert!(cloud.distances[i].abs() < 0.3);
+        }
+        assert!((0..3).all(|a| cloud.positions[i][a].abs() <= 1.0 + 0.3));
+    }
+    let again = scene.sample_points(&sampling);
+    assert_eq!(again.positions, cloud.positions);
+}
+
+#[test]
+fn point_clouds_write_ply_and_npy() {
+    let (scene, ..) = two_spheres();
+    let cloud = scene.sample_points(&PointSampling { surface: 10, near_surface: 5, volume: 5, ..PointSampling::default() });
+    assert_eq!(cloud.len(), 20);
+    let mut npy = Vec::new();
+    cloud.write_npy(&mut npy).unwrap();
+    assert_eq!(&npy[..8], b"\x93NUMPY\x01\x00");
+    let header_len = u16::from_le_bytes([npy[8], npy[9]]) as usize;
+    assert_eq!((10 + header_len) % 64, 0);
+    assert!(String::from_utf8_lossy(&npy[10..10 + header_len]).contains("'shape': (20, 12)"));
+    assert_eq!(npy.len(), 10 + header_len + 20 * 12 * 4);
+    let last = &npy[npy.len() - 4..];
+    assert_eq!(f32::from_le_bytes(last.try_into().unwrap()), 2.0);
+
+    let mut ply = Vec::new();
+    cloud.write_ply(&mut ply).unwrap();
+    let header_end = ply.windows(11).position(|w| w == b"end_header\n").unwrap() + 11;
+    assert_eq!(ply.len() - header_end, 20 * (24 + 3 + 4 + 4 + 1));
+}
+
+#[test]
+fn samples_that_miss_the_surface_are_counted() {
+    let (scene, ..) = two_spheres();
+    // bounds away from both spheres hold no surface to sample
+    let sampling = PointSampling { min: point3(2., 2., 2.), max: point3(3., 3., 3.), surface: 10, near_surface: 5, volume: 5, ..PointSampling::default() };
+    let cloud = scene.sample_points(&sampling);
+    assert_eq!((cloud.len(), cloud.shortfall), (5, 15));
+    assert!(cloud.kinds.iter().all(|kind| *kind == SampleKind::Volume));
+    assert_eq!(Scene::new().sample_points(&sampling).shortfall, 20);
+}
@@ -0,0 +1,50 @@
+//! Scene files: saving and loading again, and which versions of the format are read.
+use gpu_raymarching::*;
+use std::path::PathBuf;
+
+
+
+#[test]
+fn saved_scenes_load_unchanged() {
+    let mut scene = Scene::new();
+    scene.set_camera(Camera::new(point3(0., 1.3, 0.), point3(0., 1.3, 1.), 0.0, 1.0));
+    scene.add_light(Light::point(point3(3., 5., -2.)));
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    let red = scene.add_material(Material::new(rgb(1.0, 0.3, 0.2)));
+    scene.add(Primitive::Plane(vec3(0., 1., 0.), 0.), Transform::none(), white);
+    let room = scene.add_group("Room", Transform::translation(vec3(0.2, 0., 3.5)));
+    let table = scene.add_group("Table", Transform::new(vec3(0., 0.6, 0.), Quat::from_euler(vec3(0., 0., 0.5)), vec3(1.2, 1.2, 1.2)));
+    scene.group_mut(table).unwrap().parent = Some(room);
+    let top = scene.add_child(table, Primitive::Cuboid(vec3(0.6, 0.05, 0.4), 0.02), Transform::none(), red);
+    let ball = scene.add(Primitive::Sphere(0.3), Transform::transscale(vec3(0., 1., 3.), vec3(1., 2., 1.)), red);
+    scene.modifiers_mut(ball).unwrap().push(Modifier::Onion(0.05));
+    scene.add_bool_op(BooleanOp::new(BooleanOpType::SmoothUnion(0.2), vec![top.into(), ball.into()])).unwrap();
+
+    let path = std::env::temp_dir().join(format!("scene_file_round_trip_{}.ron", std::process::id()));
+    scene.save(&path).unwrap();
+    let loaded = Scene::load(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(loaded.to_ron().unwrap(), scene.to_ron().unwrap());
+    let p = vec3(0.1, 1.2, 3.2);
+    assert_eq!(loaded.distance(p), scene.distance(p));
+}
+
+#[test]
+fn the_bundled_scene_is_current() {
+    let src = std::fs::read_to_string(PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("scenes/basic.ron")).unwrap();
+    assert_eq!(Scene::from_ron(&src).unwrap().to_ron().unwrap(), src);
+}
+
+#[test]
+fn other_format_versions_are_rejected() {
+    let src = Scene::new().to_ron().unwrap();
+    let current = format!("version: {}", SCENE_FORMAT_VERSION);
+    assert!(src.contains(&current));
+    for version in [SCENE_FORMAT_VERSION - 1, SCENE_FORMAT_VERSION + 1] {
+        let other = src.replace(&current, &format!("version: {}", version));
+        match Scene::from_ron(&other) {
+            Err(SceneError::UnsupportedVersion(v)) => assert_eq!(v, version),
+            other => panic!("expected an unsupported version, got {:?}", other.map(|_| ())),
+        }
+    }
+}
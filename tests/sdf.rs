@@ -0,0 +1,103 @@
+//! Checks on the CPU distance functions against closed forms, so shapes can be tested
+//! without rendering them.
+use gpu_raymarching::*;
+
+
+
+const EPS: f32 = 1e-5;
+
+fn assert_near(actual: f32, expected: f32) {
+    assert!((actual - expected).abs() < EPS, "distance {} != {}", actual, expected);
+}
+
+fn scene_of(objects: Vec<(Primitive, Transform)>) -> (Scene, Vec<ObjectId>) {
+    let mut scene = Scene::new();
+    let white = scene.add_material(Material::new(rgb(1.0, 1.0, 1.0)));
+    let ids = objects.into_iter().map(|(primitive, transform)| scene.add(primitive, transform, white)).collect();
+    (scene, ids)
+}
+
+#[test]
+fn primitives_match_their_closed_forms() {
+    let p = vec3(0.3, 1.7, -0.4);
+    assert_near(Primitive::Sphere(0.5).distance(p), p.length() - 0.5);
+    assert_near(Primitive::Plane(vec3(0., 1., 0.), 0.25).distance(p), 1.7 + 0.25);
+    // outside a box along one face, and past an edge
+    assert_near(Primitive::Cuboid(vec3(1., 1., 1.), 0.0).distance(vec3(0.5, 3., 0.)), 2.0);
+    assert_near(Primitive::Cuboid(vec3(1., 1., 1.), 0.1).distance(vec3(2., 2., 0.)), 2f32.sqrt() - 0.1);
+    assert_near(Primitive::Cuboid(vec3(1., 2., 1.), 0.0).distance(vec3(0., 0.5, 0.)), -1.0);
+    assert_near(Primitive::Torus(1.0, 0.25).distance(vec3(0., 0.5, 1.)), 0.25);
+    assert_near(Primitive::Capsule(1.0, 0.5).distance(vec3(0., 2., 0.)), 0.5);
+    assert_near(Primitive::Capsule(1.0, 0.5).distance(vec3(1., 0.5, 0.)), 0.5);
+    assert_near(Primitive::CappedCylinder(1.0, 0.5).distance(vec3(0., 0., 2.)), 1.5);
+    assert_near(Primitive::Octahedron(1.0).distance(vec3(0., 0., 0.)), -1.0 / 3f32.sqrt());
+}
+
+#[test]
+fn exact_primitives_dont_change_faster_than_the_point_moves() {
+    let primitives = [
+        Primitive::Sphere(0.5),
+        Primitive::Cuboid(vec3(0.5, 0.3, 0.2), 0.05),
+        Primitive::Torus(0.5, 0.1),
+        Primitive::Capsule(0.6, 0.2),
+        Primitive::CappedCylinder(0.4, 0.3),
+        Primitive::Octahedron(0.6),
+    ];
+    let points: Vec<Point3> = (0..200).map(|i| {
+        let t = i as f32;
+        vec3((t * 0.37).sin(), (t * 0.73).cos(), (t * 1.31).sin()) * 1.2
+    }).collect();
+    for (i, primitive) in primitives.iter().enumerate() {
+        for pair in points.windows(2) {
+            let (a, b) = (pair[0], pair[1]);
+            let change = (primitive.distance(a) - primitive.distance(b)).abs();
+            assert!(change <= (a - b).length() + EPS, "primitive {} changes by {} over {}", i, change, (a - b).length());
+        }
+    }
+}
+
+#[test]
+fn transforms_move_and_scale_distances() {
+    let (scene, _) = scene_of(vec![(Primitive::Sphere(0.5), Transform::transscale(vec3(1., 2., 3.), vec3(2., 2., 2.)))]);
+    assert_near(scene.distance(vec3(1., 2., 3.)), -1.0);
+    assert_near(scene.distance(vec3(1., 5., 3.)), 2.0);
+    let (rotated, _) = scene_of(vec![(Primitive::Cuboid(vec3(1., 0.1, 0.1), 0.0), Transform::rotation(Quat::from_euler(vec3(std::f32::consts::FRAC_PI_2, 0., 0.))))]);
+    // a quarter turn about Z stands the long side up along Y
+    assert_near(rotated.distance(vec3(0., 1.5, 0.)), 0.5);
+    assert_near(rotated.distance(vec3(1.5, 0., 0.)), 1.4);
+}
+
+#[test]
+fn boolean_ops_combine_distances() {
+    let objects = || vec![
+        (Primitive::Sphere(1.0), Transform::translation(vec3(-0.5, 0., 0.))),
+        (Primitive::Sphere(1.0), Transform::translation(vec3(0.5, 0., 0.))),
+    ];
+    let p = vec3(0.2, 0.6, 0.1);
+    let a = (p - vec3(-0.5, 0., 0.)).length() - 1.0;
+    let b = (p - vec3(0.5, 0., 0.)).length() - 1.0;
+    let combined = |operator: BooleanOpType| {
+        let (mut scene, ids) = scene_of(objects());
+        scene.add_bool_op(BooleanOp::new(operator, ids.into_iter().map(|id| id.into()).collect())).unwrap();
+        scene.distance(p)
+    };
+    assert_near(combined(BooleanOpType::Union), a.min(b));
+    assert_near(combined(BooleanOpType::Intersect), a.max(b));
+    assert_near(combined(BooleanOpType::Subtract), a.max(-b));
+    assert!(combined(BooleanOpType::SmoothUnion(0.3)) < a.min(b));
+    assert!(combined(BooleanOpType::SmoothIntersect(0.3)) > a.max(b));
+}
+
+#[test]
+fn modifiers_round_and_hollow_distances() {
+    let p = vec3(0., 2., 0.);
+    let modified = |modifier: Modifier| {
+        let (mut scene, ids) = scene_of(vec![(Primitive::Cuboid(vec3(1., 1., 1.), 0.0), Transform::none())]);
+        scene.modifiers_mut(ids[0]).unwrap().push(modifier);
+        scene.distance(p)
+    };
+    assert_near(modified(Modifier::Round(0.25)), 0.75);
+    assert_near(modified(Modifier::Onion(0.1)), 0.9);
+    assert_near(modified(Modifier::Elongate(vec3(0., 0.5, 0.))), 0.5);
+    assert_near(modified(Modifier::Repeat(vec3(0., 3., 0.))), 0.0);
+}
@@ -0,0 +1,102 @@
+//! Checks on the shader sources and the tools for working on them that don't need a GPU.
+use gpu_raymarching::*;
+use std::path::PathBuf;
+
+
+
+fn shader_source(name: &str) -> String {
+    let path = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("src").join("shaders").join(name);
+    std::fs::read_to_string(path).unwrap()
+}
+
+/// Rewrites the OpenGL shader into the Vulkan flavour of GLSL naga reads: plain uniforms go
+/// in a block, every uniform gets a binding, and the combined samplers become textures
+/// sampled through one separate sampler. Lines stay where they were, so errors point at the
+/// source.
+fn vulkan_glsl(source: &str) -> String {
+    let plain: Vec<&str> = source.lines()
+        .filter_map(|line| line.strip_prefix("uniform "))
+        .filter(|declaration| !declaration.contains('{') && !declaration.starts_with("sampler"))
+        .collect();
+    let mut glsl = String::new();
+    let mut binding = 2;
+    for line in source.lines() {
+        let line = line.replace("#version 410", "#version 450")
+            .replace("samplerBuffer", "texture1D")
+            .replace("sampler3D", "texture3D")
+            .replace("(scene_voxels,", "(sampler3D(scene_voxels, texel_sampler),");
+        match line.strip_prefix("uniform ") {
+            Some(declaration) if declaration.contains('{') || declaration.starts_with("texture") => {
+                glsl += &format!("layout(binding = {}) uniform {}", binding, declaration);
+                binding += 1;
+            },
+            // the block takes the place of the first plain uniform
+            Some(declaration) if declaration == plain[0] => {
+                glsl += &format!("layout(binding = 0) uniform globals {{ {} }}; layout(binding = 1) uniform sampler texel_sampler;", plain.join(" "));
+            },
+            Some(_) => (),
+            None => glsl += &line,
+        }
+        glsl.push('\n');
+    }
+
+    // a buffer texture's texelFetch(buffer, i) becomes texelFetch(sampler1D(buffer, texel_sampler), i, 0)
+    let mut rewritten = String::new();
+    let mut rest = glsl.as_str();
+    while let Some(start) = rest.find("texelFetch(").map(|i| i + "texelFetch(".len()) {
+        let comma = start + rest[start..].find(',').unwrap();
+        let mut depth = 1;
+        let end = start + rest[start..].find(|c| {
+            depth += match c { '(' => 1, ')' => -1, _ => 0 };
+            depth == 0
+        }).unwrap();
+        rewritten += &format!("{}sampler1D({}, texel_sampler){}, 0", &rest[..start], &rest[start..comma], &rest[comma..end]);
+        rest = &rest[end..];
+    }
+    rewritten + rest
+}
+
+#[test]
+fn fragment_shader_is_valid_glsl() {
+    let glsl = vulkan_glsl(&shader_source("fragment.glsl"));
+    let options = naga::front::glsl::Options::from(naga::ShaderStage::Fragment);
+    let module = naga::front::glsl::Frontend::default().parse(&options, &glsl)
+        .unwrap_or_else(|e| panic!("fragment.glsl doesn't parse:\n{}", e.emit_to_string(&glsl)));
+    naga::valid::Validator::new(naga::valid::ValidationFlags::all(), naga::valid::Capabilities::all())
+        .validate(&module)
+        .unwrap_or_else(|e| panic!("fragment.glsl isn't valid:\n{}", e.emit_to_string(&glsl)));
+}
+
+#[test]
+fn shader_logs_point_at_source_lines() {
+    let source = "#version 410\nvoid main() {\n    float k = 1.0 oops\n    gl_FragColor = vec4(k);\n}\n";
+    let mesa = annotate_shader_log("0:4(5): error: syntax error, unexpected IDENTIFIER\n", source);
+    assert_eq!(mesa, "0:4(5): error: syntax error, unexpected IDENTIFIER\n      3 |     float k = 1.0 oops\n>     4 |     gl_FragColor = vec4(k);\n");
+    let nvidia = annotate_shader_log("0(3) : error C0000: syntax error", source);
+    assert!(nvidia.contains(">     3 |     float k = 1.0 oops"));
+    assert!(nvidia.contains("      2 | void main() {"));
+    assert!(annotate_shader_log("ERROR: 0:1: '' : version '410' is not supported", source).ends_with(">     1 | #version 410\n"));
+    // messages without a line, or past the end of the source, are kept as they are
+    assert_eq!(annotate_shader_log("error: linking failed\n0:99(1): error: oops", source), "error: linking failed\n0:99(1): error: oops\n");
+}
+
+#[test]
+fn shader_watcher_notices_edits() {
+    let dir = std::env::temp_dir().join(format!("watched_shaders_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("vertex.glsl"), "vertex").unwrap();
+    std::fs::write(dir.join("fragment.glsl"), "fragment").unwrap();
+    let mut watcher = ShaderWatcher::new(&dir);
+    assert!(watcher.changed());
+    assert_eq!(watcher.read().unwrap(), ("vertex".into(), "fragment".into()));
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(!watcher.changed());
+    std::fs::write(dir.join("fragment.glsl"), "edited").unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(watcher.changed());
+    assert_eq!(watcher.read().unwrap().1, "edited");
+    std::fs::remove_file(dir.join("vertex.glsl")).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(300));
+    assert!(watcher.changed());
+    assert!(matches!(watcher.read(), Err(ShaderError::Io(..))));
+}